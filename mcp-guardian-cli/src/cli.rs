@@ -1,6 +1,7 @@
 pub mod guard_profiles;
 pub mod mcp_servers;
 pub mod server_collections;
+pub mod server_logs;
 
 use clap::Parser;
 
@@ -16,4 +17,5 @@ pub enum SubCommand {
     GuardProfiles(guard_profiles::Args),
     McpServers(mcp_servers::Args),
     ServerCollections(server_collections::Args),
+    ServerLogs(server_logs::Args),
 }
@@ -1,7 +1,9 @@
 pub mod delete;
 pub mod get;
 pub mod list;
+pub mod replay;
 pub mod set;
+pub mod test;
 
 use clap::Parser;
 
@@ -18,4 +20,6 @@ pub enum SubCommand {
     Set(set::Args),
     List(list::Args),
     Delete(delete::Args),
+    Replay(replay::Args),
+    Test(test::Args),
 }
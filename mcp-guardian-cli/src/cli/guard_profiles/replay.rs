@@ -0,0 +1,24 @@
+use std::path::PathBuf;
+
+use clap::{Parser, ValueHint};
+
+/// Replay a recorded session against a guard-profile, and compare its decisions to the recorded
+/// ones.
+#[derive(Debug, Clone, Parser)]
+pub struct Args {
+    /// The namespace of the guard-profile to replay.
+    #[clap(short, long)]
+    pub namespace: String,
+
+    /// The name of the guard-profile to replay.
+    pub profile_name: String,
+
+    /// The path to the session transcript to replay, as recorded by the proxy.
+    #[clap(value_hint = ValueHint::FilePath, value_parser)]
+    pub transcript: PathBuf,
+
+    /// The path to a JSON file of scripted answers to requests for approval. Requests not
+    /// answered there are answered as they were in the transcript.
+    #[clap(short, long, value_hint = ValueHint::FilePath, value_parser)]
+    pub answers: Option<PathBuf>,
+}
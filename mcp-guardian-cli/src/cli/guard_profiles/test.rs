@@ -0,0 +1,19 @@
+use std::path::PathBuf;
+
+use clap::{Parser, ValueHint};
+
+/// Test a guard-profile against a directory of fixtures.
+#[derive(Debug, Clone, Parser)]
+pub struct Args {
+    /// The namespace of the guard-profile to test.
+    #[clap(short, long)]
+    pub namespace: String,
+
+    /// The name of the guard-profile to test.
+    pub profile_name: String,
+
+    /// The path to the fixture directory, holding a JSON file of cases per fixture. Defaults to
+    /// the fixtures shipped with core guard-profiles.
+    #[clap(value_hint = ValueHint::DirPath, value_parser)]
+    pub fixture_dir: Option<PathBuf>,
+}
@@ -0,0 +1,17 @@
+pub mod get;
+pub mod list;
+
+use clap::Parser;
+
+/// Commands related to logs of mcp-server processes.
+#[derive(Debug, Clone, Parser)]
+pub struct Args {
+    #[clap(subcommand)]
+    pub cmd: SubCommand,
+}
+
+#[derive(Debug, Clone, Parser)]
+pub enum SubCommand {
+    Get(get::Args),
+    List(list::Args),
+}
@@ -0,0 +1,12 @@
+use clap::Parser;
+
+/// Get the log of an mcp-server process.
+#[derive(Debug, Clone, Parser)]
+pub struct Args {
+    /// The name the mcp-server was proxied under.
+    #[clap(short, long)]
+    pub name: String,
+
+    /// The proxy session the log belongs to.
+    pub session_id: String,
+}
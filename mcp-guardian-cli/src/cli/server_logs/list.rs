@@ -0,0 +1,5 @@
+use clap::Parser;
+
+/// List all logs of mcp-server processes.
+#[derive(Debug, Clone, Parser)]
+pub struct Args {}
@@ -1,11 +1,18 @@
 use std::fs;
 
-use anyhow::Result;
-use mcp_guardian_core::guard_profile::{GuardProfile, NamedGuardProfile};
+use anyhow::{bail, Result};
+use mcp_guardian_core::{
+    guard_profile::{
+        fixture::{load_core_fixtures, load_fixtures, CaseOutcome},
+        replay::{ReplayedMessage, ScriptedApproval},
+        GuardProfile, NamedGuardProfile,
+    },
+    message_interceptor::record::read_transcript,
+};
 
 use crate::cli;
 
-pub fn cmd(args: cli::guard_profiles::Args) -> anyhow::Result<()> {
+pub async fn cmd(args: cli::guard_profiles::Args) -> anyhow::Result<()> {
     let cli::guard_profiles::Args { cmd } = args;
 
     match cmd {
@@ -13,6 +20,8 @@ pub fn cmd(args: cli::guard_profiles::Args) -> anyhow::Result<()> {
         cli::guard_profiles::SubCommand::Set(args) => set(args)?,
         cli::guard_profiles::SubCommand::List(args) => list(args)?,
         cli::guard_profiles::SubCommand::Delete(args) => delete(args)?,
+        cli::guard_profiles::SubCommand::Replay(args) => replay(args).await?,
+        cli::guard_profiles::SubCommand::Test(args) => test(args).await?,
     }
 
     Ok(())
@@ -81,3 +90,129 @@ fn delete(args: cli::guard_profiles::delete::Args) -> Result<()> {
 
     Ok(())
 }
+
+async fn replay(args: cli::guard_profiles::replay::Args) -> Result<()> {
+    let cli::guard_profiles::replay::Args {
+        namespace,
+        profile_name,
+        transcript,
+        answers,
+    } = args;
+
+    let guard_profile =
+        mcp_guardian_core::guard_profile::load_guard_profile(&namespace, &profile_name)?
+            .ok_or_else(|| anyhow::anyhow!("Guard profile not found."))?;
+
+    let recorded_messages = read_transcript(&transcript)?;
+
+    let scripted_approvals = match answers {
+        Some(path) => {
+            let scripted_approvals = fs::read_to_string(&path)?;
+            serde_json::from_str::<Vec<ScriptedApproval>>(&scripted_approvals)?
+        }
+        None => Vec::new(),
+    };
+
+    let replayed_messages = mcp_guardian_core::guard_profile::replay::replay_transcript(
+        &guard_profile,
+        recorded_messages,
+        &scripted_approvals,
+    )
+    .await?;
+
+    let mut changed = 0;
+    for (index, replayed_message) in replayed_messages.iter().enumerate() {
+        let ReplayedMessage {
+            recorded_message,
+            action,
+        } = replayed_message;
+
+        let method = recorded_message.message["method"]
+            .as_str()
+            .map(|method| format!(" {method}"))
+            .unwrap_or_default();
+        let id = recorded_message
+            .message
+            .get("id")
+            .map(|id| format!(" (id {id})"))
+            .unwrap_or_default();
+
+        println!(
+            "{} [{}] {} {}{method}{id}: {}",
+            index + 1,
+            recorded_message.mcp_server_name,
+            recorded_message.direction,
+            recorded_message.message_type,
+            serde_json::to_string(action)?,
+        );
+
+        if replayed_message.changed() {
+            changed += 1;
+            println!(
+                "  - recorded: {}",
+                serde_json::to_string(&recorded_message.action)?
+            );
+            println!("  + replayed: {}", serde_json::to_string(action)?);
+        }
+    }
+
+    println!(
+        "{} of {} decisions changed.",
+        changed,
+        replayed_messages.len()
+    );
+
+    if changed > 0 {
+        bail!("Guard profile '{namespace}.{profile_name}' changed {changed} decisions.");
+    }
+
+    Ok(())
+}
+
+async fn test(args: cli::guard_profiles::test::Args) -> Result<()> {
+    let cli::guard_profiles::test::Args {
+        namespace,
+        profile_name,
+        fixture_dir,
+    } = args;
+
+    let guard_profile =
+        mcp_guardian_core::guard_profile::load_guard_profile(&namespace, &profile_name)?
+            .ok_or_else(|| anyhow::anyhow!("Guard profile not found."))?;
+
+    let fixtures = match fixture_dir {
+        Some(fixture_dir) => load_fixtures(&fixture_dir)?,
+        None => load_core_fixtures(&namespace, &profile_name)?,
+    };
+    if fixtures.is_empty() {
+        bail!("No fixtures found for guard profile '{namespace}.{profile_name}'.");
+    }
+
+    let outcomes =
+        mcp_guardian_core::guard_profile::fixture::run_fixtures(&guard_profile, &fixtures).await?;
+
+    let mut failed = 0;
+    for CaseOutcome {
+        fixture_name,
+        case_name,
+        failure,
+        ..
+    } in &outcomes
+    {
+        match failure {
+            None => println!("pass {fixture_name}: {case_name}"),
+            Some(failure) => {
+                failed += 1;
+                println!("FAIL {fixture_name}: {case_name}: {failure}");
+            }
+        }
+    }
+
+    println!("{} passed, {failed} failed.", outcomes.len() - failed);
+
+    if failed > 0 {
+        bail!("Guard profile '{namespace}.{profile_name}' failed {failed} cases.");
+    }
+
+    Ok(())
+}
@@ -2,3 +2,4 @@ pub mod cli;
 pub mod guard_profiles;
 pub mod mcp_servers;
 pub mod server_collections;
+pub mod server_logs;
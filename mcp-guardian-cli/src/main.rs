@@ -1,6 +1,6 @@
 use anyhow::Result;
 use clap::Parser;
-use mcp_guardian_cli::{cli, guard_profiles, mcp_servers, server_collections};
+use mcp_guardian_cli::{cli, guard_profiles, mcp_servers, server_collections, server_logs};
 
 #[tokio::main]
 async fn main() -> Result<()> {
@@ -9,9 +9,10 @@ async fn main() -> Result<()> {
     mcp_guardian_core::init("mcp-guardian-cli")?;
 
     match cmd {
-        cli::SubCommand::GuardProfiles(args) => guard_profiles::cmd(args)?,
+        cli::SubCommand::GuardProfiles(args) => guard_profiles::cmd(args).await?,
         cli::SubCommand::McpServers(args) => mcp_servers::cmd(args)?,
         cli::SubCommand::ServerCollections(args) => server_collections::cmd(args)?,
+        cli::SubCommand::ServerLogs(args) => server_logs::cmd(args)?,
     }
 
     Ok(())
@@ -0,0 +1,42 @@
+use anyhow::Result;
+use mcp_guardian_core::server_log::ServerLog;
+
+use crate::cli;
+
+pub fn cmd(args: cli::server_logs::Args) -> anyhow::Result<()> {
+    let cli::server_logs::Args { cmd } = args;
+
+    match cmd {
+        cli::server_logs::SubCommand::Get(args) => get(args)?,
+        cli::server_logs::SubCommand::List(args) => list(args)?,
+    }
+
+    Ok(())
+}
+
+fn get(args: cli::server_logs::get::Args) -> Result<()> {
+    let cli::server_logs::get::Args { name, session_id } = args;
+
+    let server_log = mcp_guardian_core::server_log::read_server_log(&name, &session_id)?;
+
+    print!("{server_log}");
+
+    Ok(())
+}
+
+fn list(args: cli::server_logs::list::Args) -> Result<()> {
+    let _ = args;
+
+    let server_logs = mcp_guardian_core::server_log::list_server_logs()?;
+
+    for ServerLog {
+        mcp_server_name,
+        session_id,
+        size,
+    } in server_logs
+    {
+        println!("{mcp_server_name} {session_id} ({size} bytes)");
+    }
+
+    Ok(())
+}
@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { ApprovalTimeoutAction } from "./ApprovalTimeoutAction";
+
+export type ApprovalEvent = { "type": "requested" } | { "type": "approved" } | { "type": "denied" } | { "type": "timed_out", action: ApprovalTimeoutAction, } | { "type": "withdrawn" };
@@ -0,0 +1,8 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { ApprovalEvent } from "./ApprovalEvent";
+
+export type ApprovalHistoryEntry = { 
+/**
+ * RFC 3339 timestamp
+ */
+timestamp: string, event: ApprovalEvent, };
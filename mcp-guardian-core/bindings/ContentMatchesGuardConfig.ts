@@ -0,0 +1,14 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * Regex searched for in the textual content of a message.
+ */
+export type ContentMatchesGuardConfig = { 
+/**
+ * Regex, matching anywhere in a block of content unless anchored
+ */
+regex: string, case_sensitive: boolean, 
+/**
+ * Size in bytes above which blocks of content are skipped
+ */
+max_block_size: number, };
@@ -0,0 +1,21 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { DenialResponseKind } from "./DenialResponseKind";
+
+/**
+ * Answer to a denied message: for `tools/call` requests and responses, a `tools/call` result
+ * flagged as an error or a json-rpc error as `kind` says, a json-rpc error otherwise.
+ */
+export type DenialResponseGuardConfig = { 
+/**
+ * Text of the tool result, or message of the json-rpc error
+ */
+message?: string | null, 
+/**
+ * Code of the json-rpc error
+ */
+code?: number | null, 
+/**
+ * How denied `tools/call` requests and responses are answered, a tool result flagged as an
+ * error if unset
+ */
+kind?: DenialResponseKind | null, };
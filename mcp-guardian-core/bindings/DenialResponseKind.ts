@@ -0,0 +1,6 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * How a denied `tools/call` request or response is answered.
+ */
+export type DenialResponseKind = "tool_error" | "json_rpc_error";
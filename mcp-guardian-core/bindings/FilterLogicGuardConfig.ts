@@ -1,3 +1,6 @@
 // This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { ContentMatchesGuardConfig } from "./ContentMatchesGuardConfig";
+import type { JsonPointerGuardConfig } from "./JsonPointerGuardConfig";
+import type { StringPatternGuardConfig } from "./StringPatternGuardConfig";
 
-export type FilterLogicGuardConfig = { "direction": string } | { "message_type": string } | { "request_method": string } | { "and": Array<FilterLogicGuardConfig> } | { "or": Array<FilterLogicGuardConfig> } | { "not": FilterLogicGuardConfig };
+export type FilterLogicGuardConfig = { "direction": string } | { "message_type": string } | { "request_method": string } | { "tool_name": StringPatternGuardConfig } | { "json_pointer": JsonPointerGuardConfig } | { "content_matches": ContentMatchesGuardConfig } | { "and": Array<FilterLogicGuardConfig> } | { "or": Array<FilterLogicGuardConfig> } | { "not": FilterLogicGuardConfig };
@@ -0,0 +1,6 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * Environment variables an MCP server process inherits from the proxy.
+ */
+export type InheritEnv = "all" | "none" | { "allowlist": Array<string> };
@@ -0,0 +1,11 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { ValueConditionGuardConfig } from "./ValueConditionGuardConfig";
+
+/**
+ * Condition on the value at a JSON pointer into a message.
+ */
+export type JsonPointerGuardConfig = { 
+/**
+ * JSON pointer into the message, e.g. `/params/arguments/path` or `/result/isError`
+ */
+path: string, condition: ValueConditionGuardConfig, };
@@ -1,3 +1,22 @@
 // This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { ApprovalTimeoutAction } from "./ApprovalTimeoutAction";
+import type { DenialResponseGuardConfig } from "./DenialResponseGuardConfig";
 
-export type ManualApprovalGuardConfig = Record<string, never>;
+export type ManualApprovalGuardConfig = { 
+/**
+ * How long to wait for approval before taking `timeout_action`. Waits indefinitely if unset.
+ */
+timeout_ms?: number | null, 
+/**
+ * What becomes of the message once its approval times out
+ */
+timeout_action?: ApprovalTimeoutAction, 
+/**
+ * How often to check whether the message was approved. At least 50 ms.
+ */
+poll_interval_ms?: number, 
+/**
+ * How denied messages are answered. Uses the defaults of [`DenialResponseGuardConfig`] if
+ * unset.
+ */
+denial_response?: DenialResponseGuardConfig | null, };
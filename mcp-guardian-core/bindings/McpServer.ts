@@ -1,3 +1,63 @@
 // This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { InheritEnv } from "./InheritEnv";
+import type { NetworkIsolation } from "./NetworkIsolation";
+import type { RemoteMcpServer } from "./RemoteMcpServer";
+import type { ResourceLimits } from "./ResourceLimits";
+import type { RestartPolicy } from "./RestartPolicy";
+import type { SandboxConfig } from "./SandboxConfig";
+import type { StderrLogConfig } from "./StderrLogConfig";
 
-export type McpServer = { cmd: string, args: Array<string>, };
+export type McpServer = { cmd: string, args: Array<string>, 
+/**
+ * Absolute path of the directory the server process runs in. The proxy's own working
+ * directory if unset.
+ */
+cwd?: string | null, 
+/**
+ * Shell the server is launched through, e.g. `/bin/sh`. `cmd` is then a command line in the
+ * shell's syntax, which `args` are appended to, quoted.
+ */
+shell?: string | null, 
+/**
+ * Umask of the server process in octal, e.g. `"077"`. Unix only.
+ */
+umask?: string | null, 
+/**
+ * Size in bytes of the buffer messages to the server process are written through
+ */
+stdin_buffer_size?: number | null, 
+/**
+ * Size in bytes of the buffer messages from the server process are read through
+ */
+stdout_buffer_size?: number | null, 
+/**
+ * Sandbox the server process is launched in. Linux only.
+ */
+sandbox?: SandboxConfig | null, 
+/**
+ * Network the server process may access, from a network namespace of its own. Linux only.
+ * Unrestricted if unset.
+ */
+network?: NetworkIsolation | null, 
+/**
+ * Resource limits of the server process. Unix only.
+ */
+limits?: ResourceLimits | null, 
+/**
+ * Which of the proxy's environment variables the server process inherits. Inherits all of
+ * them if unset.
+ */
+inherit_env?: InheritEnv | null, 
+/**
+ * Restarts the server process if it crashes. The proxy exits along with the server if unset.
+ */
+restart?: RestartPolicy | null, 
+/**
+ * How the server process' stderr is logged. Uses the defaults of [`StderrLogConfig`] if
+ * unset.
+ */
+stderr_log?: StderrLogConfig | null, 
+/**
+ * Remote MCP server to connect to instead of spawning `cmd`.
+ */
+remote?: RemoteMcpServer | null, };
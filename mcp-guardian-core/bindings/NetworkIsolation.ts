@@ -0,0 +1,6 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * Network an MCP server process may access, from a network namespace of its own.
+ */
+export type NetworkIsolation = "none" | "loopback";
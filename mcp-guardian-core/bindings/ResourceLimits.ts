@@ -0,0 +1,26 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * Resource limits an MCP server process is launched with, as rlimits. Unix only.
+ *
+ * Limits are inherited by the processes the server starts, but each of them is limited on its own.
+ */
+export type ResourceLimits = { 
+/**
+ * Maximum size of the server's virtual memory in MiB. Runtimes that reserve large amounts of
+ * virtual memory up front, such as Node.js, need a generous limit.
+ */
+max_address_space_mb?: number | null, 
+/**
+ * Maximum CPU time of the server in seconds
+ */
+max_cpu_seconds?: number | null, 
+/**
+ * Maximum number of files the server may have open at once
+ */
+max_open_files?: number | null, 
+/**
+ * Maximum number of processes the server's user may run, including those it already runs
+ * outside of the server
+ */
+max_processes?: number | null, };
@@ -0,0 +1,15 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export type RestartPolicy = { 
+/**
+ * Maximum number of restarts before giving up
+ */
+max_retries: number, 
+/**
+ * Delay before the first restart, doubled after every further restart
+ */
+initial_backoff_ms: number, 
+/**
+ * Upper limit on the delay between restarts
+ */
+max_backoff_ms: number, };
@@ -0,0 +1,23 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * Restrictions an MCP server process is launched under, on Linux. The server may only access the
+ * listed paths (with Landlock), may not make syscalls that an MCP server has no business making
+ * (with seccomp), and may not gain privileges (with `no_new_privs`).
+ *
+ * The listed paths must cover everything the server needs to run, including its program, shared
+ * libraries and interpreter, e.g. `/usr`, `/lib` and `/etc`.
+ */
+export type SandboxConfig = { 
+/**
+ * Absolute paths the server may read and execute, along with everything beneath them
+ */
+read_paths: Array<string>, 
+/**
+ * Absolute paths the server may read, execute and write, along with everything beneath them
+ */
+write_paths: Array<string>, 
+/**
+ * Whether syscalls such as `ptrace` and `mount` are denied. Enabled unless set to false.
+ */
+seccomp: boolean, };
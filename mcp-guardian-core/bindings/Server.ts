@@ -1,3 +1,8 @@
 // This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
 
-export type Server = { mcp_server: string, guard_profile: string, };
+export type Server = { mcp_server: string, guard_profile: string, 
+/**
+ * Prepended to the names of the server's tools, prompts and resources when the collection is
+ * aggregated. Defaults to the name of the MCP server followed by `__`.
+ */
+prefix?: string | null, };
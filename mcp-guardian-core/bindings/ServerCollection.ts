@@ -1,4 +1,9 @@
 // This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
 import type { Server } from "./Server";
 
-export type ServerCollection = { servers: Array<Server>, };
+export type ServerCollection = { servers: Array<Server>, 
+/**
+ * Runs every server behind a single proxy, which the host sees as one MCP server, rather than
+ * a proxy per server.
+ */
+aggregate: boolean, };
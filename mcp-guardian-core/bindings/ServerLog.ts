@@ -0,0 +1,10 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * Log of an MCP server process' stderr during a single proxy session.
+ */
+export type ServerLog = { mcp_server_name: string, session_id: string, 
+/**
+ * Combined size in bytes of the log and its rotations
+ */
+size: number, };
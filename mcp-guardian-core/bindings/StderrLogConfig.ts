@@ -0,0 +1,19 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * Stderr of a server process is captured line by line into a log per proxy session, under
+ * `AppSubDir::ServerLogs`.
+ */
+export type StderrLogConfig = { 
+/**
+ * Also write captured lines to the proxy's own stderr
+ */
+mirror: boolean, 
+/**
+ * Size in bytes at which the log is rotated
+ */
+max_file_size: number, 
+/**
+ * Number of rotated logs kept alongside the current one
+ */
+max_files: number, };
@@ -0,0 +1,6 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * Pattern a string is matched against.
+ */
+export type StringPatternGuardConfig = { "exact": string } | { "glob": string } | { "regex": string };
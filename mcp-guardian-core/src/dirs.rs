@@ -17,12 +17,14 @@ fn app_dir() -> Result<PathBuf> {
 #[derive(VariantArray)]
 pub enum AppSubDir {
     Logs,
+    ServerLogs,
     GuardProfiles,
     McpServers,
     MessageApprovals,
     MessageApprovalsPending,
     MessageApprovalsApproved,
     MessageApprovalsDenied,
+    MessageApprovalsHistory,
     ServerCollections,
 }
 
@@ -36,6 +38,7 @@ impl AppSubDir {
     fn _path(&self, base_dir: PathBuf) -> PathBuf {
         match self {
             Self::Logs => base_dir.join("logs"),
+            Self::ServerLogs => Self::Logs._path(base_dir).join("mcp-servers"),
             Self::GuardProfiles => base_dir.join("guard-profiles"),
             Self::McpServers => base_dir.join("mcp-servers"),
             Self::MessageApprovals => base_dir.join("message-approvals"),
@@ -44,6 +47,7 @@ impl AppSubDir {
                 Self::MessageApprovals._path(base_dir).join("approved")
             }
             Self::MessageApprovalsDenied => Self::MessageApprovals._path(base_dir).join("denied"),
+            Self::MessageApprovalsHistory => Self::MessageApprovals._path(base_dir).join("history"),
             Self::ServerCollections => base_dir.join("server-collections"),
         }
     }
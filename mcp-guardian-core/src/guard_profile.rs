@@ -1,9 +1,11 @@
 pub mod chain;
 pub mod filter;
+pub mod fixture;
 pub mod manual_approval;
 pub mod message_log;
 pub mod profiles;
 pub mod py_func;
+pub mod replay;
 
 use std::{fs, sync::Arc};
 
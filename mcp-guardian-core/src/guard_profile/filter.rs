@@ -1,14 +1,19 @@
 use std::sync::Arc;
 
-use anyhow::{bail, Result};
+use anyhow::{bail, Context as _, Result};
+use globset::Glob;
+use regex::{Regex, RegexBuilder};
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use ts_rs::TS;
 
 use crate::{
     guard_profile::MessageInterceptorGuardConfig,
     message::{MessageDirection, MessageType},
     message_interceptor::{
-        filter::{Filter, FilterAction, FilterInterceptor, FilterLogic},
+        filter::{
+            Filter, FilterAction, FilterInterceptor, FilterLogic, StringPattern, ValueCondition,
+        },
         MessageInterceptor,
     },
 };
@@ -47,6 +52,9 @@ pub enum FilterLogicGuardConfig {
     Direction(String),
     MessageType(String),
     RequestMethod(String),
+    ToolName(StringPatternGuardConfig),
+    JsonPointer(JsonPointerGuardConfig),
+    ContentMatches(ContentMatchesGuardConfig),
     And(Vec<Self>),
     Or(Vec<Self>),
     Not(Box<Self>),
@@ -77,6 +85,25 @@ impl TryFrom<FilterLogicGuardConfig> for FilterLogic {
             FilterLogicGuardConfig::RequestMethod(request_method) => {
                 FilterLogic::RequestMethod(request_method)
             }
+            FilterLogicGuardConfig::ToolName(pattern) => FilterLogic::ToolName(pattern.try_into()?),
+            FilterLogicGuardConfig::JsonPointer(JsonPointerGuardConfig { path, condition }) => {
+                if !path.is_empty() && !path.starts_with('/') {
+                    bail!("Invalid JSON pointer: {path}");
+                }
+
+                FilterLogic::JsonPointer(path, condition.try_into()?)
+            }
+            FilterLogicGuardConfig::ContentMatches(ContentMatchesGuardConfig {
+                regex,
+                case_sensitive,
+                max_block_size,
+            }) => FilterLogic::ContentMatches {
+                regex: RegexBuilder::new(&regex)
+                    .case_insensitive(!case_sensitive)
+                    .build()
+                    .with_context(|| format!("Invalid regex: {regex}"))?,
+                max_block_size: max_block_size as usize,
+            },
             FilterLogicGuardConfig::And(logics) => {
                 let logics = logics
                     .into_iter()
@@ -104,6 +131,104 @@ impl TryFrom<FilterLogicGuardConfig> for FilterLogic {
     }
 }
 
+/// Pattern a string is matched against.
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[serde(rename_all = "snake_case")]
+#[ts(export)]
+pub enum StringPatternGuardConfig {
+    Exact(String),
+    /// Glob pattern, e.g. `delete_*`
+    Glob(String),
+    /// Regex, matching anywhere in the string unless anchored
+    Regex(String),
+}
+
+impl TryFrom<StringPatternGuardConfig> for StringPattern {
+    type Error = anyhow::Error;
+
+    fn try_from(value: StringPatternGuardConfig) -> Result<StringPattern> {
+        let pattern = match value {
+            StringPatternGuardConfig::Exact(exact) => StringPattern::Exact(exact),
+            StringPatternGuardConfig::Glob(glob) => StringPattern::Glob(
+                Glob::new(&glob)
+                    .with_context(|| format!("Invalid glob: {glob}"))?
+                    .compile_matcher(),
+            ),
+            StringPatternGuardConfig::Regex(regex) => StringPattern::Regex(
+                Regex::new(&regex).with_context(|| format!("Invalid regex: {regex}"))?,
+            ),
+        };
+
+        Ok(pattern)
+    }
+}
+
+/// Condition on the value at a JSON pointer into a message.
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct JsonPointerGuardConfig {
+    /// JSON pointer into the message, e.g. `/params/arguments/path` or `/result/isError`
+    pub path: String,
+    pub condition: ValueConditionGuardConfig,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[serde(rename_all = "snake_case")]
+#[ts(export)]
+pub enum ValueConditionGuardConfig {
+    Equals(#[ts(type = "unknown")] Value),
+    /// The value is a string matching the regex, anywhere unless anchored
+    Regex(String),
+    Gt(f64),
+    Gte(f64),
+    Lt(f64),
+    Lte(f64),
+    Exists,
+}
+
+impl TryFrom<ValueConditionGuardConfig> for ValueCondition {
+    type Error = anyhow::Error;
+
+    fn try_from(value: ValueConditionGuardConfig) -> Result<ValueCondition> {
+        let condition = match value {
+            ValueConditionGuardConfig::Equals(value) => ValueCondition::Equals(value),
+            ValueConditionGuardConfig::Regex(regex) => ValueCondition::Regex(
+                Regex::new(&regex).with_context(|| format!("Invalid regex: {regex}"))?,
+            ),
+            ValueConditionGuardConfig::Gt(n) => ValueCondition::GreaterThan(n),
+            ValueConditionGuardConfig::Gte(n) => ValueCondition::GreaterThanOrEqual(n),
+            ValueConditionGuardConfig::Lt(n) => ValueCondition::LessThan(n),
+            ValueConditionGuardConfig::Lte(n) => ValueCondition::LessThanOrEqual(n),
+            ValueConditionGuardConfig::Exists => ValueCondition::Exists,
+        };
+
+        Ok(condition)
+    }
+}
+
+/// Regex searched for in the textual content of a message.
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct ContentMatchesGuardConfig {
+    /// Regex, matching anywhere in a block of content unless anchored
+    pub regex: String,
+    #[serde(default = "ContentMatchesGuardConfig::default_case_sensitive")]
+    pub case_sensitive: bool,
+    /// Size in bytes above which blocks of content are skipped
+    #[serde(default = "ContentMatchesGuardConfig::default_max_block_size")]
+    pub max_block_size: u32,
+}
+
+impl ContentMatchesGuardConfig {
+    fn default_case_sensitive() -> bool {
+        true
+    }
+
+    fn default_max_block_size() -> u32 {
+        1024 * 1024
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, TS)]
 #[serde(rename_all = "snake_case")]
 #[ts(export)]
@@ -0,0 +1,203 @@
+use std::{fs, path::Path};
+
+use anyhow::{Context as _, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    guard_profile::{
+        profiles::{CORE_NAMESPACE, CORE_PROFILE_FIXTURES},
+        GuardProfile,
+    },
+    message::{Message, MessageDirection},
+    message_interceptor::{
+        manual_approval::{with_scripted_approval, ApprovalAnswer},
+        record::RecordedAction,
+        MessageInterceptorAction,
+    },
+};
+
+/// A set of test cases for a guard profile.
+///
+/// The cases of a fixture run in order through the same message interceptor, so that responses
+/// can be matched to the requests of earlier cases.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GuardProfileFixture {
+    pub name: String,
+    pub cases: Vec<FixtureCase>,
+}
+
+/// A message to intercept, along with what the guard profile is expected to do with it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FixtureCase {
+    pub name: String,
+    pub direction: MessageDirection,
+    pub message: Value,
+    /// Answer to the request for approval of the message, if any. Requests for approval left
+    /// unanswered time out at once.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub approval: Option<ApprovalAnswer>,
+    pub expected_action: ExpectedAction,
+    /// Message expected to be sent on or returned. Left unchecked if unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expected_message: Option<Value>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, strum::Display)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum ExpectedAction {
+    Send,
+    Drop,
+    Return,
+}
+
+/// Outcome of a fixture case.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CaseOutcome {
+    pub fixture_name: String,
+    pub case_name: String,
+    pub action: RecordedAction,
+    /// Why the case failed, if it did
+    pub failure: Option<String>,
+}
+
+impl CaseOutcome {
+    pub fn passed(&self) -> bool {
+        self.failure.is_none()
+    }
+}
+
+/// Loads the fixtures of a fixture directory, one per `.json` file, named after the file. Each
+/// file holds a list of cases.
+pub fn load_fixtures(dir: &Path) -> Result<Vec<GuardProfileFixture>> {
+    let mut paths = fs::read_dir(dir)
+        .with_context(|| format!("Failed to read fixture directory '{}'", dir.display()))?
+        .map(|entry| Ok(entry?.path()))
+        .collect::<Result<Vec<_>>>()?;
+    paths.retain(|path| {
+        path.extension()
+            .is_some_and(|extension| extension == "json")
+    });
+    paths.sort();
+
+    paths
+        .iter()
+        .map(|path| {
+            let name = path
+                .file_stem()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .into_owned();
+            let json_str = fs::read_to_string(path)?;
+            let cases = serde_json::from_str(&json_str)
+                .with_context(|| format!("Invalid fixture '{}'", path.display()))?;
+
+            Ok(GuardProfileFixture { name, cases })
+        })
+        .collect()
+}
+
+/// Loads the fixtures shipped with a core guard profile.
+pub fn load_core_fixtures(namespace: &str, profile_name: &str) -> Result<Vec<GuardProfileFixture>> {
+    if namespace != CORE_NAMESPACE {
+        return Ok(Vec::new());
+    }
+
+    CORE_PROFILE_FIXTURES
+        .iter()
+        .filter(|(name, _)| *name == profile_name)
+        .map(|(name, json_str)| {
+            let cases = serde_json::from_str(json_str)
+                .with_context(|| format!("Invalid fixture of core guard profile '{name}'"))?;
+
+            Ok(GuardProfileFixture {
+                name: (*name).to_owned(),
+                cases,
+            })
+        })
+        .collect()
+}
+
+/// Runs the cases of `fixtures` through the message interceptor of `guard_profile`.
+pub async fn run_fixtures(
+    guard_profile: &GuardProfile,
+    fixtures: &[GuardProfileFixture],
+) -> Result<Vec<CaseOutcome>> {
+    let mut outcomes = Vec::new();
+
+    for GuardProfileFixture { name, cases } in fixtures {
+        let message_interceptor = guard_profile
+            .primary_message_interceptor
+            .clone()
+            .try_into_message_interceptor("fixture".to_owned())?;
+
+        for case in cases {
+            let action = with_scripted_approval(
+                case.approval,
+                message_interceptor
+                    .intercept_message(case.direction, Message::from_json(case.message.clone())),
+            )
+            .await?;
+
+            outcomes.push(CaseOutcome {
+                fixture_name: name.clone(),
+                case_name: case.name.clone(),
+                failure: check_action(case, &action),
+                action: RecordedAction::from_action(&case.message, &action),
+            });
+        }
+    }
+
+    Ok(outcomes)
+}
+
+fn check_action(case: &FixtureCase, action: &MessageInterceptorAction) -> Option<String> {
+    let (actual_action, actual_message) = match action {
+        MessageInterceptorAction::Send(message) => (ExpectedAction::Send, Some(&message.raw_msg)),
+        MessageInterceptorAction::Drop => (ExpectedAction::Drop, None),
+        MessageInterceptorAction::Return(message) => {
+            (ExpectedAction::Return, Some(&message.raw_msg))
+        }
+    };
+
+    if actual_action != case.expected_action {
+        return Some(format!(
+            "expected {}, got {actual_action}",
+            case.expected_action
+        ));
+    }
+
+    match (&case.expected_message, actual_message) {
+        (Some(expected_message), Some(actual_message)) if expected_message != actual_message => {
+            Some(format!(
+                "expected message {expected_message}, got {actual_message}"
+            ))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::guard_profile::profiles::CORE_PROFILES;
+
+    #[tokio::test]
+    async fn test_core_guard_profile_fixtures() {
+        for (profile_name, json_str) in CORE_PROFILES {
+            let guard_profile = serde_json::from_str::<GuardProfile>(json_str).unwrap();
+            let fixtures = load_core_fixtures(CORE_NAMESPACE, profile_name).unwrap();
+            assert!(!fixtures.is_empty(), "{profile_name} has no fixtures");
+
+            for outcome in run_fixtures(&guard_profile, &fixtures).await.unwrap() {
+                assert!(
+                    outcome.passed(),
+                    "{profile_name}: {}: {}",
+                    outcome.case_name,
+                    outcome.failure.unwrap()
+                );
+            }
+        }
+    }
+}
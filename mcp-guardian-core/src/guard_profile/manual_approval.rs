@@ -1,24 +1,147 @@
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 
-use anyhow::Result;
+use anyhow::{bail, Result};
 use serde::{Deserialize, Serialize};
 use ts_rs::TS;
 
-use crate::message_interceptor::{manual_approval::ManualApprovalInterceptor, MessageInterceptor};
+use crate::{
+    message_approval::ApprovalTimeoutAction,
+    message_interceptor::{
+        manual_approval::{DenialResponse, DenialResponseKind, ManualApprovalInterceptor},
+        MessageInterceptor,
+    },
+};
+
+/// Shortest interval approvals may be polled at, so waiting for approval doesn't spin on the
+/// filesystem.
+const MIN_POLL_INTERVAL_MS: u32 = 50;
 
 #[derive(Debug, Clone, Serialize, Deserialize, TS)]
 #[ts(export)]
-pub struct ManualApprovalGuardConfig {}
+pub struct ManualApprovalGuardConfig {
+    /// How long to wait for approval before taking `timeout_action`. Waits indefinitely if unset.
+    #[serde(default)]
+    #[ts(optional = nullable)]
+    pub timeout_ms: Option<u32>,
+    /// What becomes of the message once its approval times out
+    #[serde(default)]
+    #[ts(as = "Option<ApprovalTimeoutAction>", optional)]
+    pub timeout_action: ApprovalTimeoutAction,
+    /// How often to check whether the message was approved. At least 50 ms.
+    #[serde(default = "ManualApprovalGuardConfig::default_poll_interval_ms")]
+    #[ts(as = "Option<u32>", optional)]
+    pub poll_interval_ms: u32,
+    /// How denied messages are answered. Uses the defaults of [`DenialResponseGuardConfig`] if
+    /// unset.
+    #[serde(default)]
+    #[ts(optional = nullable)]
+    pub denial_response: Option<DenialResponseGuardConfig>,
+}
+
+/// Answer to a denied message: for `tools/call` requests and responses, a `tools/call` result
+/// flagged as an error or a json-rpc error as `kind` says, a json-rpc error otherwise.
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct DenialResponseGuardConfig {
+    /// Text of the tool result, or message of the json-rpc error
+    #[serde(default)]
+    #[ts(optional = nullable)]
+    pub message: Option<String>,
+    /// Code of the json-rpc error
+    #[serde(default)]
+    #[ts(optional = nullable)]
+    pub code: Option<i32>,
+    /// How denied `tools/call` requests and responses are answered, a tool result flagged as an
+    /// error if unset
+    #[serde(default)]
+    #[ts(optional = nullable)]
+    pub kind: Option<DenialResponseKind>,
+}
+
+impl From<DenialResponseGuardConfig> for DenialResponse {
+    fn from(value: DenialResponseGuardConfig) -> Self {
+        let default = DenialResponse::default();
+
+        DenialResponse {
+            message: value.message.unwrap_or(default.message),
+            code: value.code.unwrap_or(default.code),
+            kind: value.kind.unwrap_or(default.kind),
+        }
+    }
+}
 
 impl ManualApprovalGuardConfig {
+    fn default_poll_interval_ms() -> u32 {
+        1000
+    }
+
     pub fn try_into_message_interceptor(
         self,
         mcp_server_name: String,
     ) -> Result<Arc<dyn MessageInterceptor>> {
-        let _ = mcp_server_name;
+        if self.poll_interval_ms < MIN_POLL_INTERVAL_MS {
+            bail!(
+                "poll_interval_ms must be at least {MIN_POLL_INTERVAL_MS}, got {}.",
+                self.poll_interval_ms
+            );
+        }
 
-        let interceptor = Arc::new(ManualApprovalInterceptor::new(mcp_server_name));
+        let interceptor = Arc::new(ManualApprovalInterceptor::new(
+            mcp_server_name,
+            self.timeout_ms.map(|ms| Duration::from_millis(ms.into())),
+            self.timeout_action,
+            Duration::from_millis(self.poll_interval_ms.into()),
+            self.denial_response.map(Into::into).unwrap_or_default(),
+        ));
 
         Ok(interceptor)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_defaults() {
+        let config: ManualApprovalGuardConfig = serde_json::from_value(json!({})).unwrap();
+
+        assert_eq!(config.timeout_ms, None);
+        assert_eq!(config.timeout_action, ApprovalTimeoutAction::Deny);
+        assert_eq!(config.poll_interval_ms, 1000);
+
+        let config: ManualApprovalGuardConfig = serde_json::from_value(json!({
+            "timeout_ms": 60000,
+            "timeout_action": "drop"
+        }))
+        .unwrap();
+
+        assert_eq!(config.timeout_ms, Some(60000));
+        assert_eq!(config.timeout_action, ApprovalTimeoutAction::Drop);
+
+        let config: ManualApprovalGuardConfig = serde_json::from_value(json!({
+            "denial_response": { "kind": "json_rpc_error" }
+        }))
+        .unwrap();
+        let denial_response: DenialResponse = config.denial_response.unwrap().into();
+
+        assert_eq!(denial_response.kind, DenialResponseKind::JsonRpcError);
+        assert_eq!(denial_response.code, DenialResponse::default().code);
+    }
+
+    #[test]
+    fn test_poll_interval() {
+        let config = |poll_interval_ms: u32| -> ManualApprovalGuardConfig {
+            serde_json::from_value(json!({ "poll_interval_ms": poll_interval_ms })).unwrap()
+        };
+
+        assert!(config(0)
+            .try_into_message_interceptor("test".to_owned())
+            .is_err());
+        assert!(config(MIN_POLL_INTERVAL_MS)
+            .try_into_message_interceptor("test".to_owned())
+            .is_ok());
+    }
+}
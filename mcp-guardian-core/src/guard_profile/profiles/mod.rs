@@ -19,3 +19,24 @@ pub static CORE_PROFILES: &[(&str, &str)] = &[
     ),
     ("log-only", include_str!("./log-only.json")),
 ];
+
+/// Fixtures of the core guard profiles, by profile name.
+pub static CORE_PROFILE_FIXTURES: &[(&str, &str)] = &[
+    (
+        "approve-tool-call-requests-and-responses",
+        include_str!("./fixtures/approve-tool-call-requests-and-responses.json"),
+    ),
+    (
+        "approve-tool-call-requests",
+        include_str!("./fixtures/approve-tool-call-requests.json"),
+    ),
+    (
+        "approve-tool-call-responses",
+        include_str!("./fixtures/approve-tool-call-responses.json"),
+    ),
+    (
+        "block-get-current-time",
+        include_str!("./fixtures/block-get-current-time.json"),
+    ),
+    ("log-only", include_str!("./fixtures/log-only.json")),
+];
@@ -0,0 +1,254 @@
+use std::{collections::HashMap, sync::Arc};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    guard_profile::GuardProfile,
+    message::{Message, MessageDirection},
+    message_interceptor::{
+        manual_approval::{with_scripted_approval, ApprovalAnswer},
+        record::{RecordedAction, RecordedMessage},
+        MessageInterceptor,
+    },
+};
+
+/// Answer to the requests for approval of the messages it matches, when replaying a transcript.
+/// Matches every message on the criteria it leaves unset.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScriptedApproval {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mcp_server_name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub direction: Option<MessageDirection>,
+    /// json-rpc method of the message
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub method: Option<String>,
+    /// json-rpc id of the message
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<Value>,
+    /// Answer to the request for approval, or none to let it time out
+    pub answer: Option<ApprovalAnswer>,
+}
+
+impl ScriptedApproval {
+    fn matches(&self, recorded_message: &RecordedMessage) -> bool {
+        let Self {
+            mcp_server_name,
+            direction,
+            method,
+            id,
+            answer: _,
+        } = self;
+
+        mcp_server_name
+            .as_ref()
+            .is_none_or(|name| name == &recorded_message.mcp_server_name)
+            && direction.is_none_or(|direction| direction == recorded_message.direction)
+            && method
+                .as_deref()
+                .is_none_or(|method| recorded_message.message["method"].as_str() == Some(method))
+            && id
+                .as_ref()
+                .is_none_or(|id| recorded_message.message.get("id") == Some(id))
+    }
+}
+
+/// A recorded message, along with what the replayed guard profile did with it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReplayedMessage {
+    pub recorded_message: RecordedMessage,
+    pub action: RecordedAction,
+}
+
+impl ReplayedMessage {
+    /// Whether the guard profile did something else with the message than was recorded.
+    pub fn changed(&self) -> bool {
+        self.action != self.recorded_message.action
+    }
+}
+
+/// Replays the messages of a transcript through the message interceptor of `guard_profile`, as
+/// if they were proxied again, without a live MCP server.
+///
+/// Requests for approval are answered by the first of `scripted_approvals` matching the message,
+/// or else as they were in the transcript.
+pub async fn replay_transcript(
+    guard_profile: &GuardProfile,
+    recorded_messages: Vec<RecordedMessage>,
+    scripted_approvals: &[ScriptedApproval],
+) -> Result<Vec<ReplayedMessage>> {
+    let mut message_interceptors = HashMap::<String, Arc<dyn MessageInterceptor>>::new();
+    let mut replayed_messages = Vec::with_capacity(recorded_messages.len());
+
+    for recorded_message in recorded_messages {
+        let message_interceptor = match message_interceptors.get(&recorded_message.mcp_server_name)
+        {
+            Some(message_interceptor) => message_interceptor.clone(),
+            None => {
+                let message_interceptor = guard_profile
+                    .primary_message_interceptor
+                    .clone()
+                    .try_into_message_interceptor(recorded_message.mcp_server_name.clone())?;
+                message_interceptors.insert(
+                    recorded_message.mcp_server_name.clone(),
+                    message_interceptor.clone(),
+                );
+                message_interceptor
+            }
+        };
+
+        let answer = match scripted_approvals
+            .iter()
+            .find(|scripted_approval| scripted_approval.matches(&recorded_message))
+        {
+            Some(scripted_approval) => scripted_approval.answer,
+            None => transcript_answer(&recorded_message.action),
+        };
+
+        let raw_msg = recorded_message.message.clone();
+        let action = with_scripted_approval(
+            answer,
+            message_interceptor
+                .intercept_message(recorded_message.direction, Message::from_json(raw_msg)),
+        )
+        .await?;
+
+        replayed_messages.push(ReplayedMessage {
+            action: RecordedAction::from_action(&recorded_message.message, &action),
+            recorded_message,
+        });
+    }
+
+    Ok(replayed_messages)
+}
+
+/// Tells how a request for approval of a recorded message was answered, from what became of it.
+///
+/// Messages sent on unchanged were approved, and messages dropped or answered otherwise were
+/// denied, unless the answer says the request timed out.
+fn transcript_answer(action: &RecordedAction) -> Option<ApprovalAnswer> {
+    let reason = |message: &Value| {
+        message["error"]["data"]["reason"]
+            .as_str()
+            .map(str::to_owned)
+    };
+
+    match action {
+        RecordedAction::Send { message: None } => Some(ApprovalAnswer::Approve),
+        RecordedAction::Send {
+            message: Some(message),
+        } => match reason(message).as_deref() {
+            Some("timed_out") => None,
+            Some("denied") => Some(ApprovalAnswer::Deny),
+            _ => Some(ApprovalAnswer::Approve),
+        },
+        RecordedAction::Drop => Some(ApprovalAnswer::Deny),
+        RecordedAction::Return { message } => match reason(message).as_deref() {
+            Some("timed_out") => None,
+            _ => Some(ApprovalAnswer::Deny),
+        },
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::*;
+    use crate::{
+        guard_profile::{
+            manual_approval::ManualApprovalGuardConfig, MessageInterceptorGuardConfig,
+        },
+        message::{MessageDirection::Outbound, MessageType},
+    };
+
+    fn recorded_message(id: u32, method: &str, action: RecordedAction) -> RecordedMessage {
+        RecordedMessage {
+            timestamp: "2025-01-01T00:00:00.000Z".to_owned(),
+            session_id: "session".to_owned(),
+            host_session_id: None,
+            mcp_server_name: "test".to_owned(),
+            direction: Outbound,
+            message_type: MessageType::Request,
+            message: json!({"jsonrpc": "2.0", "id": id, "method": method}),
+            action,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_replay_transcript() {
+        let guard_profile = GuardProfile {
+            primary_message_interceptor: MessageInterceptorGuardConfig::ManualApproval(
+                serde_json::from_value::<ManualApprovalGuardConfig>(json!({})).unwrap(),
+            ),
+        };
+        let denied = RecordedAction::Return {
+            message: json!({
+                "jsonrpc": "2.0",
+                "id": 2,
+                "error": {
+                    "code": -32001,
+                    "message": "Access approval was denied.",
+                    "data": { "reason": "denied" }
+                }
+            }),
+        };
+        let recorded_messages = vec![
+            recorded_message(1, "tools/list", RecordedAction::Send { message: None }),
+            recorded_message(2, "resources/list", denied.clone()),
+            recorded_message(3, "prompts/list", RecordedAction::Send { message: None }),
+        ];
+        let scripted_approvals = [ScriptedApproval {
+            mcp_server_name: None,
+            direction: None,
+            method: Some("prompts/list".to_owned()),
+            id: None,
+            answer: Some(ApprovalAnswer::Approve),
+        }];
+
+        let replayed_messages = replay_transcript(
+            &guard_profile,
+            recorded_messages.clone(),
+            &scripted_approvals,
+        )
+        .await
+        .unwrap();
+        assert!(replayed_messages.iter().all(|replayed| !replayed.changed()));
+
+        let scripted_approvals = [ScriptedApproval {
+            answer: Some(ApprovalAnswer::Deny),
+            ..scripted_approvals[0].clone()
+        }];
+        let replayed_messages =
+            replay_transcript(&guard_profile, recorded_messages, &scripted_approvals)
+                .await
+                .unwrap();
+        let actions = replayed_messages
+            .iter()
+            .map(|replayed| (replayed.changed(), replayed.action.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            actions,
+            [
+                (false, RecordedAction::Send { message: None }),
+                (false, denied),
+                (
+                    true,
+                    RecordedAction::Return {
+                        message: json!({
+                            "jsonrpc": "2.0",
+                            "id": 3,
+                            "error": {
+                                "code": -32001,
+                                "message": "Access approval was denied.",
+                                "data": { "reason": "denied" }
+                            }
+                        })
+                    }
+                ),
+            ]
+        );
+    }
+}
@@ -13,6 +13,7 @@ pub mod message_interceptor;
 pub mod proxy;
 pub mod request_cache;
 pub mod server_collection;
+pub mod server_log;
 
 static APP_NAME: &str = "mcp-guardian";
 
@@ -1,3 +1,8 @@
+pub mod env;
+pub mod launch;
+pub mod limits;
+pub mod network;
+pub mod sandbox;
 pub mod servers;
 
 use std::{collections::HashMap, fs};
@@ -8,18 +13,161 @@ use ts_rs::TS;
 
 use crate::{
     dirs::AppSubDir::McpServers,
+    mcp_server::env::InheritEnv,
+    mcp_server::limits::ResourceLimits,
+    mcp_server::network::NetworkIsolation,
+    mcp_server::sandbox::SandboxConfig,
     mcp_server::servers::{CORE_NAMESPACE, CORE_SERVERS},
     server_collection::claude_config::{ClaudeConfig, ClaudeMcpServer},
 };
 
-#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize, TS)]
 #[ts(export)]
 pub struct McpServer {
+    #[serde(default)]
     pub cmd: String,
+    #[serde(default)]
     pub args: Vec<String>,
+    /// Absolute path of the directory the server process runs in. The proxy's own working
+    /// directory if unset.
+    #[serde(default)]
+    #[ts(optional = nullable)]
+    pub cwd: Option<String>,
+    /// Shell the server is launched through, e.g. `/bin/sh`. `cmd` is then a command line in the
+    /// shell's syntax, which `args` are appended to, quoted.
+    #[serde(default)]
+    #[ts(optional = nullable)]
+    pub shell: Option<String>,
+    /// Umask of the server process in octal, e.g. `"077"`. Unix only.
+    #[serde(default)]
+    #[ts(optional = nullable)]
+    pub umask: Option<String>,
+    /// Size in bytes of the buffer messages to the server process are written through
+    #[serde(default)]
+    #[ts(optional = nullable)]
+    pub stdin_buffer_size: Option<u32>,
+    /// Size in bytes of the buffer messages from the server process are read through
+    #[serde(default)]
+    #[ts(optional = nullable)]
+    pub stdout_buffer_size: Option<u32>,
+    /// Sandbox the server process is launched in. Linux only.
+    #[serde(default)]
+    #[ts(optional = nullable)]
+    pub sandbox: Option<SandboxConfig>,
+    /// Network the server process may access, from a network namespace of its own. Linux only.
+    /// Unrestricted if unset.
+    #[serde(default)]
+    #[ts(optional = nullable)]
+    pub network: Option<NetworkIsolation>,
+    /// Resource limits of the server process. Unix only.
+    #[serde(default)]
+    #[ts(optional = nullable)]
+    pub limits: Option<ResourceLimits>,
+    /// Environment variables set for the server process. Values may reference the proxy's own
+    /// environment with `${VAR}` or `${VAR:-default}`.
     #[serde(default, skip_serializing_if = "HashMap::is_empty")]
     #[ts(skip)]
     pub env: HashMap<String, String>,
+    /// Which of the proxy's environment variables the server process inherits. Inherits all of
+    /// them if unset.
+    #[serde(default)]
+    #[ts(optional = nullable)]
+    pub inherit_env: Option<InheritEnv>,
+    /// Restarts the server process if it crashes. The proxy exits along with the server if unset.
+    #[serde(default)]
+    #[ts(optional = nullable)]
+    pub restart: Option<RestartPolicy>,
+    /// How the server process' stderr is logged. Uses the defaults of [`StderrLogConfig`] if
+    /// unset.
+    #[serde(default)]
+    #[ts(optional = nullable)]
+    pub stderr_log: Option<StderrLogConfig>,
+    /// Remote MCP server to connect to instead of spawning `cmd`.
+    #[serde(default)]
+    #[ts(optional = nullable)]
+    pub remote: Option<RemoteMcpServer>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct RestartPolicy {
+    /// Maximum number of restarts before giving up
+    pub max_retries: u32,
+    /// Delay before the first restart, doubled after every further restart
+    #[serde(default = "RestartPolicy::default_initial_backoff_ms")]
+    pub initial_backoff_ms: u32,
+    /// Upper limit on the delay between restarts
+    #[serde(default = "RestartPolicy::default_max_backoff_ms")]
+    pub max_backoff_ms: u32,
+}
+
+impl RestartPolicy {
+    fn default_initial_backoff_ms() -> u32 {
+        500
+    }
+
+    fn default_max_backoff_ms() -> u32 {
+        30_000
+    }
+}
+
+/// Stderr of a server process is captured line by line into a log per proxy session, under
+/// `AppSubDir::ServerLogs`.
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct StderrLogConfig {
+    /// Also write captured lines to the proxy's own stderr
+    #[serde(default)]
+    pub mirror: bool,
+    /// Size in bytes at which the log is rotated
+    #[serde(default = "StderrLogConfig::default_max_file_size")]
+    pub max_file_size: u32,
+    /// Number of rotated logs kept alongside the current one
+    #[serde(default = "StderrLogConfig::default_max_files")]
+    pub max_files: u32,
+}
+
+impl StderrLogConfig {
+    fn default_max_file_size() -> u32 {
+        10 * 1024 * 1024
+    }
+
+    fn default_max_files() -> u32 {
+        3
+    }
+}
+
+impl Default for StderrLogConfig {
+    fn default() -> Self {
+        Self {
+            mirror: false,
+            max_file_size: Self::default_max_file_size(),
+            max_files: Self::default_max_files(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct RemoteMcpServer {
+    pub url: String,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    #[ts(skip)]
+    pub headers: HashMap<String, String>,
+    #[serde(default)]
+    pub transport: RemoteTransport,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize, TS, strum::Display)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+#[ts(export)]
+pub enum RemoteTransport {
+    /// Streamable HTTP transport (MCP 2025-03-26 and later)
+    #[default]
+    StreamableHttp,
+    /// Legacy HTTP+SSE transport (MCP 2024-11-05)
+    Sse,
 }
 
 impl From<&ClaudeMcpServer> for McpServer {
@@ -27,7 +175,9 @@ impl From<&ClaudeMcpServer> for McpServer {
         McpServer {
             cmd: value.command.clone(),
             args: value.args.clone(),
+            cwd: value.cwd.clone(),
             env: value.env.clone(),
+            ..Default::default()
         }
     }
 }
@@ -77,6 +227,8 @@ pub fn save_mcp_server(namespace: &str, name: &str, mcp_server: &McpServer) -> R
         bail!("Failed to save MCP server. The `{CORE_NAMESPACE}` namespace is reserved for built-in MCP servers.")
     }
 
+    launch::validate(mcp_server)?;
+
     log::info!("Saving MCP server '{name}'.");
     let json_str = serde_json::to_string_pretty(mcp_server)?;
 
@@ -0,0 +1,201 @@
+use std::{collections::HashMap, ffi::OsString, process::Command};
+
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+/// Environment variables an MCP server process inherits from the proxy.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[serde(rename_all = "snake_case")]
+#[ts(export)]
+pub enum InheritEnv {
+    /// Inherit every environment variable
+    All,
+    /// Inherit no environment variables
+    None,
+    /// Inherit only the listed environment variables
+    Allowlist(Vec<String>),
+}
+
+/// Applies an MCP server's environment to the command used to launch it.
+///
+/// Values in `env` are expanded against the proxy's own environment (see [`expand`]), regardless
+/// of which variables are inherited.
+pub fn apply_env(
+    command: &mut Command,
+    env: &HashMap<String, String>,
+    inherit_env: Option<&InheritEnv>,
+) -> Result<()> {
+    apply_env_with(command, env, inherit_env, |name| std::env::var_os(name))
+}
+
+/// Applies an MCP server's environment as [`apply_env`] does, looking the proxy's environment
+/// variables up with `var`.
+fn apply_env_with(
+    command: &mut Command,
+    env: &HashMap<String, String>,
+    inherit_env: Option<&InheritEnv>,
+    var: impl Fn(&str) -> Option<OsString>,
+) -> Result<()> {
+    match inherit_env {
+        None | Some(InheritEnv::All) => {}
+        Some(InheritEnv::None) => {
+            command.env_clear();
+        }
+        Some(InheritEnv::Allowlist(names)) => {
+            command.env_clear();
+            for name in names {
+                if let Some(value) = var(name) {
+                    command.env(name, value);
+                }
+            }
+        }
+    }
+
+    for (name, value) in env {
+        let value = expand(value, |name| var(name)?.into_string().ok())?;
+        command.env(name, value);
+    }
+
+    Ok(())
+}
+
+/// Expands `${VAR}` and `${VAR:-default}` references in `value`, looking variables up with
+/// `lookup`. The default is used when the variable is unset or empty, and `$$` escapes a `$`.
+///
+/// # Errors
+///
+/// Returns an error if a reference is unterminated, or names a variable that is unset and has no
+/// default.
+pub fn expand(value: &str, lookup: impl Fn(&str) -> Option<String>) -> Result<String> {
+    let mut expanded = String::with_capacity(value.len());
+    let mut rest = value;
+
+    while let Some(pos) = rest.find('$') {
+        expanded.push_str(&rest[..pos]);
+        rest = &rest[pos..];
+
+        if let Some(after) = rest.strip_prefix("$$") {
+            expanded.push('$');
+            rest = after;
+            continue;
+        }
+
+        let Some(after) = rest.strip_prefix("${") else {
+            expanded.push('$');
+            rest = &rest[1..];
+            continue;
+        };
+
+        let Some(end) = after.find('}') else {
+            bail!("Unterminated environment variable reference: {rest}");
+        };
+
+        let (name, default) = match after[..end].split_once(":-") {
+            Some((name, default)) => (name, Some(default)),
+            None => (&after[..end], None),
+        };
+
+        match (lookup(name), default) {
+            (Some(value), Some(default)) if value.is_empty() => expanded.push_str(default),
+            (Some(value), _) => expanded.push_str(&value),
+            (None, Some(default)) => expanded.push_str(default),
+            (None, None) => bail!("Environment variable '{name}' is not set."),
+        }
+
+        rest = &after[end + 1..];
+    }
+
+    expanded.push_str(rest);
+
+    Ok(expanded)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn lookup(name: &str) -> Option<String> {
+        match name {
+            "API_KEY" => Some("secret".to_owned()),
+            "EMPTY" => Some(String::new()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_expand() {
+        let cases = [
+            ("plain", "plain"),
+            ("${API_KEY}", "secret"),
+            ("Bearer ${API_KEY}!", "Bearer secret!"),
+            ("${MISSING:-fallback}", "fallback"),
+            ("${EMPTY:-fallback}", "fallback"),
+            ("${EMPTY}", ""),
+            ("${MISSING:-}", ""),
+            ("$$${API_KEY} $HOME $", "$secret $HOME $"),
+        ];
+
+        for (value, expected) in cases {
+            assert_eq!(expand(value, lookup).unwrap(), expected, "{value}");
+        }
+    }
+
+    #[test]
+    fn test_expand_errors() {
+        assert!(expand("${MISSING}", lookup).is_err());
+        assert!(expand("${API_KEY", lookup).is_err());
+    }
+
+    /// Runs `env` with the MCP server environment applied, returning the variables it sees.
+    #[cfg(unix)]
+    fn child_env(inherit_env: Option<&InheritEnv>) -> HashMap<String, String> {
+        let mut command = Command::new("/usr/bin/env");
+        let env = HashMap::from([(
+            "SERVER_TOKEN".to_owned(),
+            "${MCP_GUARDIAN_TEST_SECRET}".to_owned(),
+        )]);
+        let var = |name: &str| match name {
+            "MCP_GUARDIAN_TEST_SECRET" => Some("secret".into()),
+            "MCP_GUARDIAN_TEST_ALLOWED" => Some("allowed".into()),
+            _ => None,
+        };
+        apply_env_with(&mut command, &env, inherit_env, var).unwrap();
+
+        let output = command.output().unwrap();
+        String::from_utf8(output.stdout)
+            .unwrap()
+            .lines()
+            .filter_map(|line| line.split_once('='))
+            .map(|(name, value)| (name.to_owned(), value.to_owned()))
+            .collect()
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_apply_env() {
+        // everything is inherited from the process itself
+        let env = child_env(None);
+        assert!(env.contains_key("PATH"));
+        assert_eq!(env["SERVER_TOKEN"], "secret");
+
+        // nothing is inherited, but the server's own env is still expanded
+        let env = child_env(Some(&InheritEnv::None));
+        assert_eq!(
+            env,
+            HashMap::from([("SERVER_TOKEN".to_owned(), "secret".to_owned())])
+        );
+
+        let env = child_env(Some(&InheritEnv::Allowlist(vec![
+            "MCP_GUARDIAN_TEST_ALLOWED".to_owned(),
+            "MCP_GUARDIAN_TEST_UNSET".to_owned(),
+        ])));
+        assert_eq!(
+            env,
+            HashMap::from([
+                ("MCP_GUARDIAN_TEST_ALLOWED".to_owned(), "allowed".to_owned()),
+                ("SERVER_TOKEN".to_owned(), "secret".to_owned()),
+            ])
+        );
+    }
+}
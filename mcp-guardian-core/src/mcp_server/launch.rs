@@ -0,0 +1,323 @@
+use std::{path::Path, process::Command};
+
+use anyhow::{anyhow, bail, Result};
+
+use crate::mcp_server::{
+    env::apply_env, limits::apply_limits, network::apply_network, sandbox, sandbox::apply_sandbox,
+    McpServer,
+};
+
+/// Builds the command an MCP server process is launched with: its program and arguments, run
+/// through its shell if it has one, in its working directory, with its environment and umask, and
+/// in its network namespace and sandbox, with its resource limits.
+pub fn build_command(mcp_server: &McpServer) -> Result<Command> {
+    let mut command = match &mcp_server.shell {
+        Some(shell) => {
+            let mut command = Command::new(shell);
+            command.arg(shell_flag(shell)).arg(shell_command_line(
+                shell,
+                &mcp_server.cmd,
+                &mcp_server.args,
+            ));
+            command
+        }
+        None => {
+            let mut command = Command::new(&mcp_server.cmd);
+            command.args(&mcp_server.args);
+            command
+        }
+    };
+
+    if let Some(cwd) = &mcp_server.cwd {
+        command.current_dir(cwd);
+    }
+
+    apply_env(
+        &mut command,
+        &mcp_server.env,
+        mcp_server.inherit_env.as_ref(),
+    )?;
+
+    if let Some(umask) = &mcp_server.umask {
+        apply_umask(&mut command, parse_umask(umask)?);
+    }
+
+    // the network namespace is set up before the sandbox, which denies the syscalls doing so
+    if let Some(network) = mcp_server.network {
+        apply_network(&mut command, network)?;
+    }
+
+    if let Some(sandbox) = &mcp_server.sandbox {
+        apply_sandbox(&mut command, sandbox)?;
+    }
+
+    // the resource limits are applied last, so that a low limit on open files doesn't get in the
+    // way of setting up the rest
+    if let Some(limits) = &mcp_server.limits {
+        apply_limits(&mut command, limits)?;
+    }
+
+    Ok(command)
+}
+
+/// Checks that an MCP server has a command to launch or a remote server to connect to, and checks
+/// its launch options, so that a server that can't be launched isn't saved.
+pub fn validate(mcp_server: &McpServer) -> Result<()> {
+    if mcp_server.remote.is_some() {
+        // remote servers aren't launched, so their launch options would be silently ignored
+        let launch_options = [
+            ("cwd", mcp_server.cwd.is_some()),
+            ("shell", mcp_server.shell.is_some()),
+            ("umask", mcp_server.umask.is_some()),
+            ("stdin_buffer_size", mcp_server.stdin_buffer_size.is_some()),
+            (
+                "stdout_buffer_size",
+                mcp_server.stdout_buffer_size.is_some(),
+            ),
+            ("sandbox", mcp_server.sandbox.is_some()),
+            ("network", mcp_server.network.is_some()),
+            ("limits", mcp_server.limits.is_some()),
+        ]
+        .into_iter()
+        .filter_map(|(name, set)| set.then_some(name))
+        .collect::<Vec<_>>();
+
+        if !launch_options.is_empty() {
+            bail!(
+                "Launch options can't be set for a remote MCP server: {}.",
+                launch_options.join(", ")
+            );
+        }
+
+        return Ok(());
+    }
+
+    if mcp_server.cmd.trim().is_empty() {
+        bail!("MCP server has neither a command nor a remote server.");
+    }
+
+    if let Some(cwd) = &mcp_server.cwd {
+        let path = Path::new(cwd);
+        if !path.is_absolute() {
+            bail!("Working directory '{cwd}' is not an absolute path.");
+        }
+        if !path.is_dir() {
+            bail!("Working directory '{cwd}' does not exist.");
+        }
+    }
+
+    if let Some(shell) = &mcp_server.shell {
+        let path = Path::new(shell);
+        // shells given by name alone are looked up on the PATH at launch
+        if path.components().count() > 1 && !path.is_file() {
+            bail!("Shell '{shell}' does not exist.");
+        }
+    }
+
+    if let Some(umask) = &mcp_server.umask {
+        parse_umask(umask)?;
+    }
+
+    if mcp_server.stdin_buffer_size == Some(0) || mcp_server.stdout_buffer_size == Some(0) {
+        bail!("Buffer sizes must be greater than zero.");
+    }
+
+    if let Some(sandbox) = &mcp_server.sandbox {
+        sandbox::validate(sandbox)?;
+    }
+
+    Ok(())
+}
+
+/// Parses a umask written in octal, e.g. `"022"` or `"0o077"`.
+pub fn parse_umask(umask: &str) -> Result<u32> {
+    let digits = umask.strip_prefix("0o").unwrap_or(umask);
+
+    match u32::from_str_radix(digits, 8) {
+        Ok(umask) if umask <= 0o777 => Ok(umask),
+        _ => Err(anyhow!(
+            "Invalid umask '{umask}'. Expected octal permission bits, e.g. \"022\"."
+        )),
+    }
+}
+
+#[cfg(unix)]
+fn apply_umask(command: &mut Command, umask: u32) {
+    use std::os::unix::process::CommandExt;
+
+    use nix::sys::stat::{umask as set_umask, Mode};
+
+    let mode = Mode::from_bits_truncate(umask as nix::libc::mode_t);
+
+    // SAFETY: umask(2) is async-signal-safe, so it may be called between fork and exec.
+    unsafe {
+        command.pre_exec(move || {
+            set_umask(mode);
+            Ok(())
+        });
+    }
+}
+
+#[cfg(not(unix))]
+fn apply_umask(_command: &mut Command, _umask: u32) {
+    log::warn!("Ignoring umask, which is only supported on unix.");
+}
+
+/// Name of a shell's program, without its directory or extension, whichever platform's path it
+/// is.
+fn shell_name(shell: &str) -> String {
+    let file_name = shell.rsplit(['/', '\\']).next().unwrap_or_default();
+    let name = file_name.split('.').next().unwrap_or_default();
+
+    name.to_ascii_lowercase()
+}
+
+/// Flag making a shell run the command line that follows.
+fn shell_flag(shell: &str) -> &'static str {
+    match shell_name(shell).as_str() {
+        "cmd" => "/C",
+        "powershell" | "pwsh" => "-Command",
+        _ => "-c",
+    }
+}
+
+/// The command line a shell runs: `cmd` as it is, so that it may use the shell's syntax, followed
+/// by `args`, each quoted so that the shell passes it on unchanged.
+fn shell_command_line(shell: &str, cmd: &str, args: &[String]) -> String {
+    let quote = match shell_name(shell).as_str() {
+        "cmd" => quote_cmd,
+        "powershell" | "pwsh" => quote_powershell,
+        _ => quote_posix,
+    };
+
+    let mut command_line = cmd.to_owned();
+    for arg in args {
+        command_line.push(' ');
+        command_line.push_str(&quote(arg));
+    }
+
+    command_line
+}
+
+fn quote_posix(arg: &str) -> String {
+    let safe = |c: char| c.is_ascii_alphanumeric() || "_-./=:@,+%".contains(c);
+
+    if !arg.is_empty() && arg.chars().all(safe) {
+        return arg.to_owned();
+    }
+
+    format!("'{}'", arg.replace('\'', r"'\''"))
+}
+
+/// Quotes an argument as Windows programs split their command line, then escapes cmd's
+/// metacharacters with carets, quotes included so that cmd doesn't leave the quoted part alone.
+fn quote_cmd(arg: &str) -> String {
+    let mut escaped = String::new();
+    for c in quote_command_line_arg(arg).chars() {
+        if "&|<>^%\"".contains(c) {
+            escaped.push('^');
+        }
+        escaped.push(c);
+    }
+
+    escaped
+}
+
+/// Quotes an argument as Windows programs split their command line: in double quotes, with the
+/// backslashes before a double quote, or before the closing one, escaped.
+fn quote_command_line_arg(arg: &str) -> String {
+    if !arg.is_empty() && !arg.contains([' ', '\t', '"']) {
+        return arg.to_owned();
+    }
+
+    let mut quoted = String::from('"');
+    let mut backslashes = 0;
+    for c in arg.chars() {
+        if c == '"' {
+            quoted.push_str(&"\\".repeat(backslashes + 1));
+        }
+        backslashes = if c == '\\' { backslashes + 1 } else { 0 };
+        quoted.push(c);
+    }
+    quoted.push_str(&"\\".repeat(backslashes));
+    quoted.push('"');
+
+    quoted
+}
+
+fn quote_powershell(arg: &str) -> String {
+    let safe = |c: char| c.is_ascii_alphanumeric() || "_-./=:\\".contains(c);
+
+    if !arg.is_empty() && arg.chars().all(safe) {
+        return arg.to_owned();
+    }
+
+    format!("'{}'", arg.replace('\'', "''"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mcp_server::RemoteMcpServer;
+
+    #[test]
+    fn test_shell_command_line() {
+        let args = [
+            "--root".to_owned(),
+            "/home/me/my repo".to_owned(),
+            "it's".to_owned(),
+            String::new(),
+            "a&b".to_owned(),
+            "%PATH%".to_owned(),
+            "$x".to_owned(),
+            r#"say "hi" C:\"#.to_owned(),
+        ];
+
+        assert_eq!(
+            shell_command_line("/bin/sh", "npx -y server | tee out", &args),
+            r#"npx -y server | tee out --root '/home/me/my repo' 'it'\''s' '' 'a&b' %PATH% '$x' 'say "hi" C:\'"#
+        );
+        assert_eq!(
+            shell_command_line("C:\\Windows\\System32\\cmd.exe", "server.exe", &args),
+            r#"server.exe --root ^"/home/me/my repo^" it's ^"^" a^&b ^%PATH^% $x ^"say \^"hi\^" C:\\^""#
+        );
+        for shell in ["powershell.exe", "pwsh"] {
+            assert_eq!(
+                shell_command_line(shell, "server.exe", &args),
+                r#"server.exe --root '/home/me/my repo' 'it''s' '' 'a&b' '%PATH%' '$x' 'say "hi" C:\'"#
+            );
+        }
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(validate(&McpServer::default()).is_err());
+
+        let remote = McpServer {
+            remote: Some(RemoteMcpServer {
+                url: "http://localhost:8000/mcp".to_owned(),
+                headers: Default::default(),
+                transport: Default::default(),
+            }),
+            ..Default::default()
+        };
+        assert!(validate(&remote).is_ok());
+        assert!(validate(&McpServer {
+            umask: Some("077".to_owned()),
+            limits: Some(Default::default()),
+            ..remote
+        })
+        .unwrap_err()
+        .to_string()
+        .ends_with("umask, limits."));
+    }
+
+    #[test]
+    fn test_parse_umask() {
+        assert_eq!(parse_umask("022").unwrap(), 0o022);
+        assert_eq!(parse_umask("0o077").unwrap(), 0o077);
+        assert!(parse_umask("8").is_err());
+        assert!(parse_umask("01000").is_err());
+        assert!(parse_umask("").is_err());
+    }
+}
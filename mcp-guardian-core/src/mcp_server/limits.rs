@@ -0,0 +1,157 @@
+use std::process::{Command, ExitStatus};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+/// Resource limits an MCP server process is launched with, as rlimits. Unix only.
+///
+/// Limits are inherited by the processes the server starts, but each of them is limited on its own.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct ResourceLimits {
+    /// Maximum size of the server's virtual memory in MiB. Runtimes that reserve large amounts of
+    /// virtual memory up front, such as Node.js, need a generous limit.
+    #[serde(default)]
+    #[ts(optional = nullable)]
+    pub max_address_space_mb: Option<u32>,
+    /// Maximum CPU time of the server in seconds
+    #[serde(default)]
+    #[ts(optional = nullable)]
+    pub max_cpu_seconds: Option<u32>,
+    /// Maximum number of files the server may have open at once
+    #[serde(default)]
+    #[ts(optional = nullable)]
+    pub max_open_files: Option<u32>,
+    /// Maximum number of processes the server's user may run, including those it already runs
+    /// outside of the server
+    #[serde(default)]
+    #[ts(optional = nullable)]
+    pub max_processes: Option<u32>,
+}
+
+impl ResourceLimits {
+    /// Describes the limit an MCP server process was killed for exceeding, judging by the signal
+    /// it was killed with, if any.
+    ///
+    /// Running out of address space makes allocations fail, which most runtimes answer by
+    /// aborting, so the limit is only the likely cause of such an exit. Running out of files or
+    /// processes makes calls fail without killing the process, so those limits are never named.
+    #[cfg(unix)]
+    pub fn exceeded_limit(&self, status: &ExitStatus) -> Option<String> {
+        use std::os::unix::process::ExitStatusExt;
+
+        use nix::sys::signal::Signal;
+
+        let signal = Signal::try_from(status.signal()?).ok()?;
+
+        match (signal, self.max_cpu_seconds, self.max_address_space_mb) {
+            (Signal::SIGXCPU, Some(seconds), _) => {
+                Some(format!("its CPU time limit of {seconds} s"))
+            }
+            (Signal::SIGABRT | Signal::SIGSEGV | Signal::SIGBUS, _, Some(mb)) => {
+                Some(format!("most likely its address space limit of {mb} MiB"))
+            }
+            _ => None,
+        }
+    }
+
+    #[cfg(not(unix))]
+    pub fn exceeded_limit(&self, _status: &ExitStatus) -> Option<String> {
+        None
+    }
+}
+
+/// Launches the command with `limits` as both its soft and hard rlimits, so that the MCP server
+/// can't raise them.
+///
+/// The hard CPU time limit is a second above the soft one, so that exceeding it kills the server
+/// with SIGXCPU, rather than SIGKILL, and the cause of its exit can be told.
+#[cfg(unix)]
+pub fn apply_limits(command: &mut Command, limits: &ResourceLimits) -> Result<()> {
+    use std::os::unix::process::CommandExt;
+
+    use nix::sys::resource::{rlim_t, setrlimit, Resource};
+
+    let mut rlimits = Vec::new();
+    if let Some(mb) = limits.max_address_space_mb {
+        let bytes = rlim_t::from(mb) * 1024 * 1024;
+        rlimits.push((Resource::RLIMIT_AS, bytes, bytes));
+    }
+    if let Some(seconds) = limits.max_cpu_seconds {
+        let seconds = rlim_t::from(seconds);
+        rlimits.push((Resource::RLIMIT_CPU, seconds, seconds + 1));
+    }
+    if let Some(files) = limits.max_open_files {
+        rlimits.push((Resource::RLIMIT_NOFILE, files.into(), files.into()));
+    }
+    if let Some(processes) = limits.max_processes {
+        rlimits.push((Resource::RLIMIT_NPROC, processes.into(), processes.into()));
+    }
+
+    log::info!("Limiting the MCP server's resources to {limits:?}.");
+
+    // SAFETY: the limits are collected above, so that only syscalls are made between fork and
+    // exec.
+    unsafe {
+        command.pre_exec(move || {
+            for &(resource, soft_limit, hard_limit) in &rlimits {
+                setrlimit(resource, soft_limit, hard_limit)?;
+            }
+            Ok(())
+        });
+    }
+
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn apply_limits(_command: &mut Command, _limits: &ResourceLimits) -> Result<()> {
+    log::warn!("Ignoring resource limits, which are only supported on unix.");
+
+    Ok(())
+}
+
+#[cfg(all(test, unix))]
+mod test {
+    use std::process::Stdio;
+
+    use super::*;
+
+    #[test]
+    fn test_apply_limits() {
+        let limits = ResourceLimits {
+            max_address_space_mb: Some(1024),
+            max_open_files: Some(64),
+            ..Default::default()
+        };
+
+        let mut command = Command::new("sh");
+        command
+            .args(["-c", "ulimit -v; ulimit -n"])
+            .stdout(Stdio::piped());
+        apply_limits(&mut command, &limits).unwrap();
+        let output = command.output().unwrap();
+
+        assert_eq!(String::from_utf8(output.stdout).unwrap(), "1048576\n64\n");
+    }
+
+    #[test]
+    fn test_exceeded_limit() {
+        let limits = ResourceLimits {
+            max_cpu_seconds: Some(1),
+            ..Default::default()
+        };
+
+        let mut command = Command::new("sh");
+        command.args(["-c", "while :; do :; done"]);
+        apply_limits(&mut command, &limits).unwrap();
+        let status = command.status().unwrap();
+
+        assert_eq!(
+            limits.exceeded_limit(&status).as_deref(),
+            Some("its CPU time limit of 1 s")
+        );
+        assert_eq!(ResourceLimits::default().exceeded_limit(&status), None);
+    }
+}
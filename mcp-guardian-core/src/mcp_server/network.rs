@@ -0,0 +1,211 @@
+use std::process::Command;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+/// Network an MCP server process may access, from a network namespace of its own.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, TS)]
+#[serde(rename_all = "snake_case")]
+#[ts(export)]
+pub enum NetworkIsolation {
+    /// No network at all, not even loopback
+    None,
+    /// A loopback interface of its own only, so the server can't reach services listening on the
+    /// host's loopback interface either
+    Loopback,
+}
+
+/// Launches the command in a network namespace of its own. Without the privileges to create one,
+/// it is created in an unprivileged user namespace, in which the server keeps its user and group
+/// ids.
+///
+/// Fails closed: if the namespace can't be set up, the command fails to spawn.
+#[cfg(target_os = "linux")]
+pub fn apply_network(command: &mut Command, network: NetworkIsolation) -> Result<()> {
+    use std::os::unix::process::CommandExt;
+
+    // SAFETY: getuid(2) and getgid(2) always succeed.
+    let (uid, gid) = unsafe { (libc::getuid(), libc::getgid()) };
+    let uid_map = format!("{uid} {uid} 1");
+    let gid_map = format!("{gid} {gid} 1");
+
+    log::info!("Isolating the MCP server's network ({network:?}) in a network namespace.");
+
+    // SAFETY: the id maps are formatted above, so that only syscalls are made between fork and
+    // exec.
+    unsafe {
+        command.pre_exec(move || linux::isolate(network, &uid_map, &gid_map));
+    }
+
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn apply_network(_command: &mut Command, _network: NetworkIsolation) -> Result<()> {
+    anyhow::bail!("Network isolation is only supported on Linux.");
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use std::{ffi::CStr, io};
+
+    use super::NetworkIsolation;
+
+    /// Moves the calling process into a network namespace of its own.
+    pub(super) fn isolate(
+        network: NetworkIsolation,
+        uid_map: &str,
+        gid_map: &str,
+    ) -> io::Result<()> {
+        unshare_network(uid_map, gid_map)?;
+        if network == NetworkIsolation::Loopback {
+            bring_up_loopback()?;
+        }
+        Ok(())
+    }
+
+    fn unshare_network(uid_map: &str, gid_map: &str) -> io::Result<()> {
+        // SAFETY: unshare(2) only affects the calling process.
+        if unsafe { libc::unshare(libc::CLONE_NEWNET) } == 0 {
+            return Ok(());
+        }
+        let error = io::Error::last_os_error();
+        if error.raw_os_error() != Some(libc::EPERM) {
+            return Err(error);
+        }
+
+        // SAFETY: as above. Only the forked child is affected, which has a single thread, as a
+        // user namespace requires.
+        if unsafe { libc::unshare(libc::CLONE_NEWUSER | libc::CLONE_NEWNET) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // setgroups must be denied before an unprivileged process may map its group id
+        write_file(c"/proc/self/setgroups", b"deny")?;
+        write_file(c"/proc/self/uid_map", uid_map.as_bytes())?;
+        write_file(c"/proc/self/gid_map", gid_map.as_bytes())?;
+
+        Ok(())
+    }
+
+    /// Brings up the loopback interface, which is down in a new network namespace.
+    fn bring_up_loopback() -> io::Result<()> {
+        // SAFETY: the socket is only used to configure the interface, and closed below.
+        let socket =
+            unsafe { libc::socket(libc::AF_INET, libc::SOCK_DGRAM | libc::SOCK_CLOEXEC, 0) };
+        if socket < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        // SAFETY: ifreq is plain old data, and the ioctls read and write its flags only.
+        let result = unsafe {
+            let mut ifreq: libc::ifreq = std::mem::zeroed();
+            for (dst, &src) in ifreq.ifr_name.iter_mut().zip(b"lo") {
+                *dst = src as libc::c_char;
+            }
+
+            if libc::ioctl(socket, libc::SIOCGIFFLAGS as _, &mut ifreq) < 0 {
+                Err(io::Error::last_os_error())
+            } else {
+                ifreq.ifr_ifru.ifru_flags |= libc::IFF_UP as libc::c_short;
+                match libc::ioctl(socket, libc::SIOCSIFFLAGS as _, &ifreq) {
+                    0 => Ok(()),
+                    _ => Err(io::Error::last_os_error()),
+                }
+            }
+        };
+
+        // SAFETY: the socket was opened above.
+        unsafe { libc::close(socket) };
+
+        result
+    }
+
+    fn write_file(path: &CStr, contents: &[u8]) -> io::Result<()> {
+        // SAFETY: path is nul-terminated, and the file is closed below.
+        let fd = unsafe { libc::open(path.as_ptr(), libc::O_WRONLY | libc::O_CLOEXEC) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        // SAFETY: contents is valid for its length.
+        let written = unsafe { libc::write(fd, contents.as_ptr().cast(), contents.len()) };
+        let result = match written {
+            written if written == contents.len() as isize => Ok(()),
+            _ => Err(io::Error::last_os_error()),
+        };
+
+        // SAFETY: the file was opened above.
+        unsafe { libc::close(fd) };
+
+        result
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod test {
+    use std::net::{SocketAddr, TcpListener, TcpStream};
+
+    use super::*;
+
+    /// Exit code of the child when it may not create a user namespace
+    const UNSUPPORTED: i32 = 100;
+
+    /// Whether a child process can connect to a listener on the host's loopback interface, then
+    /// to one on the loopback interface it sees, or `None` if it may not create a user namespace.
+    fn connect(network: Option<NetworkIsolation>) -> Option<(bool, bool)> {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let host_addr = listener.local_addr().unwrap();
+        let loopback_addr = SocketAddr::from(([127, 0, 0, 1], 0));
+
+        // SAFETY: getuid(2) and getgid(2) always succeed.
+        let (uid, gid) = unsafe { (libc::getuid(), libc::getgid()) };
+        let (uid_map, gid_map) = (format!("{uid} {uid} 1"), format!("{gid} {gid} 1"));
+
+        // SAFETY: the child connects to socket addresses, which doesn't allocate, so that it only
+        // makes syscalls, and exits without unwinding.
+        unsafe {
+            let pid = libc::fork();
+            assert!(pid >= 0);
+
+            if pid == 0 {
+                if let Some(network) = network {
+                    if let Err(e) = linux::isolate(network, &uid_map, &gid_map) {
+                        let unsupported = e.raw_os_error() == Some(libc::EPERM);
+                        libc::_exit(if unsupported { UNSUPPORTED } else { 101 });
+                    }
+                }
+
+                let host = TcpStream::connect(host_addr).is_ok();
+                let loopback = TcpListener::bind(loopback_addr)
+                    .and_then(|listener| TcpStream::connect(listener.local_addr()?))
+                    .is_ok();
+                libc::_exit(host as i32 | (loopback as i32) << 1);
+            }
+
+            let mut status = 0;
+            assert_eq!(libc::waitpid(pid, &mut status, 0), pid);
+            assert!(libc::WIFEXITED(status));
+
+            match libc::WEXITSTATUS(status) {
+                UNSUPPORTED => None,
+                code => {
+                    assert!(code < 4, "network isolation failed");
+                    Some((code & 1 != 0, code & 2 != 0))
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_network_isolation() {
+        assert_eq!(connect(None), Some((true, true)));
+
+        let Some(loopback) = connect(Some(NetworkIsolation::Loopback)) else {
+            eprintln!("Skipping, as user namespaces may not be created.");
+            return;
+        };
+        assert_eq!(loopback, (false, true));
+        assert_eq!(connect(Some(NetworkIsolation::None)), Some((false, false)));
+    }
+}
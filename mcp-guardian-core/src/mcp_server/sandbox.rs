@@ -0,0 +1,453 @@
+use std::{path::Path, process::Command};
+
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+/// Restrictions an MCP server process is launched under, on Linux. The server may only access the
+/// listed paths (with Landlock), may not make syscalls that an MCP server has no business making
+/// (with seccomp), and may not gain privileges (with `no_new_privs`).
+///
+/// The listed paths must cover everything the server needs to run, including its program, shared
+/// libraries and interpreter, e.g. `/usr`, `/lib` and `/etc`.
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct SandboxConfig {
+    /// Absolute paths the server may read and execute, along with everything beneath them
+    #[serde(default)]
+    pub read_paths: Vec<String>,
+    /// Absolute paths the server may read, execute and write, along with everything beneath them
+    #[serde(default)]
+    pub write_paths: Vec<String>,
+    /// Whether syscalls such as `ptrace` and `mount` are denied. Enabled unless set to false.
+    #[serde(default = "SandboxConfig::default_seccomp")]
+    pub seccomp: bool,
+}
+
+impl SandboxConfig {
+    fn default_seccomp() -> bool {
+        true
+    }
+}
+
+impl Default for SandboxConfig {
+    fn default() -> Self {
+        Self {
+            read_paths: Vec::new(),
+            write_paths: Vec::new(),
+            seccomp: Self::default_seccomp(),
+        }
+    }
+}
+
+pub fn validate(sandbox: &SandboxConfig) -> Result<()> {
+    for path in sandbox.read_paths.iter().chain(&sandbox.write_paths) {
+        if !Path::new(path).is_absolute() {
+            bail!("Sandbox path '{path}' is not an absolute path.");
+        }
+    }
+
+    Ok(())
+}
+
+/// Launches the command under the restrictions of `sandbox`. Restrictions the kernel doesn't
+/// support are logged and skipped, rather than failing the launch.
+#[cfg(target_os = "linux")]
+pub fn apply_sandbox(command: &mut Command, sandbox: &SandboxConfig) -> Result<()> {
+    use std::os::unix::process::CommandExt;
+
+    let ruleset = linux::landlock_ruleset(sandbox)?;
+    let filters = match sandbox.seccomp {
+        true => linux::seccomp_filters()?,
+        false => Vec::new(),
+    };
+
+    // SAFETY: everything that allocates is prepared above, so that only syscalls are made between
+    // fork and exec. Errors are read from errno, which the failing syscall has just set.
+    unsafe {
+        command.pre_exec(move || {
+            if libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) != 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+            // the command is spawned again on restarts, so each child restricts itself with its
+            // own handle on the ruleset
+            if let Some(ruleset) = &ruleset {
+                if ruleset.try_clone()?.restrict_self().is_err() {
+                    return Err(std::io::Error::last_os_error());
+                }
+            }
+            for filter in &filters {
+                if seccompiler::apply_filter(filter).is_err() {
+                    return Err(std::io::Error::last_os_error());
+                }
+            }
+            Ok(())
+        });
+    }
+
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn apply_sandbox(_command: &mut Command, _sandbox: &SandboxConfig) -> Result<()> {
+    log::warn!(
+        "Ignoring sandbox, which is only supported on Linux. The MCP server is not sandboxed."
+    );
+
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use std::collections::BTreeMap;
+
+    use anyhow::{Context, Result};
+    use landlock::{
+        Access, AccessFs, PathBeneath, PathFd, Ruleset, RulesetAttr, RulesetCreated,
+        RulesetCreatedAttr, ABI,
+    };
+    use seccompiler::{
+        BpfProgram, SeccompAction, SeccompCmpArgLen, SeccompCmpOp, SeccompCondition, SeccompFilter,
+        SeccompRule, TargetArch,
+    };
+
+    use super::SandboxConfig;
+
+    /// Landlock ABI whose access rights are restricted. ABI 3 is the first that restricts
+    /// truncating files.
+    const LANDLOCK_ABI: ABI = ABI::V3;
+
+    /// Syscalls that let a process escape its sandbox, tamper with other processes or the kernel,
+    /// or that a process without privileges has no use for.
+    const DENIED_SYSCALLS: &[libc::c_long] = &[
+        libc::SYS_ptrace,
+        libc::SYS_process_vm_readv,
+        libc::SYS_process_vm_writev,
+        libc::SYS_mount,
+        libc::SYS_umount2,
+        libc::SYS_pivot_root,
+        libc::SYS_chroot,
+        libc::SYS_unshare,
+        libc::SYS_setns,
+        libc::SYS_open_by_handle_at,
+        libc::SYS_init_module,
+        libc::SYS_finit_module,
+        libc::SYS_delete_module,
+        libc::SYS_kexec_load,
+        libc::SYS_kexec_file_load,
+        libc::SYS_bpf,
+        libc::SYS_perf_event_open,
+        libc::SYS_userfaultfd,
+        libc::SYS_keyctl,
+        libc::SYS_add_key,
+        libc::SYS_request_key,
+        libc::SYS_swapon,
+        libc::SYS_swapoff,
+        libc::SYS_reboot,
+        libc::SYS_acct,
+        libc::SYS_io_uring_setup,
+        libc::SYS_io_uring_enter,
+        libc::SYS_io_uring_register,
+    ];
+
+    /// Flags of `clone` that create namespaces, denied as `unshare` is.
+    const NAMESPACE_CLONE_FLAGS: &[libc::c_int] = &[
+        libc::CLONE_NEWUSER,
+        libc::CLONE_NEWNS,
+        libc::CLONE_NEWNET,
+        libc::CLONE_NEWPID,
+        libc::CLONE_NEWIPC,
+        libc::CLONE_NEWUTS,
+        libc::CLONE_NEWCGROUP,
+    ];
+
+    /// Bit set in the numbers of x32 syscalls, which are made with the same audit arch as x86_64
+    /// syscalls.
+    #[cfg(target_arch = "x86_64")]
+    const X32_SYSCALL_BIT: u32 = 0x4000_0000;
+
+    /// Landlock ABI supported by the running kernel, or `None` if it doesn't support Landlock.
+    pub(super) fn landlock_abi() -> Option<ABI> {
+        const LANDLOCK_CREATE_RULESET_VERSION: libc::c_uint = 1;
+
+        // SAFETY: asking for the ABI version takes no ruleset attributes.
+        let version = unsafe {
+            libc::syscall(
+                libc::SYS_landlock_create_ruleset,
+                std::ptr::null::<libc::c_void>(),
+                0usize,
+                LANDLOCK_CREATE_RULESET_VERSION,
+            )
+        };
+
+        match version {
+            version if version > 0 => Some(ABI::from(version as i32)),
+            _ => None,
+        }
+    }
+
+    /// Ruleset granting access to the paths of `sandbox` only, or `None` if the kernel doesn't
+    /// support Landlock.
+    pub(super) fn landlock_ruleset(sandbox: &SandboxConfig) -> Result<Option<RulesetCreated>> {
+        match landlock_abi() {
+            None => {
+                log::warn!(
+                    "The kernel does not support Landlock. The MCP server's filesystem access is \
+                     not restricted."
+                );
+                return Ok(None);
+            }
+            Some(abi) if abi < LANDLOCK_ABI => log::warn!(
+                "The kernel only supports Landlock ABI {abi}, older than ABI {LANDLOCK_ABI}. The \
+                 MCP server's filesystem access is only partially restricted, e.g. it may truncate \
+                 any file."
+            ),
+            Some(abi) => log::info!(
+                "Restricting the MCP server's filesystem access with Landlock (kernel ABI {abi})."
+            ),
+        }
+
+        let mut ruleset = Ruleset::default()
+            .handle_access(AccessFs::from_all(LANDLOCK_ABI))?
+            .create()?;
+
+        let rules = [
+            (&sandbox.read_paths, AccessFs::from_read(LANDLOCK_ABI)),
+            (&sandbox.write_paths, AccessFs::from_all(LANDLOCK_ABI)),
+        ];
+        for (paths, access) in rules {
+            for path in paths {
+                let fd = match PathFd::new(path) {
+                    Ok(fd) => fd,
+                    Err(e) => {
+                        log::warn!("Skipping sandbox path '{path}', which can't be opened: {e}");
+                        continue;
+                    }
+                };
+                // access rights to directory entries are only valid for directories
+                let access = match std::fs::metadata(path) {
+                    Ok(metadata) if metadata.is_dir() => access,
+                    _ => access & AccessFs::from_file(LANDLOCK_ABI),
+                };
+                ruleset = ruleset
+                    .add_rule(PathBeneath::new(fd, access))
+                    .with_context(|| format!("Failed to add sandbox path '{path}'."))?;
+            }
+        }
+
+        Ok(Some(ruleset))
+    }
+
+    /// Filters denying [`DENIED_SYSCALLS`] and `clone` with any of [`NAMESPACE_CLONE_FLAGS`]
+    /// with `EPERM`, and `clone3`, whose flags can't be filtered, with `ENOSYS` so that callers fall
+    /// back to `clone`. Empty if the kernel or architecture doesn't support seccomp.
+    pub(super) fn seccomp_filters() -> Result<Vec<BpfProgram>> {
+        // SAFETY: PR_GET_SECCOMP takes no arguments.
+        if unsafe { libc::prctl(libc::PR_GET_SECCOMP, 0, 0, 0, 0) } < 0 {
+            log::warn!(
+                "The kernel does not support seccomp. The MCP server's syscalls are not filtered."
+            );
+            return Ok(Vec::new());
+        }
+
+        let arch = match TargetArch::try_from(std::env::consts::ARCH) {
+            Ok(arch) => arch,
+            Err(_) => {
+                log::warn!(
+                    "Seccomp filters are not supported on {}. The MCP server's syscalls are not \
+                     filtered.",
+                    std::env::consts::ARCH
+                );
+                return Ok(Vec::new());
+            }
+        };
+
+        // syscall numbers are a c_long, which is only an i64 on 64-bit targets
+        #[allow(clippy::useless_conversion)]
+        let mut rules = DENIED_SYSCALLS
+            .iter()
+            .map(|&syscall| (i64::from(syscall), vec![]))
+            .collect::<BTreeMap<_, _>>();
+        // the flags are the first argument of `clone` on every architecture seccompiler supports
+        let clone_rules = NAMESPACE_CLONE_FLAGS
+            .iter()
+            .map(|&flag| {
+                let flag = flag as u64;
+                SeccompRule::new(vec![SeccompCondition::new(
+                    0,
+                    SeccompCmpArgLen::Qword,
+                    SeccompCmpOp::MaskedEq(flag),
+                    flag,
+                )?])
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        #[allow(clippy::useless_conversion)]
+        rules.insert(i64::from(libc::SYS_clone), clone_rules);
+
+        let denied = SeccompFilter::new(
+            rules,
+            SeccompAction::Allow,
+            SeccompAction::Errno(libc::EPERM as u32),
+            arch,
+        )?;
+        #[allow(clippy::useless_conversion)]
+        let clone3 = SeccompFilter::new(
+            [(i64::from(libc::SYS_clone3), vec![])].into(),
+            SeccompAction::Allow,
+            SeccompAction::Errno(libc::ENOSYS as u32),
+            arch,
+        )?;
+
+        #[allow(unused_mut)]
+        let mut filters = vec![denied.try_into()?, clone3.try_into()?];
+        #[cfg(target_arch = "x86_64")]
+        filters.push(x32_filter());
+
+        log::info!("Filtering the MCP server's syscalls with seccomp.");
+
+        Ok(filters)
+    }
+
+    /// Filter denying x32 syscalls with `ENOSYS`, as though the kernel didn't support them. They
+    /// would otherwise slip past the other filters, which match syscall numbers without the x32
+    /// bit.
+    #[cfg(target_arch = "x86_64")]
+    fn x32_filter() -> BpfProgram {
+        use seccompiler::sock_filter;
+
+        let instruction = |code: u32, jt: u8, jf: u8, k: u32| sock_filter {
+            code: code as u16,
+            jt,
+            jf,
+            k,
+        };
+
+        vec![
+            // the syscall number, first in `struct seccomp_data`
+            instruction(libc::BPF_LD | libc::BPF_W | libc::BPF_ABS, 0, 0, 0),
+            instruction(
+                libc::BPF_JMP | libc::BPF_JSET | libc::BPF_K,
+                0,
+                1,
+                X32_SYSCALL_BIT,
+            ),
+            instruction(
+                libc::BPF_RET | libc::BPF_K,
+                0,
+                0,
+                libc::SECCOMP_RET_ERRNO | libc::ENOSYS as u32,
+            ),
+            instruction(libc::BPF_RET | libc::BPF_K, 0, 0, libc::SECCOMP_RET_ALLOW),
+        ]
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod test {
+    use std::process::Stdio;
+
+    use super::*;
+
+    #[test]
+    fn test_sandbox() {
+        if linux::landlock_abi().is_none() {
+            eprintln!("Skipping, as the kernel does not support Landlock.");
+            return;
+        }
+
+        let dir = std::env::temp_dir().join(format!("sandbox-{}", uuid::Uuid::new_v4()));
+        let (readable, writable, hidden) = (
+            dir.join("readable"),
+            dir.join("writable"),
+            dir.join("hidden"),
+        );
+        for dir in [&readable, &writable, &hidden] {
+            std::fs::create_dir_all(dir).unwrap();
+            std::fs::write(dir.join("file"), "contents").unwrap();
+        }
+
+        let mut read_paths = ["/bin", "/lib", "/lib64", "/usr", "/etc"]
+            .map(String::from)
+            .to_vec();
+        read_paths.push(readable.to_string_lossy().into_owned());
+        let sandbox = SandboxConfig {
+            read_paths,
+            write_paths: vec![
+                writable.to_string_lossy().into_owned(),
+                "/dev/null".to_owned(),
+            ],
+            seccomp: true,
+        };
+
+        let script = r#"
+            cat readable/file >/dev/null 2>&1 && echo read readable
+            cat hidden/file >/dev/null 2>&1 && echo read hidden
+            echo x 2>/dev/null >writable/file && echo wrote writable
+            echo x 2>/dev/null >readable/file && echo wrote readable
+            unshare -U true 2>/dev/null && echo unshared
+        "#;
+        let mut command = Command::new("/bin/sh");
+        command
+            .args(["-c", script])
+            .current_dir(&dir)
+            .stdout(Stdio::piped());
+        apply_sandbox(&mut command, &sandbox).unwrap();
+        let output = command.output().unwrap();
+
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(
+            String::from_utf8(output.stdout).unwrap(),
+            "read readable\nwrote writable\n"
+        );
+    }
+
+    #[test]
+    fn test_seccomp() {
+        let filters = linux::seccomp_filters().unwrap();
+        if filters.is_empty() {
+            eprintln!("Skipping, as seccomp is not supported.");
+            return;
+        }
+
+        // SAFETY: the child only makes syscalls, which can't deadlock on state held by the
+        // parent's other threads, and exits without unwinding.
+        unsafe {
+            let pid = libc::fork();
+            assert!(pid >= 0);
+
+            if pid == 0 {
+                for filter in &filters {
+                    if seccompiler::apply_filter(filter).is_err() {
+                        libc::_exit(100);
+                    }
+                }
+
+                let errno = |ret: libc::c_long| match ret {
+                    -1 => std::io::Error::last_os_error()
+                        .raw_os_error()
+                        .unwrap_or_default(),
+                    // child of a clone that should have been denied
+                    0 => libc::_exit(0),
+                    _ => 0,
+                };
+                let namespaces = (libc::CLONE_NEWUSER | libc::SIGCHLD) as libc::c_ulong;
+
+                let results = [
+                    errno(libc::syscall(libc::SYS_clone, namespaces, 0, 0, 0, 0)) == libc::EPERM,
+                    errno(libc::syscall(libc::SYS_clone3, 0, 0)) == libc::ENOSYS,
+                    errno(libc::syscall(libc::SYS_io_uring_setup, 1, 0)) == libc::EPERM,
+                    !cfg!(target_arch = "x86_64")
+                        || errno(libc::syscall(libc::SYS_getpid | 0x4000_0000)) == libc::ENOSYS,
+                ];
+                let failed = results.iter().position(|passed| !passed);
+                libc::_exit(failed.map_or(0, |index| index as i32 + 1));
+            }
+
+            let mut status = 0;
+            assert_eq!(libc::waitpid(pid, &mut status, 0), pid);
+            assert!(libc::WIFEXITED(status));
+            assert_eq!(libc::WEXITSTATUS(status), 0);
+        }
+    }
+}
@@ -57,6 +57,15 @@ impl Message {
         }
     }
 
+    /// The messages making up a raw json-rpc message: the elements of a batch, or else the message
+    /// itself.
+    pub fn unbatch(msg: &Value) -> &[Value] {
+        match msg {
+            Value::Array(elements) => elements,
+            msg => std::slice::from_ref(msg),
+        }
+    }
+
     pub fn log_prefix(&self) -> String {
         match self.type_ {
             MessageType::Request => "Request",
@@ -1,10 +1,22 @@
-use std::sync::Arc;
+use std::{
+    fs::OpenOptions,
+    io::{ErrorKind, Write},
+    sync::Arc,
+    time::SystemTime,
+};
 
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, bail, Result};
+use humantime::format_rfc3339_millis;
+use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
+use tokio::io::AsyncWriteExt;
+use ts_rs::TS;
 
 use crate::{
-    dirs::AppSubDir::{MessageApprovalsApproved, MessageApprovalsDenied, MessageApprovalsPending},
+    dirs::AppSubDir::{
+        MessageApprovalsApproved, MessageApprovalsDenied, MessageApprovalsHistory,
+        MessageApprovalsPending,
+    },
     message::MessageDirection,
 };
 
@@ -20,6 +32,89 @@ pub enum MessageStatus {
     Unknown,
 }
 
+/// What becomes of a message whose approval times out.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize, TS)]
+#[serde(rename_all = "snake_case")]
+#[ts(export)]
+pub enum ApprovalTimeoutAction {
+    #[default]
+    Deny,
+    Approve,
+    Drop,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[serde(tag = "type", rename_all = "snake_case")]
+#[ts(export)]
+pub enum ApprovalEvent {
+    Requested,
+    Approved,
+    Denied,
+    /// No one approved or denied the message in time
+    TimedOut {
+        action: ApprovalTimeoutAction,
+    },
+    /// The approval was no longer needed, e.g. because the message was cancelled
+    Withdrawn,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct ApprovalHistoryEntry {
+    /// RFC 3339 timestamp
+    pub timestamp: String,
+    pub event: ApprovalEvent,
+}
+
+/// Checks that the id of an approval, the name of its files, can't name a file outside the
+/// approval directories.
+fn check_id(id: &str) -> Result<()> {
+    if id.is_empty() || id.contains("..") || id.contains(['/', '\\', '\0']) {
+        bail!("Invalid message approval id: {id:?}");
+    }
+
+    Ok(())
+}
+
+/// Line of the history of an approval recording `event`, as of now.
+fn history_line(event: ApprovalEvent) -> Result<String> {
+    let entry = ApprovalHistoryEntry {
+        timestamp: format_rfc3339_millis(SystemTime::now()).to_string(),
+        event,
+    };
+
+    Ok(format!("{}\n", serde_json::to_string(&entry)?))
+}
+
+/// Appends an event to the history of an approval, kept under the same file name as the approval
+/// itself.
+async fn record_approval_event(filename: &str, event: ApprovalEvent) -> Result<()> {
+    let line = history_line(event)?;
+
+    let mut file = tokio::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(MessageApprovalsHistory.path()?.join(filename))
+        .await?;
+    file.write_all(line.as_bytes()).await?;
+    file.flush().await?;
+
+    Ok(())
+}
+
+/// Blocking variant of [`record_approval_event`], for [`withdraw_approval`].
+fn record_approval_event_blocking(filename: &str, event: ApprovalEvent) -> Result<()> {
+    let line = history_line(event)?;
+
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(MessageApprovalsHistory.path()?.join(filename))?
+        .write_all(line.as_bytes())?;
+
+    Ok(())
+}
+
 pub async fn request_approval(
     id: &str,
     direction: MessageDirection,
@@ -33,11 +128,13 @@ pub async fn request_approval(
     let approved_path = MessageApprovalsApproved.path()?.join(&filename);
     let denied_path = MessageApprovalsDenied.path()?.join(&filename);
 
-    tokio::fs::write(
-        MessageApprovalsPending.path()?.join(&filename),
-        message.to_string(),
-    )
-    .await?;
+    tokio::fs::write(&pending_path, message.to_string()).await?;
+
+    // a pending approval absent from the history would never be resolved in it
+    if let Err(e) = record_approval_event(&filename, ApprovalEvent::Requested).await {
+        let _ = tokio::fs::remove_file(&pending_path).await;
+        return Err(e);
+    }
 
     let poll_status_callback = Arc::new(move || {
         if pending_path.exists() {
@@ -58,6 +155,76 @@ pub async fn request_approval(
     Ok(poll_status_callback)
 }
 
+/// Withdraws a request for approval that's no longer needed, e.g. because the message was
+/// cancelled, if it's still pending.
+///
+/// Blocking, so that it can be done while dropping the interception awaiting the approval.
+pub fn withdraw_approval(id: &str, direction: MessageDirection) -> Result<()> {
+    let filename = format!("{direction}_{id}");
+
+    match std::fs::remove_file(MessageApprovalsPending.path()?.join(&filename)) {
+        Ok(()) => {
+            log::info!("Withdrew approval request for message '{id}'");
+            record_approval_event_blocking(&filename, ApprovalEvent::Withdrawn)
+        }
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Resolves a request for approval that timed out with `action`, if it's still pending.
+///
+/// Returns whether it was still pending, as it may have been approved or denied in the meantime.
+pub async fn expire_approval(
+    id: &str,
+    direction: MessageDirection,
+    action: ApprovalTimeoutAction,
+) -> Result<bool> {
+    let filename = format!("{direction}_{id}");
+    let pending_path = MessageApprovalsPending.path()?.join(&filename);
+
+    let res = match action {
+        ApprovalTimeoutAction::Approve => {
+            tokio::fs::rename(
+                &pending_path,
+                MessageApprovalsApproved.path()?.join(&filename),
+            )
+            .await
+        }
+        ApprovalTimeoutAction::Deny => {
+            tokio::fs::rename(
+                &pending_path,
+                MessageApprovalsDenied.path()?.join(&filename),
+            )
+            .await
+        }
+        ApprovalTimeoutAction::Drop => tokio::fs::remove_file(&pending_path).await,
+    };
+
+    match res {
+        Ok(()) => {
+            log::warn!("Approval of message '{id}' timed out");
+            record_approval_event(&filename, ApprovalEvent::TimedOut { action }).await?;
+            Ok(true)
+        }
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Reads the history of an approval, oldest event first.
+pub async fn get_approval_history(id: String) -> Result<Vec<ApprovalHistoryEntry>> {
+    log::info!("Getting history of message '{id}'");
+    check_id(&id)?;
+
+    let history = tokio::fs::read_to_string(MessageApprovalsHistory.path()?.join(&id)).await?;
+
+    history
+        .lines()
+        .map(|line| Ok(serde_json::from_str(line)?))
+        .collect()
+}
+
 pub async fn get_pending_messages() -> Result<Value> {
     log::info!("Getting pending messages");
     let mut pending = json!({});
@@ -82,6 +249,7 @@ pub async fn get_pending_messages() -> Result<Value> {
 
 pub async fn approve_message(id: String) -> Result<()> {
     log::info!("Approving message '{id}'");
+    check_id(&id)?;
     let pending_file_path = MessageApprovalsPending.path()?.join(&id);
     let approved_file_path = MessageApprovalsApproved.path()?.join(&id);
 
@@ -91,6 +259,7 @@ pub async fn approve_message(id: String) -> Result<()> {
     }
 
     tokio::fs::rename(&pending_file_path, &approved_file_path).await?;
+    record_approval_event(&id, ApprovalEvent::Approved).await?;
     log::info!("Message '{id}' approved");
 
     Ok(())
@@ -98,6 +267,7 @@ pub async fn approve_message(id: String) -> Result<()> {
 
 pub async fn deny_message(id: String) -> Result<()> {
     log::info!("Denying message '{id}'");
+    check_id(&id)?;
     let pending_file_path = MessageApprovalsPending.path()?.join(&id);
     let denied_file_path = MessageApprovalsDenied.path()?.join(&id);
 
@@ -107,7 +277,22 @@ pub async fn deny_message(id: String) -> Result<()> {
     }
 
     tokio::fs::rename(&pending_file_path, &denied_file_path).await?;
+    record_approval_event(&id, ApprovalEvent::Denied).await?;
     log::info!("Message '{id}' denied");
 
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_check_id() {
+        assert!(check_id("outbound_server_outbound_0b9c9f5e-52d9-4b6e-9b43-3c0c1c3f6a7d").is_ok());
+
+        for id in ["", "..", "../../etc/passwd", "a/b", "a\\b", "a\0b"] {
+            assert!(check_id(id).is_err(), "{id:?}");
+        }
+    }
+}
@@ -3,13 +3,22 @@ pub mod filter;
 pub mod manual_approval;
 pub mod message_log;
 pub mod py_func;
+pub mod record;
+
+use std::{cell::RefCell, future::Future};
 
 use anyhow::Result;
 use async_trait::async_trait;
+use serde_json::Value;
+use tokio::{sync::oneshot, task};
 
-use crate::message::{
-    Message, MessageDirection,
-    MessageDirection::{Inbound, Outbound},
+use crate::{
+    message::{
+        Message, MessageDirection,
+        MessageDirection::{Inbound, Outbound},
+        MessageType,
+    },
+    proxy::error_response,
 };
 
 #[derive(Clone)]
@@ -41,3 +50,225 @@ pub trait MessageInterceptor: Send + Sync {
         self.intercept_message(Inbound, message).await
     }
 }
+
+/// What becomes of a raw json-rpc message, which may be a batch, once intercepted.
+#[derive(Debug, Default, PartialEq)]
+pub struct InterceptedMessage {
+    /// Message to send on, if any
+    pub send: Option<Value>,
+    /// Message to return to the sender, if any
+    pub return_: Option<Value>,
+}
+
+/// Intercepts a raw json-rpc message.
+///
+/// Each element of a batch is intercepted on its own, so batching can't be used to get messages
+/// past the interceptor. The elements sent on and the elements returned are re-assembled into
+/// batches of their own.
+///
+/// A message that fails to be intercepted is dropped, and answered with an error if it's a
+/// request, so its sender isn't left waiting and the rest of a batch still goes through.
+pub async fn intercept_raw_message(
+    message_interceptor: &dyn MessageInterceptor,
+    direction: MessageDirection,
+    msg: Value,
+) -> InterceptedMessage {
+    let elements = match msg {
+        Value::Array(elements) if !elements.is_empty() => elements,
+        msg => return intercept_single_message(message_interceptor, direction, msg).await,
+    };
+
+    let mut send = Vec::new();
+    let mut return_ = Vec::new();
+
+    for element in elements {
+        let intercepted = intercept_single_message(message_interceptor, direction, element).await;
+        send.extend(intercepted.send);
+        return_.extend(intercepted.return_);
+    }
+
+    InterceptedMessage {
+        send: (!send.is_empty()).then_some(Value::Array(send)),
+        return_: (!return_.is_empty()).then_some(Value::Array(return_)),
+    }
+}
+
+async fn intercept_single_message(
+    message_interceptor: &dyn MessageInterceptor,
+    direction: MessageDirection,
+    msg: Value,
+) -> InterceptedMessage {
+    let message = Message::from_json(msg);
+    let request_id = match message.type_ {
+        MessageType::Request => message.raw_msg.get("id").cloned(),
+        _ => None,
+    };
+
+    match message_interceptor
+        .intercept_message(direction, message)
+        .await
+    {
+        Ok(MessageInterceptorAction::Send(message)) => InterceptedMessage {
+            send: Some(message.raw_msg),
+            return_: None,
+        },
+        Ok(MessageInterceptorAction::Drop) => InterceptedMessage::default(),
+        Ok(MessageInterceptorAction::Return(message)) => InterceptedMessage {
+            send: None,
+            return_: Some(message.raw_msg),
+        },
+        Err(e) => {
+            log::error!("Failed to intercept {direction} message properly: {e}");
+            InterceptedMessage {
+                send: None,
+                return_: request_id.map(|id| {
+                    error_response(id, -32603, "MCP Guardian failed to intercept the request.")
+                }),
+            }
+        }
+    }
+}
+
+tokio::task_local! {
+    static HOLD_TX: RefCell<Option<oneshot::Sender<()>>>;
+}
+
+/// Holds back the message currently being intercepted, e.g. while it awaits manual approval.
+///
+/// Messages are intercepted and forwarded one at a time, in the order they arrive. Once a message
+/// is held back, the messages after it no longer wait for it.
+pub fn hold_message() {
+    let _ = HOLD_TX.try_with(|hold_tx| {
+        if let Some(hold_tx) = hold_tx.borrow_mut().take() {
+            let _ = hold_tx.send(());
+        }
+    });
+}
+
+tokio::task_local! {
+    static ANSWERED_REQUEST_METHOD: Option<String>;
+}
+
+/// Runs the interception of a response with the method of the request it answers, as looked up by
+/// an interceptor that cached the request, at hand for the interceptors it delegates to.
+pub(crate) async fn with_answered_request_method<F: Future>(
+    method: Option<String>,
+    intercept: F,
+) -> F::Output {
+    ANSWERED_REQUEST_METHOD.scope(method, intercept).await
+}
+
+/// Method of the request answered by the response being intercepted, if an enclosing interceptor
+/// looked it up.
+pub(crate) fn answered_request_method() -> Option<String> {
+    ANSWERED_REQUEST_METHOD
+        .try_with(|method| method.clone())
+        .ok()
+        .flatten()
+}
+
+/// Runs the interception of a message, returning once it completes or the message is held back
+/// with [`hold_message`].
+///
+/// Returns a handle for aborting the interception if the message was held back.
+pub(crate) async fn intercept_in_order<F>(intercept: F) -> Option<task::AbortHandle>
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    let (hold_tx, hold_rx) = oneshot::channel();
+
+    let task = task::spawn(HOLD_TX.scope(RefCell::new(Some(hold_tx)), intercept));
+
+    // Resolves once the message is held back, or once the interception completes and drops
+    // `hold_tx`.
+    match hold_rx.await {
+        Ok(()) => Some(task.abort_handle()),
+        Err(_) => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::{
+        MessageInterceptorAction::{Drop, Return, Send},
+        *,
+    };
+
+    /// Drops `tools/call` requests, answers `ping` requests itself, fails on `fail` messages and
+    /// sends everything else on.
+    struct TestInterceptor;
+
+    #[async_trait]
+    impl MessageInterceptor for TestInterceptor {
+        async fn intercept_message(
+            &self,
+            _direction: MessageDirection,
+            message: Message,
+        ) -> Result<MessageInterceptorAction> {
+            Ok(match message.raw_msg["method"].as_str() {
+                Some("fail") => anyhow::bail!("failed"),
+                Some("tools/call") => Drop,
+                Some("ping") => Return(Message::from_json(json!({
+                    "jsonrpc": "2.0",
+                    "id": message.raw_msg["id"],
+                    "result": {}
+                }))),
+                _ => Send(message),
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_intercept_batch() {
+        let batch = json!([
+            { "jsonrpc": "2.0", "id": 1, "method": "tools/call" },
+            { "jsonrpc": "2.0", "id": 2, "method": "ping" },
+            { "jsonrpc": "2.0", "id": 3, "method": "tools/list" },
+            { "jsonrpc": "2.0", "method": "notifications/initialized" },
+        ]);
+
+        let intercepted = intercept_raw_message(&TestInterceptor, Outbound, batch).await;
+
+        assert_eq!(
+            intercepted,
+            InterceptedMessage {
+                send: Some(json!([
+                    { "jsonrpc": "2.0", "id": 3, "method": "tools/list" },
+                    { "jsonrpc": "2.0", "method": "notifications/initialized" },
+                ])),
+                return_: Some(json!([{ "jsonrpc": "2.0", "id": 2, "result": {} }])),
+            }
+        );
+
+        // an element that fails doesn't take the rest of the batch with it
+        let batch = json!([
+            { "jsonrpc": "2.0", "id": 1, "method": "fail" },
+            { "jsonrpc": "2.0", "method": "fail" },
+            { "jsonrpc": "2.0", "id": 2, "method": "tools/list" },
+        ]);
+
+        let intercepted = intercept_raw_message(&TestInterceptor, Outbound, batch).await;
+
+        assert_eq!(
+            intercepted,
+            InterceptedMessage {
+                send: Some(json!([{ "jsonrpc": "2.0", "id": 2, "method": "tools/list" }])),
+                return_: Some(json!([{
+                    "jsonrpc": "2.0",
+                    "id": 1,
+                    "error": {
+                        "code": -32603,
+                        "message": "MCP Guardian failed to intercept the request."
+                    }
+                }])),
+            }
+        );
+
+        let batch = json!([{ "jsonrpc": "2.0", "id": 1, "method": "tools/call" }]);
+        let intercepted = intercept_raw_message(&TestInterceptor, Outbound, batch).await;
+
+        assert_eq!(intercepted, InterceptedMessage::default());
+    }
+}
@@ -1,23 +1,171 @@
+use std::future::Future;
+
 use anyhow::Result;
 use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
 use serde_json::json;
-use tokio::time::{sleep, Duration};
+use tokio::time::{sleep, Duration, Instant};
+use ts_rs::TS;
 use uuid::Uuid;
 use MessageInterceptorAction::{Return, Send};
 
 use crate::{
     message::{Message, MessageDirection, MessageType},
-    message_approval::{request_approval, MessageStatus},
-    message_interceptor::{MessageInterceptor, MessageInterceptorAction},
+    message_approval::{
+        expire_approval, request_approval, withdraw_approval, ApprovalTimeoutAction, MessageStatus,
+    },
+    message_interceptor::{
+        answered_request_method, hold_message, MessageInterceptor, MessageInterceptorAction,
+    },
 };
 
 pub struct ManualApprovalInterceptor {
     pub mcp_server_name: String,
+    /// How long to wait for approval, indefinitely if unset
+    pub timeout: Option<Duration>,
+    pub timeout_action: ApprovalTimeoutAction,
+    pub poll_interval: Duration,
+    pub denial_response: DenialResponse,
 }
 
 impl ManualApprovalInterceptor {
-    pub fn new(mcp_server_name: String) -> Self {
-        Self { mcp_server_name }
+    pub fn new(
+        mcp_server_name: String,
+        timeout: Option<Duration>,
+        timeout_action: ApprovalTimeoutAction,
+        poll_interval: Duration,
+        denial_response: DenialResponse,
+    ) -> Self {
+        Self {
+            mcp_server_name,
+            timeout,
+            timeout_action,
+            poll_interval,
+            denial_response,
+        }
+    }
+}
+
+/// Builds the response standing in for a message whose approval was denied.
+///
+/// A denied `tools/call` request, or response, is answered as `kind` says, by default with a tool
+/// result flagged as an error, so the model calling the tool is told why it failed. Other messages
+/// are answered with a json-rpc error, the only response valid whatever the method.
+pub struct DenialResponse {
+    pub message: String,
+    /// json-rpc error code
+    pub code: i32,
+    pub kind: DenialResponseKind,
+}
+
+/// How a denied `tools/call` request or response is answered.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize, TS)]
+#[serde(rename_all = "snake_case")]
+#[ts(export)]
+pub enum DenialResponseKind {
+    /// A tool result flagged as an error
+    #[default]
+    ToolError,
+    /// A json-rpc error, as for any other method
+    JsonRpcError,
+}
+
+/// Server-defined json-rpc error code for messages denied by policy. The message itself was
+/// valid, so none of the codes the spec reserves apply.
+pub(crate) const DENIED_ERROR_CODE: i32 = -32001;
+
+impl Default for DenialResponse {
+    fn default() -> Self {
+        Self {
+            message: "Access approval was denied.".to_owned(),
+            code: DENIED_ERROR_CODE,
+            kind: DenialResponseKind::default(),
+        }
+    }
+}
+
+impl DenialResponse {
+    /// Builds the response to `message`, denied for `reason`, if it can be answered at all.
+    /// Notifications can't.
+    ///
+    /// `method` is that of the request being answered: the message itself if it's a request, the
+    /// request it answers if it's a response, which is unknown to the response itself.
+    pub fn build(&self, message: &Message, method: Option<&str>, reason: &str) -> Option<Message> {
+        let Self {
+            message: text,
+            code,
+            kind,
+        } = self;
+
+        let id = match message.type_ {
+            MessageType::Request | MessageType::ResponseSuccess | MessageType::ResponseFailure => {
+                message.raw_msg.get("id")?
+            }
+            MessageType::Notification | MessageType::Unknown => return None,
+        };
+
+        let raw_msg = if *kind == DenialResponseKind::ToolError && method == Some("tools/call") {
+            json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "result": {
+                    "content": [{ "type": "text", "text": text }],
+                    "isError": true
+                }
+            })
+        } else {
+            json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "error": {
+                    "code": code,
+                    "message": text,
+                    "data": { "reason": reason }
+                }
+            })
+        };
+
+        Some(Message::from_json(raw_msg))
+    }
+}
+
+/// Answer given to requests for approval in place of a person.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ApprovalAnswer {
+    Approve,
+    Deny,
+}
+
+tokio::task_local! {
+    static SCRIPTED_APPROVAL: Option<ApprovalAnswer>;
+}
+
+/// Runs `intercept` with requests for approval answered with `answer` at once, rather than by a
+/// person, e.g. when replaying a recorded session. Requests are left unanswered if `answer` is
+/// `None`, and time out at once.
+pub async fn with_scripted_approval<F: Future>(
+    answer: Option<ApprovalAnswer>,
+    intercept: F,
+) -> F::Output {
+    SCRIPTED_APPROVAL.scope(answer, intercept).await
+}
+
+/// Withdraws a request for approval when dropped before it's resolved, as happens when the request
+/// being approved is cancelled.
+struct PendingApproval {
+    id: String,
+    direction: MessageDirection,
+    resolved: bool,
+}
+
+impl Drop for PendingApproval {
+    fn drop(&mut self) {
+        if !self.resolved {
+            if let Err(e) = withdraw_approval(&self.id, self.direction) {
+                log::error!("Failed to withdraw approval request '{}': {e}", self.id);
+            }
+        }
     }
 }
 
@@ -28,48 +176,236 @@ impl MessageInterceptor for ManualApprovalInterceptor {
         direction: MessageDirection,
         message: Message,
     ) -> Result<MessageInterceptorAction> {
-        let Self { mcp_server_name } = self;
+        let Self {
+            mcp_server_name,
+            timeout,
+            timeout_action,
+            poll_interval,
+            denial_response,
+        } = self;
+
+        if let Ok(answer) = SCRIPTED_APPROVAL.try_with(|answer| *answer) {
+            return Ok(match answer {
+                Some(ApprovalAnswer::Approve) => Send(message),
+                Some(ApprovalAnswer::Deny) => deny(denial_response, message, "denied"),
+                None => time_out(*timeout_action, denial_response, message),
+            });
+        }
 
         let approval_id = format!("{mcp_server_name}_{direction}_{}", Uuid::new_v4());
 
         let check_approval =
             request_approval(&approval_id, direction, message.raw_msg.clone()).await?;
+        let mut pending_approval = PendingApproval {
+            id: approval_id,
+            direction,
+            resolved: false,
+        };
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+
+        // Let other traffic through while waiting on the approval.
+        hold_message();
 
         loop {
             match check_approval() {
                 MessageStatus::Pending => {
-                    sleep(Duration::from_millis(1000)).await;
+                    let Some(deadline) = deadline else {
+                        sleep(*poll_interval).await;
+                        continue;
+                    };
+
+                    if Instant::now() < deadline {
+                        sleep((*poll_interval).min(deadline - Instant::now())).await;
+                        continue;
+                    }
+
+                    // Checked again if the approval was resolved just as it timed out.
+                    if expire_approval(&pending_approval.id, direction, *timeout_action).await? {
+                        pending_approval.resolved = true;
+
+                        return Ok(time_out(*timeout_action, denial_response, message));
+                    }
                 }
                 MessageStatus::Approved => {
+                    pending_approval.resolved = true;
                     return Ok(Send(message));
                 }
                 MessageStatus::Denied | MessageStatus::Unknown => {
-                    let id = message
-                        .raw_msg
-                        .get("id")
-                        .ok_or_else(|| anyhow::anyhow!("Request message did not contain an ID"))?
-                        .to_owned();
-
-                    let return_message = Message {
-                        type_: MessageType::ResponseSuccess,
-                        raw_msg: json!({
-                            "id": id,
-                            "jsonrpc": "2.0",
-                            "result": {
-                                "content": [
-                                    {
-                                        "text": "Access approval was denied.",
-                                        "type": "text"
-                                    }
-                                ],
-                                "isError": false
-                            }
-                        }),
-                    };
-
-                    return Ok(Return(return_message));
+                    pending_approval.resolved = true;
+                    return Ok(deny(denial_response, message, "denied"));
                 }
             }
         }
     }
 }
+
+/// What becomes of a message whose approval timed out.
+fn time_out(
+    timeout_action: ApprovalTimeoutAction,
+    denial_response: &DenialResponse,
+    message: Message,
+) -> MessageInterceptorAction {
+    match timeout_action {
+        ApprovalTimeoutAction::Approve => Send(message),
+        ApprovalTimeoutAction::Deny => deny(denial_response, message, "timed_out"),
+        ApprovalTimeoutAction::Drop => MessageInterceptorAction::Drop,
+    }
+}
+
+/// What becomes of a denied message, according to its type.
+///
+/// A denied request is answered on the recipient's behalf, so its sender isn't left waiting, be it
+/// the host or, for requests such as sampling, the server. A denied response is replaced with an
+/// error for the same request, and a denied notification is dropped.
+fn deny(
+    denial_response: &DenialResponse,
+    message: Message,
+    reason: &str,
+) -> MessageInterceptorAction {
+    let method = match message.type_ {
+        MessageType::Request => message
+            .raw_msg
+            .get("method")
+            .and_then(|method| method.as_str())
+            .map(str::to_owned),
+        MessageType::ResponseSuccess | MessageType::ResponseFailure => answered_request_method(),
+        MessageType::Notification | MessageType::Unknown => None,
+    };
+
+    let Some(response) = denial_response.build(&message, method.as_deref(), reason) else {
+        return MessageInterceptorAction::Drop;
+    };
+
+    match message.type_ {
+        MessageType::Request => Return(response),
+        MessageType::ResponseSuccess | MessageType::ResponseFailure => Send(response),
+        MessageType::Notification | MessageType::Unknown => MessageInterceptorAction::Drop,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_denial_response() {
+        let denial_response = DenialResponse::default();
+
+        let tools_call = Message::from_json(json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "tools/call",
+            "params": { "name": "rm" }
+        }));
+        assert_eq!(
+            denial_response
+                .build(&tools_call, Some("tools/call"), "denied")
+                .unwrap()
+                .raw_msg,
+            json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "result": {
+                    "content": [{ "type": "text", "text": "Access approval was denied." }],
+                    "isError": true
+                }
+            })
+        );
+
+        let resources_read = Message::from_json(json!({
+            "jsonrpc": "2.0",
+            "id": 2,
+            "method": "resources/read",
+            "params": { "uri": "file:///etc/passwd" }
+        }));
+        let response = denial_response
+            .build(&resources_read, Some("resources/read"), "timed_out")
+            .unwrap();
+        assert_eq!(response.type_, MessageType::ResponseFailure);
+        assert_eq!(
+            response.raw_msg,
+            json!({
+                "jsonrpc": "2.0",
+                "id": 2,
+                "error": {
+                    "code": -32001,
+                    "message": "Access approval was denied.",
+                    "data": { "reason": "timed_out" }
+                }
+            })
+        );
+
+        let notification = Message::from_json(json!({
+            "jsonrpc": "2.0",
+            "method": "notifications/resources/updated"
+        }));
+        assert!(denial_response
+            .build(&notification, None, "denied")
+            .is_none());
+
+        // a tool call's result is replaced the same way as the call itself is answered
+        let tools_call_result = Message::from_json(json!({
+            "jsonrpc": "2.0",
+            "id": 3,
+            "result": { "content": [], "isError": false }
+        }));
+        let response = denial_response
+            .build(&tools_call_result, Some("tools/call"), "denied")
+            .unwrap();
+        assert_eq!(response.raw_msg["id"], json!(3));
+        assert_eq!(response.raw_msg["result"]["isError"], json!(true));
+        let response = denial_response
+            .build(&tools_call_result, None, "denied")
+            .unwrap();
+        assert_eq!(response.type_, MessageType::ResponseFailure);
+
+        let denial_response = DenialResponse {
+            kind: DenialResponseKind::JsonRpcError,
+            ..DenialResponse::default()
+        };
+        let response = denial_response
+            .build(&tools_call, Some("tools/call"), "denied")
+            .unwrap();
+        assert_eq!(response.type_, MessageType::ResponseFailure);
+        assert_eq!(response.raw_msg["error"]["code"], json!(-32001));
+    }
+
+    #[test]
+    fn test_deny() {
+        let denial_response = DenialResponse::default();
+
+        // sampling requested by the server is answered back to the server
+        let request = Message::from_json(json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "sampling/createMessage",
+            "params": {}
+        }));
+        let Return(response) = deny(&denial_response, request, "denied") else {
+            panic!("denied request wasn't answered");
+        };
+        assert_eq!(response.raw_msg["id"], json!(1));
+        assert_eq!(response.type_, MessageType::ResponseFailure);
+
+        let response = Message::from_json(json!({
+            "jsonrpc": "2.0",
+            "id": 2,
+            "result": { "contents": [] }
+        }));
+        let Send(response) = deny(&denial_response, response, "denied") else {
+            panic!("denied response wasn't replaced");
+        };
+        assert_eq!(response.raw_msg["id"], json!(2));
+        assert_eq!(response.type_, MessageType::ResponseFailure);
+
+        let notification = Message::from_json(json!({
+            "jsonrpc": "2.0",
+            "method": "notifications/progress",
+            "params": {}
+        }));
+        assert!(matches!(
+            deny(&denial_response, notification, "denied"),
+            MessageInterceptorAction::Drop
+        ));
+    }
+}
@@ -0,0 +1,295 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::Path,
+    sync::{Arc, Mutex, PoisonError},
+    time::SystemTime,
+};
+
+use anyhow::{Context as _, Result};
+use async_trait::async_trait;
+use humantime::format_rfc3339_millis;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    message::{Message, MessageDirection, MessageType},
+    message_interceptor::{MessageInterceptor, MessageInterceptorAction},
+};
+
+/// A message as recorded in a session transcript, along with what became of it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordedMessage {
+    /// RFC 3339 timestamp of when the message was intercepted
+    pub timestamp: String,
+    pub session_id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub host_session_id: Option<String>,
+    pub mcp_server_name: String,
+    pub direction: MessageDirection,
+    pub message_type: MessageType,
+    pub message: Value,
+    pub action: RecordedAction,
+}
+
+/// What the message interceptor did with a recorded message.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum RecordedAction {
+    /// Sent the message on, rewritten to `message` if given
+    Send {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        message: Option<Value>,
+    },
+    Drop,
+    /// Returned `message` to the sender instead
+    Return {
+        message: Value,
+    },
+}
+
+impl RecordedAction {
+    pub fn from_action(message: &Value, action: &MessageInterceptorAction) -> Self {
+        match action {
+            MessageInterceptorAction::Send(sent) => Self::Send {
+                message: (&sent.raw_msg != message).then(|| sent.raw_msg.clone()),
+            },
+            MessageInterceptorAction::Drop => Self::Drop,
+            MessageInterceptorAction::Return(returned) => Self::Return {
+                message: returned.raw_msg.clone(),
+            },
+        }
+    }
+}
+
+/// Appends recorded messages to a JSONL transcript, one message per line. Several proxied MCP
+/// servers may share a transcript.
+pub struct SessionRecorder {
+    file: Mutex<File>,
+}
+
+impl SessionRecorder {
+    pub fn create(path: &Path) -> Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Failed to open transcript '{}'", path.display()))?;
+
+        log::info!("Recording session to {}", path.display());
+
+        Ok(Self {
+            file: Mutex::new(file),
+        })
+    }
+
+    pub fn record(&self, recorded_message: &RecordedMessage) -> Result<()> {
+        let line = serde_json::to_string(recorded_message)?;
+
+        let mut file = self.file.lock().unwrap_or_else(PoisonError::into_inner);
+        writeln!(file, "{line}")?;
+
+        Ok(())
+    }
+}
+
+/// Reads the messages recorded in a JSONL transcript, in the order they were recorded.
+pub fn read_transcript(path: &Path) -> Result<Vec<RecordedMessage>> {
+    let file = File::open(path)
+        .with_context(|| format!("Failed to open transcript '{}'", path.display()))?;
+
+    let mut recorded_messages = Vec::new();
+    for (index, line) in BufReader::new(file).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let recorded_message = serde_json::from_str(&line)
+            .with_context(|| format!("Invalid recorded message on line {}", index + 1))?;
+        recorded_messages.push(recorded_message);
+    }
+
+    Ok(recorded_messages)
+}
+
+/// Records every message intercepted by `message_interceptor` to a session transcript, along with
+/// the action it took.
+///
+/// Messages are recorded once their interception completes, so a message held back for approval
+/// is recorded once it is approved or denied, and not at all if it is abandoned.
+pub struct RecordInterceptor {
+    message_interceptor: Arc<dyn MessageInterceptor>,
+    recorder: Arc<SessionRecorder>,
+    session_id: String,
+    host_session_id: Option<String>,
+    mcp_server_name: String,
+}
+
+impl RecordInterceptor {
+    pub fn new(
+        message_interceptor: Arc<dyn MessageInterceptor>,
+        recorder: Arc<SessionRecorder>,
+        session_id: String,
+        host_session_id: Option<String>,
+        mcp_server_name: String,
+    ) -> Self {
+        Self {
+            message_interceptor,
+            recorder,
+            session_id,
+            host_session_id,
+            mcp_server_name,
+        }
+    }
+}
+
+#[async_trait]
+impl MessageInterceptor for RecordInterceptor {
+    async fn intercept_message(
+        &self,
+        direction: MessageDirection,
+        message: Message,
+    ) -> Result<MessageInterceptorAction> {
+        let timestamp = format_rfc3339_millis(SystemTime::now()).to_string();
+        let message_type = message.type_;
+        let raw_msg = message.raw_msg.clone();
+
+        let action = self
+            .message_interceptor
+            .intercept_message(direction, message)
+            .await?;
+
+        let recorded_message = RecordedMessage {
+            timestamp,
+            session_id: self.session_id.clone(),
+            host_session_id: self.host_session_id.clone(),
+            mcp_server_name: self.mcp_server_name.clone(),
+            direction,
+            message_type,
+            action: RecordedAction::from_action(&raw_msg, &action),
+            message: raw_msg,
+        };
+        if let Err(e) = self.recorder.record(&recorded_message) {
+            log::error!("Failed to record {direction} message: {e}");
+        }
+
+        Ok(action)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::*;
+    use crate::message::MessageDirection::{Inbound, Outbound};
+
+    /// Answers `ping` requests itself, drops `tools/call` requests, tags responses and sends
+    /// everything else on.
+    struct TestInterceptor;
+
+    #[async_trait]
+    impl MessageInterceptor for TestInterceptor {
+        async fn intercept_message(
+            &self,
+            _direction: MessageDirection,
+            mut message: Message,
+        ) -> Result<MessageInterceptorAction> {
+            Ok(match message.raw_msg["method"].as_str() {
+                Some("ping") => MessageInterceptorAction::Return(Message::from_json(json!({
+                    "jsonrpc": "2.0",
+                    "id": message.raw_msg["id"],
+                    "result": {}
+                }))),
+                Some("tools/call") => MessageInterceptorAction::Drop,
+                Some(_) => MessageInterceptorAction::Send(message),
+                None => {
+                    message.raw_msg["result"]["tagged"] = json!(true);
+                    MessageInterceptorAction::Send(message)
+                }
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_record_session() {
+        let path = std::env::temp_dir().join(format!("transcript-{}.jsonl", uuid::Uuid::new_v4()));
+        let record_interceptor = RecordInterceptor::new(
+            Arc::new(TestInterceptor),
+            Arc::new(SessionRecorder::create(&path).unwrap()),
+            "session".to_owned(),
+            None,
+            "test".to_owned(),
+        );
+
+        let messages = [
+            (
+                Outbound,
+                json!({"jsonrpc": "2.0", "id": 1, "method": "ping"}),
+            ),
+            (
+                Outbound,
+                json!({"jsonrpc": "2.0", "id": 2, "method": "tools/call"}),
+            ),
+            (
+                Outbound,
+                json!({"jsonrpc": "2.0", "id": 3, "method": "tools/list"}),
+            ),
+            (Inbound, json!({"jsonrpc": "2.0", "id": 3, "result": {}})),
+        ];
+        for (direction, msg) in messages {
+            record_interceptor
+                .intercept_message(direction, Message::from_json(msg))
+                .await
+                .unwrap();
+        }
+
+        let recorded_messages = read_transcript(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let recorded = recorded_messages
+            .iter()
+            .map(|recorded| {
+                (
+                    recorded.direction,
+                    recorded.message_type,
+                    recorded.action.clone(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            recorded,
+            [
+                (
+                    Outbound,
+                    MessageType::Request,
+                    RecordedAction::Return {
+                        message: json!({"jsonrpc": "2.0", "id": 1, "result": {}})
+                    }
+                ),
+                (Outbound, MessageType::Request, RecordedAction::Drop),
+                (
+                    Outbound,
+                    MessageType::Request,
+                    RecordedAction::Send { message: None }
+                ),
+                (
+                    Inbound,
+                    MessageType::ResponseSuccess,
+                    RecordedAction::Send {
+                        message: Some(
+                            json!({"jsonrpc": "2.0", "id": 3, "result": {"tagged": true}})
+                        )
+                    }
+                ),
+            ]
+        );
+        assert!(
+            recorded_messages
+                .iter()
+                .all(|recorded| recorded.session_id == "session"
+                    && recorded.mcp_server_name == "test")
+        );
+    }
+}
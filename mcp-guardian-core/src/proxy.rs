@@ -1,22 +1,46 @@
+pub mod aggregate;
+pub mod builder;
+mod framing;
+pub mod http;
+pub mod http_server;
+mod process;
+pub mod transport;
+
 use std::{
-    io::{self, BufReader, Write},
-    process::{Command, Stdio},
-    sync::{Arc, Mutex},
+    collections::HashMap,
+    io::{self, Write},
+    process::ExitStatus,
+    sync::Arc,
+    time::Duration,
 };
 
-use anyhow::{anyhow, Result};
-use serde_json::{Deserializer, Value};
-use tokio::{sync::mpsc, task};
-use uuid::Uuid;
-
 use crate::{
-    message::Message,
+    mcp_server::{McpServer, RemoteMcpServer},
+    message::{
+        Message, MessageDirection,
+        MessageDirection::{Inbound, Outbound},
+    },
     message_interceptor::{
-        MessageInterceptor,
-        MessageInterceptorAction::{Drop, Return, Send},
+        intercept_in_order, intercept_raw_message,
+        record::{RecordInterceptor, SessionRecorder},
+        InterceptedMessage, MessageInterceptor,
     },
+    proxy::{
+        builder::{ProxyBuilder, ServerTransport},
+        transport::Connection,
+    },
+};
+use anyhow::{anyhow, Result};
+use serde_json::{json, Value};
+use tokio::{
+    sync::{mpsc, oneshot},
+    task, time,
 };
 
+/// How long the upstream MCP server's remaining messages are given to reach the host once the
+/// proxy is done.
+const FLUSH_TIMEOUT: Duration = Duration::from_secs(5);
+
 pub struct Context {
     pub mcp_server_name: String,
     pub host_session_id: Option<String>,
@@ -24,173 +48,492 @@ pub struct Context {
     pub message_interceptor: Arc<dyn MessageInterceptor>,
 }
 
+impl Context {
+    /// Records the messages of the session to a transcript with `recorder`, if given.
+    fn record(mut self, recorder: Option<Arc<SessionRecorder>>) -> Self {
+        if let Some(recorder) = recorder {
+            self.message_interceptor = Arc::new(RecordInterceptor::new(
+                self.message_interceptor,
+                recorder,
+                self.session_id.clone(),
+                self.host_session_id.clone(),
+                self.mcp_server_name.clone(),
+            ));
+        }
+
+        self
+    }
+}
+
+/// Proxies an MCP server process over stdio, recording the session with `recorder` if given.
+///
+/// Returns the exit status of the process once it exits, or once the host closes stdin or
+/// `shutdown_rx` receives and the process has been shut down.
 pub async fn proxy_mcp_server(
     mcp_server_name: String,
     host_session_id: Option<String>,
-    program: &str,
-    args: &[&str],
+    mcp_server: &McpServer,
+    message_interceptor: Arc<dyn MessageInterceptor>,
+    recorder: Option<Arc<SessionRecorder>>,
+    shutdown_rx: oneshot::Receiver<()>,
+) -> Result<ExitStatus> {
+    ProxyBuilder::new(mcp_server_name, message_interceptor)
+        .host_session_id(host_session_id)
+        .recorder(recorder)
+        .server(ServerTransport::Process(mcp_server.clone()))
+        .spawn()?
+        .wait_or_shutdown(shutdown_rx)
+        .await?
+        .ok_or_else(|| anyhow!("MCP server process exited without an exit status."))
+}
+
+/// Proxies a remote MCP server reachable over HTTP while presenting stdio to the host, recording
+/// the session with `recorder` if given, until the host closes stdin or `shutdown_rx` receives.
+pub async fn proxy_remote_mcp_server(
+    mcp_server_name: String,
+    host_session_id: Option<String>,
+    remote: &RemoteMcpServer,
     message_interceptor: Arc<dyn MessageInterceptor>,
+    recorder: Option<Arc<SessionRecorder>>,
+    shutdown_rx: oneshot::Receiver<()>,
 ) -> Result<()> {
-    let ctx = Arc::new(Context {
-        mcp_server_name,
-        host_session_id,
-        session_id: Uuid::new_v4().to_string(),
-        message_interceptor,
-    });
-
-    log::info!("Starting proxy for: {} {:?}", program, args);
-
-    #[allow(clippy::zombie_processes)]
-    let mut child = Command::new(program)
-        .args(args)
-        .stdin(Stdio::piped())
-        .stdout(Stdio::piped())
-        .stderr(Stdio::inherit())
-        .spawn()
-        .expect("Failed to start child process");
-
-    let child_stdin = child.stdin.take().expect("Failed to open child stdin");
-    let child_stdout = child.stdout.take().expect("Failed to open child stdout");
-
-    // Outbound Message Buffer
-    let (outbound_tx, mut outbound_rx) = mpsc::channel::<Value>(100);
-    // Inbound Message Buffer
-    let (inbound_tx, mut inbound_rx) = mpsc::channel::<Value>(100);
-
-    // Outbound Message Reception
-    //
-    // 1. Read json-rpc message from stdin.
-    // 2. Send to outbound message buffer.
-    log::info!("Starting outbound message receiver");
-    let outbound_message_reception_task = task::spawn_blocking(move || {
-        let stdin = io::stdin();
-        let reader = BufReader::new(stdin.lock());
-        let stream = Deserializer::from_reader(reader).into_iter::<Value>();
-
-        for msg in stream {
-            match msg {
-                Ok(json) => {
-                    if let Err(e) = outbound_tx.blocking_send(json) {
-                        log::error!("Failed to send message to outbound buffer: {e}");
-                    }
-                }
-                Err(e) => log::error!("Error parsing JSON from parent: {e}"),
-            }
-        }
-    });
+    ProxyBuilder::new(mcp_server_name, message_interceptor)
+        .host_session_id(host_session_id)
+        .recorder(recorder)
+        .server(ServerTransport::Remote(remote.clone()))
+        .spawn()?
+        .wait_or_shutdown(shutdown_rx)
+        .await?;
 
-    // Inbound Message Reception
-    //
-    // 1. Read json-rpc message from child stdout.
-    // 2. Send to inbound message buffer.
-    log::info!("Starting inbound message receiver");
-    let inbound_message_reception_task = task::spawn_blocking(move || {
-        let reader = BufReader::new(child_stdout);
-        let stream = Deserializer::from_reader(reader).into_iter::<Value>();
-
-        for msg in stream {
-            match msg {
-                Ok(json) => {
-                    if let Err(e) = inbound_tx.blocking_send(json) {
-                        log::error!("Failed to send message to inbound buffer: {e}");
-                    }
-                }
-                Err(e) => log::error!("Error parsing JSON from child: {e}"),
-            }
-        }
-    });
+    Ok(())
+}
+
+/// Waits for the relay to write the upstream MCP server's remaining messages to stdout.
+async fn flush(relay_task: task::JoinHandle<()>) {
+    if time::timeout(FLUSH_TIMEOUT, relay_task).await.is_err() {
+        log::warn!("Timed out writing the remaining messages to the host.");
+    }
+}
+
+/// Relays messages between the host and an upstream MCP server, running every message
+/// through the context's message interceptor.
+///
+/// Signals `host_closed_tx` once the host closes the connection, and returns once the upstream MCP
+/// server's messages have run out and been written to the host.
+async fn relay_messages(
+    ctx: Arc<Context>,
+    host: Connection,
+    upstream_tx: mpsc::Sender<Value>,
+    inbound_rx: mpsc::Receiver<Value>,
+    host_closed_tx: oneshot::Sender<()>,
+) {
+    // Outbound Message Reception reads messages from the host into the outbound message buffer,
+    // and Host Message Transmission writes the host message buffer to the host.
+    let Connection {
+        tx: host_tx,
+        rx: outbound_rx,
+        reader: outbound_message_reception_task,
+        writer: host_message_transmission_task,
+    } = host;
 
     // Outbound Message Transmission
     //
     // 1. Read from outbound message buffer.
     // 2. intercept_outbound_message()
-    // 3. Write to child stdin.
+    // 3. Send to upstream message buffer, or return to host message buffer.
     log::info!("Starting outbound message transmitter");
-    let ctx_clone = ctx.clone();
-    let outbound_message_transmission_task = task::spawn(async move {
-        let child_stdin = Arc::new(Mutex::new(child_stdin));
-        while let Some(msg) = outbound_rx.recv().await {
-            let ctx_clone = ctx_clone.clone();
-            let child_stdin = child_stdin.clone();
-            task::spawn(async move {
-                match ctx_clone
-                    .message_interceptor
-                    .intercept_outbound_message(Message::from_json(msg))
-                    .await
-                {
-                    Ok(Send(message)) => {
-                        if let Err(e) = writeln!(child_stdin.lock().unwrap(), "{}", message.raw_msg)
-                        {
-                            log::error!("Failed to write to child stdin: {e}");
-                        }
-                        if let Err(e) = child_stdin.lock().unwrap().flush() {
-                            log::error!("Failed to flush child stdin: {e}");
-                        }
-                    }
-                    Ok(Drop) => {}
-                    Ok(Return(message)) => {
-                        if let Err(e) = writeln!(io::stdout(), "{}", message.raw_msg) {
-                            log::error!("Failed to write to stdout: {e}");
-                        }
-                        if let Err(e) = io::stdout().flush() {
-                            log::error!("Failed to flush stdout: {e}");
-                        }
-                    }
-                    Err(e) => {
-                        log::error!("Failed to intercept outbound message properly: {e}");
-                    }
-                }
-            });
-        }
-    });
+    let upstream_return_tx = upstream_tx.downgrade();
+    task::spawn(transmit_messages(
+        ctx.clone(),
+        Outbound,
+        outbound_rx,
+        upstream_tx,
+        host_tx.downgrade(),
+    ));
 
     // Inbound Message Transmission
     //
     // 1. Read from inbound message buffer.
     // 2. intercept_inbound_message()
-    // 3. Write to stdout.
+    // 3. Send to host message buffer, or return to upstream message buffer.
     log::info!("Starting inbound message transmitter");
-    let ctx_clone = ctx.clone();
-    let inbound_message_transmission_task = task::spawn(async move {
-        while let Some(msg) = inbound_rx.recv().await {
-            let ctx_clone = ctx_clone.clone();
-            task::spawn(async move {
-                match ctx_clone
-                    .message_interceptor
-                    .intercept_inbound_message(Message::from_json(msg))
-                    .await
-                {
-                    Ok(Send(message)) => {
-                        if let Err(e) = writeln!(io::stdout(), "{}", message.raw_msg) {
-                            log::error!("Failed to write to stdout: {e}");
-                        }
-                        if let Err(e) = io::stdout().flush() {
-                            log::error!("Failed to flush stdout: {e}");
-                        }
-                    }
-                    Ok(Drop) => {}
-                    Ok(Return(message)) => {
-                        if let Err(e) = writeln!(io::stdout(), "{}", message.raw_msg) {
-                            log::error!("Failed to write to stdout: {e}");
-                        }
-                        if let Err(e) = io::stdout().flush() {
-                            log::error!("Failed to flush stdout: {e}");
-                        }
-                    }
-                    Err(e) => {
-                        log::error!("Failed to intercept outbound message properly: {e}");
-                    }
+    let mut inbound_message_transmission_task = task::spawn(transmit_messages(
+        ctx,
+        Inbound,
+        inbound_rx,
+        host_tx,
+        upstream_return_tx,
+    ));
+
+    tokio::select! {
+        _ = outbound_message_reception_task => {
+            log::info!("Host closed the connection.");
+            let _ = host_closed_tx.send(());
+        }
+        _ = &mut inbound_message_transmission_task => {
+            log::info!("Upstream MCP server closed the connection.");
+            return;
+        }
+    }
+
+    let _ = inbound_message_transmission_task.await;
+    let _ = host_message_transmission_task.await;
+}
+
+/// Writes messages from `host_rx` to the host (stdout) until the buffer is closed. Blocking.
+fn transmit_host_messages(mut host_rx: mpsc::Receiver<Value>) {
+    while let Some(msg) = host_rx.blocking_recv() {
+        if let Err(e) = writeln!(io::stdout(), "{msg}") {
+            log::error!("Failed to write to stdout: {e}");
+        }
+        if let Err(e) = io::stdout().flush() {
+            log::error!("Failed to flush stdout: {e}");
+        }
+    }
+}
+
+/// Intercepts messages from `rx` one at a time and in order, sending them on to `send_tx` or
+/// returning them to `return_tx`. Batches are split between the two as needed.
+///
+/// `return_tx` is weak so that returning messages to the sender doesn't keep the sender's buffer
+/// open: the remote connection, for one, only ends once its upstream buffer is closed.
+///
+/// A request held back by the interceptor, e.g. while it awaits manual approval, is abandoned if
+/// its sender cancels it with `notifications/cancelled`, so no late response is sent for it. A
+/// held batch is abandoned as a whole, and its other requests are answered with errors.
+async fn transmit_messages(
+    ctx: Arc<Context>,
+    direction: MessageDirection,
+    mut rx: mpsc::Receiver<Value>,
+    send_tx: mpsc::Sender<Value>,
+    return_tx: mpsc::WeakSender<Value>,
+) {
+    let mut held_requests = HashMap::<String, Arc<HeldMessage>>::new();
+
+    while let Some(msg) = rx.recv().await {
+        for element in Message::unbatch(&msg) {
+            if element.get("method") != Some(&json!("notifications/cancelled")) {
+                continue;
+            }
+            let Some(request_id) = element.pointer("/params/requestId") else {
+                continue;
+            };
+            let Some(held_message) = held_requests.remove(&request_id.to_string()) else {
+                continue;
+            };
+            if held_message.abort_handle.is_finished() {
+                continue;
+            }
+
+            log::info!("Abandoning cancelled {direction} request {request_id}.");
+            held_message.abort_handle.abort();
+
+            let mut responses = Vec::new();
+            for id in &held_message.request_ids {
+                if id != request_id {
+                    held_requests.remove(&id.to_string());
+                    responses.push(error_response(
+                        id.clone(),
+                        -32603,
+                        "Request was abandoned along with a cancelled request in its batch.",
+                    ));
                 }
+            }
+
+            if !responses.is_empty() {
+                let Some(return_tx) = return_tx.upgrade() else {
+                    log::warn!("Failed to return {direction} message: sender is gone");
+                    continue;
+                };
+                if let Err(e) = return_tx.send(Value::Array(responses)).await {
+                    log::error!("Failed to return {direction} message: {e}");
+                }
+            }
+        }
+
+        let request_ids = Message::unbatch(&msg)
+            .iter()
+            .filter_map(|element| element.get("method").and(element.get("id")))
+            .cloned()
+            .collect::<Vec<_>>();
+
+        let ctx = ctx.clone();
+        let send_tx = send_tx.clone();
+        let return_tx = return_tx.clone();
+
+        let held_message = intercept_in_order(async move {
+            let InterceptedMessage { send, return_ } =
+                intercept_raw_message(ctx.message_interceptor.as_ref(), direction, msg).await;
+
+            if let Some(msg) = send {
+                if let Err(e) = send_tx.send(msg).await {
+                    log::error!("Failed to forward {direction} message: {e}");
+                }
+            }
+            if let Some(msg) = return_ {
+                let Some(return_tx) = return_tx.upgrade() else {
+                    log::warn!("Failed to return {direction} message: sender is gone");
+                    return;
+                };
+                if let Err(e) = return_tx.send(msg).await {
+                    log::error!("Failed to return {direction} message: {e}");
+                }
+            }
+        })
+        .await;
+
+        if let Some(abort_handle) = held_message {
+            if request_ids.is_empty() {
+                continue;
+            }
+
+            held_requests.retain(|_, held_message| !held_message.abort_handle.is_finished());
+
+            let held_message = Arc::new(HeldMessage {
+                abort_handle,
+                request_ids,
             });
+            for id in &held_message.request_ids {
+                held_requests.insert(id.to_string(), held_message.clone());
+            }
         }
-    });
+    }
+}
 
-    tokio::select! {
-        _ = outbound_message_reception_task => eprintln!("Outbound Message Reception Task exited unexpectedly."),
-        _ = inbound_message_reception_task => eprintln!("Inbound Message Reception Task exited unexpectedly."),
-        _ = outbound_message_transmission_task => eprintln!("Outbound Message Transmission Task exited unexpectedly."),
-        _ = inbound_message_transmission_task => eprintln!("Inbound Message Transmission Task exited unexpectedly."),
+/// A message held back by the interceptor, known by each of the requests it contains.
+struct HeldMessage {
+    abort_handle: task::AbortHandle,
+    request_ids: Vec<Value>,
+}
+
+pub(crate) fn error_response(id: Value, code: i64, message: &str) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": {
+            "code": code,
+            "message": message,
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use async_trait::async_trait;
+    use tokio::{
+        sync::Notify,
+        time::{sleep, Duration},
+    };
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::message_interceptor::{
+        hold_message, MessageInterceptorAction, MessageInterceptorAction::Send,
+    };
+
+    /// Takes longer to intercept `initialize` than any other message.
+    struct SlowInitializeInterceptor;
+
+    #[async_trait]
+    impl MessageInterceptor for SlowInitializeInterceptor {
+        async fn intercept_message(
+            &self,
+            _direction: MessageDirection,
+            message: Message,
+        ) -> Result<MessageInterceptorAction> {
+            if message.raw_msg.get("method") == Some(&json!("initialize")) {
+                sleep(Duration::from_millis(50)).await;
+            }
+
+            Ok(Send(message))
+        }
+    }
+
+    /// Holds back `tools/call` requests until released.
+    struct HoldToolCallsInterceptor {
+        release: Arc<Notify>,
     }
 
-    Err(anyhow!("Exited MCP Guardian Early."))
+    #[async_trait]
+    impl MessageInterceptor for HoldToolCallsInterceptor {
+        async fn intercept_message(
+            &self,
+            _direction: MessageDirection,
+            message: Message,
+        ) -> Result<MessageInterceptorAction> {
+            if message.raw_msg.get("method") == Some(&json!("tools/call")) {
+                hold_message();
+                self.release.notified().await;
+            }
+
+            Ok(Send(message))
+        }
+    }
+
+    fn ctx(message_interceptor: Arc<dyn MessageInterceptor>) -> Arc<Context> {
+        Arc::new(Context {
+            mcp_server_name: "test".to_owned(),
+            host_session_id: None,
+            session_id: Uuid::new_v4().to_string(),
+            message_interceptor,
+        })
+    }
+
+    #[tokio::test]
+    async fn test_initialized_never_overtakes_initialize() {
+        let (tx, rx) = mpsc::channel::<Value>(10);
+        let (send_tx, mut send_rx) = mpsc::channel::<Value>(10);
+        let (return_tx, _return_rx) = mpsc::channel::<Value>(10);
+
+        task::spawn(transmit_messages(
+            ctx(Arc::new(SlowInitializeInterceptor)),
+            Outbound,
+            rx,
+            send_tx,
+            return_tx.downgrade(),
+        ));
+
+        for id in 0..5 {
+            tx.send(json!({ "jsonrpc": "2.0", "id": id, "method": "initialize" }))
+                .await
+                .unwrap();
+            tx.send(json!({ "jsonrpc": "2.0", "method": "notifications/initialized" }))
+                .await
+                .unwrap();
+        }
+
+        for id in 0..5 {
+            let msg = send_rx.recv().await.unwrap();
+            assert_eq!(msg["method"], json!("initialize"));
+            assert_eq!(msg["id"], json!(id));
+
+            let msg = send_rx.recv().await.unwrap();
+            assert_eq!(msg["method"], json!("notifications/initialized"));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_held_message_lets_traffic_through() {
+        let release = Arc::new(Notify::new());
+        let (tx, rx) = mpsc::channel::<Value>(10);
+        let (send_tx, mut send_rx) = mpsc::channel::<Value>(10);
+        let (return_tx, _return_rx) = mpsc::channel::<Value>(10);
+
+        task::spawn(transmit_messages(
+            ctx(Arc::new(HoldToolCallsInterceptor {
+                release: release.clone(),
+            })),
+            Outbound,
+            rx,
+            send_tx,
+            return_tx.downgrade(),
+        ));
+
+        tx.send(json!({ "jsonrpc": "2.0", "id": 1, "method": "tools/call" }))
+            .await
+            .unwrap();
+        tx.send(json!({ "jsonrpc": "2.0", "id": 2, "method": "ping" }))
+            .await
+            .unwrap();
+
+        assert_eq!(send_rx.recv().await.unwrap()["id"], json!(2));
+
+        release.notify_one();
+        assert_eq!(send_rx.recv().await.unwrap()["id"], json!(1));
+    }
+
+    #[tokio::test]
+    async fn test_cancel_held_batch_request() {
+        let release = Arc::new(Notify::new());
+        let (tx, rx) = mpsc::channel::<Value>(10);
+        let (send_tx, mut send_rx) = mpsc::channel::<Value>(10);
+        let (return_tx, mut return_rx) = mpsc::channel::<Value>(10);
+
+        task::spawn(transmit_messages(
+            ctx(Arc::new(HoldToolCallsInterceptor {
+                release: release.clone(),
+            })),
+            Outbound,
+            rx,
+            send_tx,
+            return_tx.downgrade(),
+        ));
+
+        tx.send(json!([
+            { "jsonrpc": "2.0", "id": 1, "method": "tools/call" },
+            { "jsonrpc": "2.0", "id": 2, "method": "tools/list" },
+        ]))
+        .await
+        .unwrap();
+        tx.send(json!({
+            "jsonrpc": "2.0",
+            "method": "notifications/cancelled",
+            "params": { "requestId": 1 }
+        }))
+        .await
+        .unwrap();
+
+        // the batch is abandoned, and its other request answered
+        assert_eq!(
+            return_rx.recv().await.unwrap(),
+            json!([{
+                "jsonrpc": "2.0",
+                "id": 2,
+                "error": {
+                    "code": -32603,
+                    "message": "Request was abandoned along with a cancelled request in its batch."
+                }
+            }])
+        );
+        assert_eq!(
+            send_rx.recv().await.unwrap()["method"],
+            json!("notifications/cancelled")
+        );
+
+        release.notify_waiters();
+        drop(tx);
+        assert_eq!(send_rx.recv().await, None);
+    }
+
+    #[tokio::test]
+    async fn test_cancel_held_request() {
+        let release = Arc::new(Notify::new());
+        let (tx, rx) = mpsc::channel::<Value>(10);
+        let (send_tx, mut send_rx) = mpsc::channel::<Value>(10);
+        let (return_tx, _return_rx) = mpsc::channel::<Value>(10);
+
+        task::spawn(transmit_messages(
+            ctx(Arc::new(HoldToolCallsInterceptor {
+                release: release.clone(),
+            })),
+            Outbound,
+            rx,
+            send_tx,
+            return_tx.downgrade(),
+        ));
+
+        tx.send(json!({ "jsonrpc": "2.0", "id": 1, "method": "tools/call" }))
+            .await
+            .unwrap();
+        tx.send(json!({
+            "jsonrpc": "2.0",
+            "method": "notifications/cancelled",
+            "params": { "requestId": 1 }
+        }))
+        .await
+        .unwrap();
+        tx.send(json!({ "jsonrpc": "2.0", "id": 2, "method": "ping" }))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            send_rx.recv().await.unwrap()["method"],
+            json!("notifications/cancelled")
+        );
+        assert_eq!(send_rx.recv().await.unwrap()["id"], json!(2));
+
+        release.notify_waiters();
+        drop(tx);
+        assert_eq!(send_rx.recv().await, None);
+    }
 }
@@ -0,0 +1,1544 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+
+use anyhow::Result;
+use futures_util::future::join_all;
+use serde_json::{json, Map, Value};
+use tokio::{
+    io::BufReader,
+    sync::{mpsc, oneshot},
+    task, time,
+};
+use uuid::Uuid;
+
+use crate::{
+    mcp_server::McpServer,
+    message::{
+        Message,
+        MessageDirection::{Inbound, Outbound},
+        MessageType,
+    },
+    message_interceptor::{record::SessionRecorder, MessageInterceptor},
+    proxy::{
+        error_response, flush, http, process::spawn_mcp_server, transmit_host_messages,
+        transmit_messages, transport::receive_stream_messages, Context, FLUSH_TIMEOUT,
+    },
+    server_log::ServerLogWriter,
+};
+
+/// Most pages of a list requested from a single server, in case its cursors never run out.
+const MAX_LIST_PAGES: usize = 100;
+
+/// An MCP server proxied alongside others by [`proxy_mcp_servers`].
+pub struct AggregatedMcpServer {
+    pub name: String,
+    /// Prepended to the names of the server's tools, prompts and resources
+    pub prefix: String,
+    pub mcp_server: McpServer,
+    /// Guards the messages to and from this server only
+    pub message_interceptor: Arc<dyn MessageInterceptor>,
+}
+
+/// Proxies several MCP servers over stdio as though they were one.
+///
+/// The servers' tools, prompts and resources are listed together, their names prefixed with the
+/// prefix of the server they belong to, and requests for them are routed to that server. Every
+/// server is guarded by its own message interceptor, which sees the server's messages as the
+/// server sends and receives them, without prefixes.
+///
+/// Every server's messages are recorded with `recorder` if given, to the one transcript.
+///
+/// Returns once the host closes stdin or `shutdown_rx` receives and the servers have been shut
+/// down, or once every server is gone.
+pub async fn proxy_mcp_servers(
+    name: String,
+    host_session_id: Option<String>,
+    mcp_servers: Vec<AggregatedMcpServer>,
+    recorder: Option<Arc<SessionRecorder>>,
+    mut shutdown_rx: oneshot::Receiver<()>,
+) -> Result<()> {
+    let session_id = Uuid::new_v4().to_string();
+
+    // Messages from the servers for the aggregator, by server, and `None` once a server is gone
+    let (server_msg_tx, mut server_msg_rx) = mpsc::channel::<(usize, Option<Value>)>(100);
+
+    let mut upstreams = Vec::new();
+    let mut processes = Vec::new();
+
+    for (
+        index,
+        AggregatedMcpServer {
+            name,
+            prefix,
+            mcp_server,
+            message_interceptor,
+        },
+    ) in mcp_servers.into_iter().enumerate()
+    {
+        let ctx = Arc::new(
+            Context {
+                mcp_server_name: name.clone(),
+                host_session_id: host_session_id.clone(),
+                session_id: session_id.clone(),
+                message_interceptor,
+            }
+            .record(recorder.clone()),
+        );
+
+        let (upstream_tx, inbound_rx) = match &mcp_server.remote {
+            Some(remote) => {
+                log::info!("Connecting to '{name}': {}", remote.url);
+
+                let (upstream_tx, upstream_rx) = mpsc::channel::<Value>(100);
+                let (inbound_tx, inbound_rx) = mpsc::channel::<Value>(100);
+
+                let remote = remote.clone();
+                let name = name.clone();
+                task::spawn(async move {
+                    if let Err(e) = http::run_client(remote, upstream_rx, inbound_tx).await {
+                        log::error!("Remote connection to '{name}' failed: {e}");
+                    }
+                });
+
+                (upstream_tx, inbound_rx)
+            }
+            None => {
+                log::info!(
+                    "Starting '{name}': {} {:?}",
+                    mcp_server.cmd,
+                    mcp_server.args
+                );
+
+                let stderr_log = ServerLogWriter::create_or_inherit(
+                    &name,
+                    &session_id,
+                    &mcp_server.stderr_log.clone().unwrap_or_default(),
+                );
+                let (upstream_tx, inbound_rx, process) = spawn_mcp_server(&mcp_server, stderr_log)?;
+                processes.push(process);
+
+                (upstream_tx, inbound_rx)
+            }
+        };
+
+        // Messages for the server, intercepted on their way
+        let (outbound_tx, outbound_rx) = mpsc::channel::<Value>(100);
+        // Messages from the server, or returned to the host by its interceptor
+        let (server_tx, mut server_rx) = mpsc::channel::<Value>(100);
+
+        let upstream_return_tx = upstream_tx.downgrade();
+        task::spawn(transmit_messages(
+            ctx.clone(),
+            Outbound,
+            outbound_rx,
+            upstream_tx,
+            server_tx.downgrade(),
+        ));
+        task::spawn(transmit_messages(
+            ctx,
+            Inbound,
+            inbound_rx,
+            server_tx,
+            upstream_return_tx,
+        ));
+
+        let server_msg_tx = server_msg_tx.clone();
+        task::spawn(async move {
+            while let Some(msg) = server_rx.recv().await {
+                if server_msg_tx.send((index, Some(msg))).await.is_err() {
+                    return;
+                }
+            }
+            let _ = server_msg_tx.send((index, None)).await;
+        });
+
+        upstreams.push(Upstream {
+            name,
+            prefix,
+            tx: Some(outbound_tx),
+        });
+    }
+    drop(server_msg_tx);
+
+    // Outbound Message Buffer
+    let (outbound_tx, mut outbound_rx) = mpsc::channel::<Value>(100);
+    // Host Message Buffer
+    let (host_tx, host_rx) = mpsc::channel::<Value>(100);
+
+    log::info!("Starting outbound message receiver");
+    task::spawn(receive_stream_messages(
+        BufReader::new(tokio::io::stdin()),
+        "host",
+        outbound_tx,
+    ));
+
+    log::info!("Starting host message transmitter");
+    let host_message_transmission_task =
+        task::spawn_blocking(move || transmit_host_messages(host_rx));
+
+    let mut aggregator = Aggregator::new(name, upstreams, host_tx);
+
+    loop {
+        tokio::select! {
+            msg = outbound_rx.recv() => match msg {
+                Some(msg) => aggregator.handle_host_message(msg).await,
+                None => {
+                    log::info!("Host closed stdin.");
+                    break;
+                }
+            },
+            msg = server_msg_rx.recv() => match msg {
+                Some((index, Some(msg))) => aggregator.handle_server_message(index, msg).await,
+                Some((index, None)) => aggregator.handle_server_gone(index).await,
+                None => {
+                    log::info!("Every MCP server is gone.");
+                    break;
+                }
+            },
+            Ok(()) = &mut shutdown_rx => break,
+        }
+    }
+
+    log::info!("Shutting down MCP server processes.");
+    aggregator.close_upstreams();
+    for res in join_all(processes.into_iter().map(|process| process.shutdown())).await {
+        if let Err(e) = res {
+            log::error!("Failed to shut down MCP server process: {e}");
+        }
+    }
+
+    // Deliver the servers' remaining messages.
+    let _ = time::timeout(FLUSH_TIMEOUT, async {
+        while let Some((index, msg)) = server_msg_rx.recv().await {
+            if let Some(msg) = msg {
+                aggregator.handle_server_message(index, msg).await;
+            }
+        }
+    })
+    .await;
+
+    drop(aggregator);
+    flush(host_message_transmission_task).await;
+
+    Ok(())
+}
+
+struct Upstream {
+    name: String,
+    prefix: String,
+    /// Buffer of messages for the server, until the server or the host is gone
+    tx: Option<mpsc::Sender<Value>>,
+}
+
+/// A request the aggregator sent a server, awaiting its response.
+enum Pending {
+    /// A host request concerning this server only
+    Routed { host_id: Value },
+    /// This server's part of a host request sent to every server
+    FanOut { host_id: Value },
+}
+
+/// A host request sent to every server, whose responses are merged into one.
+struct FanOut {
+    method: String,
+    /// The request as sent to the servers, sent again for the next page of a list
+    request: Value,
+    /// Servers that haven't responded yet
+    awaiting: HashSet<usize>,
+    /// Result of every server that responded successfully, by server
+    results: Vec<Option<Value>>,
+    /// Items listed by every server, by server
+    items: Vec<Vec<Value>>,
+    /// Cursors of the further pages of a list requested from every server, by server
+    cursors: Vec<HashSet<Value>>,
+}
+
+/// A batch of host requests, answered together once every request in it is.
+struct Batch {
+    /// Ids of the requests not answered yet
+    awaiting: HashSet<Value>,
+    responses: Vec<Value>,
+}
+
+/// Merges the MCP servers behind the proxy into a single MCP server for the host.
+struct Aggregator {
+    name: String,
+    upstreams: Vec<Upstream>,
+    host_tx: mpsc::Sender<Value>,
+    next_id: u64,
+    /// Requests sent to the servers, by the id they were sent under, with the server they were
+    /// sent to
+    pending: HashMap<Value, (usize, Pending)>,
+    /// Host requests sent to every server, by the host's id
+    fan_outs: HashMap<Value, FanOut>,
+    /// Requests from the servers sent on to the host, by the id they were sent under, with the
+    /// server they came from and their original id
+    server_requests: HashMap<Value, (usize, Value)>,
+    /// Batches of host requests not answered yet
+    batches: Vec<Batch>,
+    /// Server each listed tool and prompt belongs to, by list and prefixed name, with the name as
+    /// the server knows it
+    name_owners: HashMap<(&'static str, String), (usize, String)>,
+    /// Server each listed resource belongs to, by uri
+    resource_owners: HashMap<String, usize>,
+    /// Server each listed resource template belongs to
+    resource_templates: Vec<(String, usize)>,
+}
+
+impl Aggregator {
+    fn new(name: String, upstreams: Vec<Upstream>, host_tx: mpsc::Sender<Value>) -> Self {
+        Self {
+            name,
+            upstreams,
+            host_tx,
+            next_id: 0,
+            pending: HashMap::new(),
+            fan_outs: HashMap::new(),
+            server_requests: HashMap::new(),
+            batches: Vec::new(),
+            name_owners: HashMap::new(),
+            resource_owners: HashMap::new(),
+            resource_templates: Vec::new(),
+        }
+    }
+
+    /// Handles a message from the host. The elements of a batch are handled one by one, and
+    /// answered together.
+    async fn handle_host_message(&mut self, msg: Value) {
+        if let Value::Array(elements) = &msg {
+            let awaiting = elements
+                .iter()
+                .filter(|msg| Message::from_json((*msg).clone()).type_ == MessageType::Request)
+                .map(|msg| msg["id"].clone())
+                .collect::<HashSet<_>>();
+
+            if !awaiting.is_empty() {
+                self.batches.push(Batch {
+                    awaiting,
+                    responses: Vec::new(),
+                });
+            }
+        }
+
+        for msg in Message::unbatch(&msg) {
+            let msg = msg.clone();
+
+            match Message::from_json(msg.clone()).type_ {
+                MessageType::Request => self.handle_host_request(msg).await,
+                MessageType::ResponseSuccess | MessageType::ResponseFailure => {
+                    self.handle_host_response(msg).await
+                }
+                MessageType::Notification => self.handle_host_notification(msg).await,
+                MessageType::Unknown => log::warn!("Ignoring invalid message from host: {msg}"),
+            }
+        }
+    }
+
+    async fn handle_host_request(&mut self, mut msg: Value) {
+        let id = msg["id"].clone();
+        let method = msg["method"].as_str().unwrap_or_default().to_owned();
+
+        match method.as_str() {
+            "initialize"
+            | "logging/setLevel"
+            | "tools/list"
+            | "prompts/list"
+            | "resources/list"
+            | "resources/templates/list" => self.fan_out(msg).await,
+            "ping" => {
+                self.send_to_host(json!({ "jsonrpc": "2.0", "id": id, "result": {} }))
+                    .await
+            }
+            "tools/call" | "prompts/get" => {
+                let list_key = if method == "tools/call" {
+                    "tools"
+                } else {
+                    "prompts"
+                };
+                let name = msg.pointer("/params/name").and_then(Value::as_str);
+                match name.and_then(|name| self.route_name(list_key, name)) {
+                    Some((index, name)) => {
+                        msg["params"]["name"] = json!(name);
+                        self.route(index, msg).await;
+                    }
+                    None => self.reject(id, "No MCP server provides this name.").await,
+                }
+            }
+            "resources/read" | "resources/subscribe" | "resources/unsubscribe" => {
+                let uri = msg.pointer("/params/uri").and_then(Value::as_str);
+                match uri.and_then(|uri| self.route_uri(uri)) {
+                    Some(index) => self.route(index, msg).await,
+                    None => {
+                        self.reject(id, "No MCP server provides this resource.")
+                            .await
+                    }
+                }
+            }
+            "completion/complete" => {
+                let reference = msg.pointer("/params/ref").cloned().unwrap_or_default();
+                let route = match reference["type"].as_str() {
+                    Some("ref/prompt") => reference["name"].as_str().and_then(|name| {
+                        let (index, name) = self.route_name("prompts", name)?;
+                        msg["params"]["ref"]["name"] = json!(name);
+                        Some(index)
+                    }),
+                    Some("ref/resource") => reference["uri"]
+                        .as_str()
+                        .and_then(|uri| self.route_uri(uri)),
+                    _ => None,
+                };
+
+                match route {
+                    Some(index) => self.route(index, msg).await,
+                    None => {
+                        self.reject(id, "No MCP server provides this reference.")
+                            .await
+                    }
+                }
+            }
+            _ => {
+                self.send_to_host(error_response(
+                    id,
+                    -32601,
+                    &format!("Method not found: {method}"),
+                ))
+                .await
+            }
+        }
+    }
+
+    /// Passes the host's response to a server's request back to that server.
+    async fn handle_host_response(&mut self, mut msg: Value) {
+        let Some((index, id)) = self.server_requests.remove(&msg["id"]) else {
+            log::warn!("Received response for an unknown request from host: {msg}");
+            return;
+        };
+
+        msg["id"] = id;
+        self.send_to_server(index, msg).await;
+    }
+
+    async fn handle_host_notification(&mut self, mut msg: Value) {
+        if msg["method"] != json!("notifications/cancelled") {
+            for index in 0..self.upstreams.len() {
+                self.send_to_server(index, msg.clone()).await;
+            }
+            return;
+        }
+
+        // The request is cancelled wherever it was sent.
+        let host_id = msg
+            .pointer("/params/requestId")
+            .cloned()
+            .unwrap_or_default();
+        self.fan_outs.remove(&host_id);
+        if let Some(batch) = self.answer_batch(&host_id, None) {
+            self.transmit_to_host(batch).await;
+        }
+
+        let cancelled = self
+            .pending
+            .iter()
+            .filter(|(_, (_, pending))| match pending {
+                Pending::Routed { host_id: id } | Pending::FanOut { host_id: id } => *id == host_id,
+            })
+            .map(|(id, (index, _))| (id.clone(), *index))
+            .collect::<Vec<_>>();
+
+        for (id, index) in cancelled {
+            self.pending.remove(&id);
+            msg["params"]["requestId"] = id;
+            self.send_to_server(index, msg.clone()).await;
+        }
+    }
+
+    /// Handles a message from a server. The elements of a batch are handled one by one.
+    async fn handle_server_message(&mut self, index: usize, msg: Value) {
+        for msg in Message::unbatch(&msg) {
+            let mut msg = msg.clone();
+
+            match Message::from_json(msg.clone()).type_ {
+                MessageType::ResponseSuccess | MessageType::ResponseFailure => {
+                    match self.pending.remove(&msg["id"]) {
+                        Some((from, Pending::Routed { host_id })) if from == index => {
+                            msg["id"] = host_id;
+                            self.send_to_host(msg).await;
+                        }
+                        Some((from, Pending::FanOut { host_id })) if from == index => {
+                            self.receive_fan_out_response(index, host_id, msg).await;
+                        }
+                        _ => log::warn!(
+                            "Received response for an unknown request from '{}': {msg}",
+                            self.upstreams[index].name
+                        ),
+                    }
+                }
+                MessageType::Request => {
+                    let id = self.next_id();
+                    self.server_requests
+                        .insert(id.clone(), (index, msg["id"].clone()));
+                    msg["id"] = id;
+                    self.send_to_host(msg).await;
+                }
+                MessageType::Notification => {
+                    if msg["method"] == json!("notifications/cancelled") {
+                        let request_id = msg.pointer("/params/requestId").cloned();
+                        let Some(id) = self.server_requests.iter().find_map(|(id, request)| {
+                            (*request == (index, request_id.clone().unwrap_or_default()))
+                                .then(|| id.clone())
+                        }) else {
+                            continue;
+                        };
+
+                        self.server_requests.remove(&id);
+                        msg["params"]["requestId"] = id;
+                    }
+
+                    self.send_to_host(msg).await;
+                }
+                MessageType::Unknown => log::warn!(
+                    "Ignoring invalid message from '{}': {msg}",
+                    self.upstreams[index].name
+                ),
+            }
+        }
+    }
+
+    /// Answers the requests a server that's gone never will.
+    async fn handle_server_gone(&mut self, index: usize) {
+        log::warn!("MCP server '{}' is gone.", self.upstreams[index].name);
+        self.upstreams[index].tx = None;
+        self.server_requests.retain(|_, (from, _)| *from != index);
+
+        let pending_ids = self
+            .pending
+            .iter()
+            .filter(|(_, (to, _))| *to == index)
+            .map(|(id, _)| id.clone())
+            .collect::<Vec<_>>();
+
+        for id in pending_ids {
+            match self.pending.remove(&id) {
+                Some((_, Pending::Routed { host_id })) => {
+                    self.reject_unavailable(host_id, index).await;
+                }
+                Some((_, Pending::FanOut { host_id })) => {
+                    if let Some(fan_out) = self.fan_outs.get_mut(&host_id) {
+                        fan_out.awaiting.remove(&index);
+                        if fan_out.awaiting.is_empty() {
+                            self.finish_fan_out(host_id).await;
+                        }
+                    }
+                }
+                None => {}
+            }
+        }
+    }
+
+    /// Closes the buffers of messages for the servers, once the host is gone.
+    fn close_upstreams(&mut self) {
+        for upstream in &mut self.upstreams {
+            upstream.tx = None;
+        }
+    }
+
+    /// Sends a host request on to the server it concerns.
+    async fn route(&mut self, index: usize, mut msg: Value) {
+        let host_id = msg["id"].clone();
+        let id = self.next_id();
+        msg["id"] = id.clone();
+
+        self.pending.insert(
+            id.clone(),
+            (
+                index,
+                Pending::Routed {
+                    host_id: host_id.clone(),
+                },
+            ),
+        );
+
+        if !self.send_to_server(index, msg).await {
+            self.pending.remove(&id);
+            self.reject_unavailable(host_id, index).await;
+        }
+    }
+
+    /// Sends a host request on to every server.
+    async fn fan_out(&mut self, mut msg: Value) {
+        let host_id = msg["id"].clone();
+
+        // Lists are answered in full, so there's never a page after the first.
+        if let Some(params) = msg.get_mut("params").and_then(Value::as_object_mut) {
+            params.remove("cursor");
+        }
+
+        let mut fan_out = FanOut {
+            method: msg["method"].as_str().unwrap_or_default().to_owned(),
+            request: msg.clone(),
+            awaiting: HashSet::new(),
+            results: vec![None; self.upstreams.len()],
+            items: vec![Vec::new(); self.upstreams.len()],
+            cursors: vec![HashSet::new(); self.upstreams.len()],
+        };
+
+        for index in 0..self.upstreams.len() {
+            let id = self.next_id();
+            msg["id"] = id.clone();
+
+            if self.send_to_server(index, msg.clone()).await {
+                self.pending.insert(
+                    id,
+                    (
+                        index,
+                        Pending::FanOut {
+                            host_id: host_id.clone(),
+                        },
+                    ),
+                );
+                fan_out.awaiting.insert(index);
+            }
+        }
+
+        let awaiting = !fan_out.awaiting.is_empty();
+        self.fan_outs.insert(host_id.clone(), fan_out);
+
+        if !awaiting {
+            self.finish_fan_out(host_id).await;
+        }
+    }
+
+    async fn receive_fan_out_response(&mut self, index: usize, host_id: Value, msg: Value) {
+        let Some(fan_out) = self.fan_outs.get(&host_id) else {
+            return;
+        };
+        let method = fan_out.method.clone();
+
+        match (msg.get("result"), list_key(&method)) {
+            (Some(result), Some(list_key)) => {
+                let items = self.claim_items(index, list_key, &result[list_key]);
+                let next_cursor = result.get("nextCursor").filter(|cursor| !cursor.is_null());
+
+                let Some(fan_out) = self.fan_outs.get_mut(&host_id) else {
+                    return;
+                };
+                fan_out.items[index].extend(items);
+
+                // a server's list is cut short rather than requested without end
+                let name = &self.upstreams[index].name;
+                let next_cursor = next_cursor.filter(|next_cursor| {
+                    if fan_out.cursors[index].len() + 1 >= MAX_LIST_PAGES {
+                        log::warn!("MCP server '{name}' listed more than {MAX_LIST_PAGES} pages for {method}. Ignoring the rest.");
+                        false
+                    } else if !fan_out.cursors[index].insert((*next_cursor).clone()) {
+                        log::warn!("MCP server '{name}' repeated cursor {next_cursor} for {method}. Ignoring the rest.");
+                        false
+                    } else {
+                        true
+                    }
+                });
+
+                if let Some(next_cursor) = next_cursor {
+                    let mut request = fan_out.request.clone();
+                    request["params"]["cursor"] = next_cursor.clone();
+
+                    let id = self.next_id();
+                    request["id"] = id.clone();
+                    self.pending.insert(
+                        id.clone(),
+                        (
+                            index,
+                            Pending::FanOut {
+                                host_id: host_id.clone(),
+                            },
+                        ),
+                    );
+
+                    if self.send_to_server(index, request).await {
+                        return;
+                    }
+                    self.pending.remove(&id);
+                }
+            }
+            (Some(result), None) => {
+                let Some(fan_out) = self.fan_outs.get_mut(&host_id) else {
+                    return;
+                };
+                fan_out.results[index] = Some(result.clone());
+            }
+            (None, _) => log::info!(
+                "MCP server '{}' failed {method}: {}",
+                self.upstreams[index].name,
+                msg["error"]
+            ),
+        }
+
+        let Some(fan_out) = self.fan_outs.get_mut(&host_id) else {
+            return;
+        };
+        fan_out.awaiting.remove(&index);
+        if fan_out.awaiting.is_empty() {
+            self.finish_fan_out(host_id).await;
+        }
+    }
+
+    /// Answers a host request sent to every server with the servers' merged responses.
+    async fn finish_fan_out(&mut self, host_id: Value) {
+        let Some(fan_out) = self.fan_outs.remove(&host_id) else {
+            return;
+        };
+
+        let result = match (fan_out.method.as_str(), list_key(&fan_out.method)) {
+            ("initialize", _) => match self.merge_initialize_results(&fan_out.results) {
+                Some(result) => result,
+                None => {
+                    self.send_to_host(error_response(
+                        host_id,
+                        -32603,
+                        "No MCP server could be initialized.",
+                    ))
+                    .await;
+                    return;
+                }
+            },
+            (_, Some(list_key)) => json!({ list_key: fan_out.items.concat() }),
+            _ => json!({}),
+        };
+
+        self.send_to_host(json!({ "jsonrpc": "2.0", "id": host_id, "result": result }))
+            .await;
+    }
+
+    /// Merges the servers' results of `initialize`. The servers are spoken to in the protocol
+    /// version most of them answered with, the first server's on a tie. Servers that answered
+    /// with another version, or failed to initialize, are no longer routed to.
+    fn merge_initialize_results(&mut self, results: &[Option<Value>]) -> Option<Value> {
+        let versions = results
+            .iter()
+            .flatten()
+            .map(|result| &result["protocolVersion"])
+            .collect::<Vec<_>>();
+        let protocol_version = versions
+            .iter()
+            .rev()
+            .max_by_key(|version| versions.iter().filter(|other| other == version).count())
+            .map(|version| (*version).clone())?;
+
+        let mut capabilities = Map::new();
+        let mut instructions = Vec::new();
+
+        for (index, result) in results.iter().enumerate() {
+            let upstream = &mut self.upstreams[index];
+            let result = match result {
+                None => {
+                    if upstream.tx.take().is_some() {
+                        log::warn!(
+                            "MCP server '{}' failed to initialize. No longer routing to it.",
+                            upstream.name
+                        );
+                    }
+                    continue;
+                }
+                Some(result) if result["protocolVersion"] != protocol_version => {
+                    log::warn!(
+                        "MCP server '{}' answered with protocol version {}, not {protocol_version}. \
+                         No longer routing to it.",
+                        upstream.name,
+                        result["protocolVersion"]
+                    );
+                    upstream.tx = None;
+                    continue;
+                }
+                Some(result) => result,
+            };
+
+            if let Some(server_capabilities) = result["capabilities"].as_object() {
+                merge_capabilities(&mut capabilities, server_capabilities);
+            }
+            if let Some(server_instructions) = result["instructions"].as_str() {
+                instructions.push(format!(
+                    "{} ({}): {server_instructions}",
+                    self.upstreams[index].name, self.upstreams[index].prefix
+                ));
+            }
+        }
+
+        let mut result = json!({
+            "protocolVersion": protocol_version,
+            "capabilities": capabilities,
+            "serverInfo": {
+                "name": self.name,
+                "version": env!("CARGO_PKG_VERSION"),
+            },
+        });
+        if !instructions.is_empty() {
+            result["instructions"] = json!(instructions.join("\n\n"));
+        }
+
+        Some(result)
+    }
+
+    /// Prefixes the names of the items a server listed, and notes which items are the server's.
+    fn claim_items(&mut self, index: usize, list_key: &'static str, items: &Value) -> Vec<Value> {
+        let Some(items) = items.as_array() else {
+            return Vec::new();
+        };
+
+        let prefix = &self.upstreams[index].prefix;
+        let mut claimed = Vec::new();
+
+        for item in items {
+            let mut item = item.clone();
+
+            if let Some(name) = item["name"].as_str() {
+                let prefixed_name = format!("{prefix}{name}");
+                if matches!(list_key, "tools" | "prompts") {
+                    self.name_owners
+                        .insert((list_key, prefixed_name.clone()), (index, name.to_owned()));
+                }
+                item["name"] = json!(prefixed_name);
+            }
+
+            match list_key {
+                "resources" => {
+                    if let Some(uri) = item["uri"].as_str() {
+                        if let Some(owner) = self.resource_owners.insert(uri.to_owned(), index) {
+                            self.warn_duplicate(owner, index, uri);
+                        }
+                    }
+                }
+                "resourceTemplates" => {
+                    if let Some(uri_template) = item["uriTemplate"].as_str() {
+                        if let Some((_, owner)) = self
+                            .resource_templates
+                            .iter()
+                            .find(|(template, _)| template == uri_template)
+                        {
+                            self.warn_duplicate(*owner, index, uri_template);
+                        }
+                        self.resource_templates
+                            .retain(|(template, _)| template != uri_template);
+                        self.resource_templates
+                            .push((uri_template.to_owned(), index));
+                    }
+                }
+                _ => {}
+            }
+
+            claimed.push(item);
+        }
+
+        claimed
+    }
+
+    /// Warns that a resource listed by a server is also listed by another, to which it's routed
+    /// from now on.
+    fn warn_duplicate(&self, owner: usize, index: usize, uri: &str) {
+        if owner != index {
+            log::warn!(
+                "Resource '{uri}' is listed by both '{}' and '{}'. Routing it to '{}'.",
+                self.upstreams[owner].name,
+                self.upstreams[index].name,
+                self.upstreams[index].name
+            );
+        }
+    }
+
+    /// Finds the server that listed a prefixed name among the items of a list, along with the
+    /// name as the server knows it. Names not listed yet go to the server whose prefix they start
+    /// with, prefixes never overlapping.
+    fn route_name(&self, list_key: &'static str, name: &str) -> Option<(usize, String)> {
+        if let Some(owner) = self.name_owners.get(&(list_key, name.to_owned())) {
+            return Some(owner.clone());
+        }
+
+        let mut owners = self
+            .upstreams
+            .iter()
+            .enumerate()
+            .filter_map(|(index, upstream)| {
+                Some((index, name.strip_prefix(&upstream.prefix)?.to_owned()))
+            });
+        let owner = owners.next()?;
+        owners.next().is_none().then_some(owner)
+    }
+
+    /// Finds the server a resource belongs to: the server that listed it, or else the server with
+    /// the most specific template matching it.
+    fn route_uri(&self, uri: &str) -> Option<usize> {
+        if let Some(index) = self.resource_owners.get(uri) {
+            return Some(*index);
+        }
+
+        self.resource_templates
+            .iter()
+            .filter_map(|(template, index)| {
+                if template == uri {
+                    return Some((usize::MAX, *index));
+                }
+
+                let literal = template.split('{').next().unwrap_or_default();
+                (!literal.is_empty() && uri.starts_with(literal)).then_some((literal.len(), *index))
+            })
+            .max_by_key(|(specificity, _)| *specificity)
+            .map(|(_, index)| index)
+    }
+
+    async fn reject(&mut self, id: Value, message: &str) {
+        self.send_to_host(error_response(id, -32602, message)).await;
+    }
+
+    async fn reject_unavailable(&mut self, id: Value, index: usize) {
+        let message = format!(
+            "MCP server '{}' is unavailable.",
+            self.upstreams[index].name
+        );
+        self.send_to_host(error_response(id, -32603, &message))
+            .await;
+    }
+
+    fn next_id(&mut self) -> Value {
+        self.next_id += 1;
+        json!(self.next_id)
+    }
+
+    /// Sends a message to a server, returning whether the server is still there to receive it.
+    async fn send_to_server(&self, index: usize, msg: Value) -> bool {
+        let Some(tx) = &self.upstreams[index].tx else {
+            return false;
+        };
+
+        tx.send(msg).await.is_ok()
+    }
+
+    /// Sends a message to the host, holding back the responses to a batch until every request in
+    /// it is answered.
+    async fn send_to_host(&mut self, msg: Value) {
+        let msg = match Message::from_json(msg.clone()).type_ {
+            MessageType::ResponseSuccess | MessageType::ResponseFailure => {
+                match self.answer_batch(&msg["id"].clone(), Some(msg)) {
+                    Some(msg) => msg,
+                    None => return,
+                }
+            }
+            _ => msg,
+        };
+
+        self.transmit_to_host(msg).await;
+    }
+
+    /// Notes the answer to a host request, `None` if it was cancelled. Returns the message to send
+    /// the host: the response itself, or the responses to the request's batch once every request
+    /// in it is answered.
+    fn answer_batch(&mut self, id: &Value, response: Option<Value>) -> Option<Value> {
+        let Some(position) = self
+            .batches
+            .iter()
+            .position(|batch| batch.awaiting.contains(id))
+        else {
+            return response;
+        };
+
+        let batch = &mut self.batches[position];
+        batch.awaiting.remove(id);
+        batch.responses.extend(response);
+        if !batch.awaiting.is_empty() {
+            return None;
+        }
+
+        let batch = self.batches.remove(position);
+        (!batch.responses.is_empty()).then_some(Value::Array(batch.responses))
+    }
+
+    async fn transmit_to_host(&self, msg: Value) {
+        if let Err(e) = self.host_tx.send(msg).await {
+            log::error!("Failed to send message to host buffer: {e}");
+        }
+    }
+}
+
+/// Key of the items in the result of a list method.
+fn list_key(method: &str) -> Option<&'static str> {
+    match method {
+        "tools/list" => Some("tools"),
+        "prompts/list" => Some("prompts"),
+        "resources/list" => Some("resources"),
+        "resources/templates/list" => Some("resourceTemplates"),
+        _ => None,
+    }
+}
+
+/// Merges the capabilities of a server into those of the aggregate: a capability is supported if
+/// any server supports it.
+fn merge_capabilities(into: &mut Map<String, Value>, from: &Map<String, Value>) {
+    for (key, value) in from {
+        match (into.get_mut(key), value) {
+            (Some(Value::Object(into)), Value::Object(from)) => merge_capabilities(into, from),
+            (Some(Value::Bool(into)), Value::Bool(from)) => *into |= from,
+            (Some(_), _) => {}
+            (None, value) => {
+                into.insert(key.clone(), value.clone());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    struct Harness {
+        aggregator: Aggregator,
+        host_rx: mpsc::Receiver<Value>,
+        server_rxs: Vec<mpsc::Receiver<Value>>,
+    }
+
+    fn harness(prefixes: &[&str]) -> Harness {
+        let (host_tx, host_rx) = mpsc::channel(10);
+        let mut upstreams = Vec::new();
+        let mut server_rxs = Vec::new();
+
+        for prefix in prefixes {
+            let (tx, rx) = mpsc::channel(10);
+            upstreams.push(Upstream {
+                name: prefix.trim_end_matches('_').to_owned(),
+                prefix: (*prefix).to_owned(),
+                tx: Some(tx),
+            });
+            server_rxs.push(rx);
+        }
+
+        Harness {
+            aggregator: Aggregator::new("test".to_owned(), upstreams, host_tx),
+            host_rx,
+            server_rxs,
+        }
+    }
+
+    /// Has the host list the items of every server, each server listing its own `items`.
+    async fn list(h: &mut Harness, method: &str, items: &[Value]) -> Value {
+        let list_key = list_key(method).unwrap();
+        h.aggregator
+            .handle_host_message(json!({ "jsonrpc": "2.0", "id": "list", "method": method }))
+            .await;
+
+        for (index, items) in items.iter().enumerate() {
+            let request = h.server_rxs[index].try_recv().unwrap();
+            h.aggregator
+                .handle_server_message(
+                    index,
+                    json!({ "jsonrpc": "2.0", "id": request["id"], "result": { list_key: items } }),
+                )
+                .await;
+        }
+
+        h.host_rx.try_recv().unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_initialize() {
+        let mut h = harness(&["a__", "b__", "c__", "d__"]);
+
+        h.aggregator
+            .handle_host_message(json!({
+                "jsonrpc": "2.0",
+                "id": 0,
+                "method": "initialize",
+                "params": { "protocolVersion": "2025-03-26", "capabilities": {} }
+            }))
+            .await;
+
+        let responses = [
+            json!({
+                "result": {
+                    "protocolVersion": "2025-03-26",
+                    "capabilities": { "tools": { "listChanged": false } },
+                    "serverInfo": { "name": "a", "version": "1.0.0" },
+                    "instructions": "Use a."
+                }
+            }),
+            json!({
+                "result": {
+                    "protocolVersion": "2025-03-26",
+                    "capabilities": { "tools": { "listChanged": true }, "prompts": {} },
+                    "serverInfo": { "name": "b", "version": "1.0.0" }
+                }
+            }),
+            json!({
+                "result": {
+                    "protocolVersion": "2024-11-05",
+                    "capabilities": { "resources": {} },
+                    "serverInfo": { "name": "c", "version": "1.0.0" }
+                }
+            }),
+            json!({ "error": { "code": -32603, "message": "failed" } }),
+        ];
+        for (index, mut response) in responses.into_iter().enumerate() {
+            let request = h.server_rxs[index].try_recv().unwrap();
+            response["jsonrpc"] = json!("2.0");
+            response["id"] = request["id"].clone();
+            h.aggregator.handle_server_message(index, response).await;
+        }
+
+        assert_eq!(
+            h.host_rx.try_recv().unwrap(),
+            json!({
+                "jsonrpc": "2.0",
+                "id": 0,
+                "result": {
+                    "protocolVersion": "2025-03-26",
+                    "capabilities": { "tools": { "listChanged": true }, "prompts": {} },
+                    "serverInfo": { "name": "test", "version": env!("CARGO_PKG_VERSION") },
+                    "instructions": "a (a__): Use a."
+                }
+            })
+        );
+
+        // the server of another protocol version and the one that failed are no longer routed to
+        let routed = h
+            .aggregator
+            .upstreams
+            .iter()
+            .map(|upstream| upstream.tx.is_some())
+            .collect::<Vec<_>>();
+        assert_eq!(routed, [true, true, false, false]);
+    }
+
+    #[tokio::test]
+    async fn test_list_and_call_tools() {
+        let mut h = harness(&["a__", "b__"]);
+
+        h.aggregator
+            .handle_host_message(json!({ "jsonrpc": "2.0", "id": 1, "method": "tools/list" }))
+            .await;
+
+        let request = h.server_rxs[0].recv().await.unwrap();
+        h.aggregator
+            .handle_server_message(
+                0,
+                json!({
+                    "jsonrpc": "2.0",
+                    "id": request["id"],
+                    "result": { "tools": [{ "name": "echo" }], "nextCursor": "2" }
+                }),
+            )
+            .await;
+
+        // the next page is fetched before the host is answered
+        let request = h.server_rxs[0].recv().await.unwrap();
+        assert_eq!(request["params"]["cursor"], json!("2"));
+        h.aggregator
+            .handle_server_message(
+                0,
+                json!({
+                    "jsonrpc": "2.0",
+                    "id": request["id"],
+                    "result": { "tools": [{ "name": "sleep" }] }
+                }),
+            )
+            .await;
+
+        let request = h.server_rxs[1].recv().await.unwrap();
+        h.aggregator
+            .handle_server_message(
+                1,
+                json!({
+                    "jsonrpc": "2.0",
+                    "id": request["id"],
+                    "result": { "tools": [{ "name": "echo" }] }
+                }),
+            )
+            .await;
+
+        assert_eq!(
+            h.host_rx.recv().await.unwrap(),
+            json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "result": {
+                    "tools": [{ "name": "a__echo" }, { "name": "a__sleep" }, { "name": "b__echo" }]
+                }
+            })
+        );
+
+        h.aggregator
+            .handle_host_message(json!({
+                "jsonrpc": "2.0",
+                "id": "call",
+                "method": "tools/call",
+                "params": { "name": "b__echo", "arguments": {} }
+            }))
+            .await;
+
+        let request = h.server_rxs[1].recv().await.unwrap();
+        assert_eq!(request["params"]["name"], json!("echo"));
+
+        h.aggregator
+            .handle_server_message(
+                1,
+                json!({ "jsonrpc": "2.0", "id": request["id"], "result": { "content": [] } }),
+            )
+            .await;
+
+        assert_eq!(
+            h.host_rx.recv().await.unwrap(),
+            json!({ "jsonrpc": "2.0", "id": "call", "result": { "content": [] } })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_repeated_cursor() {
+        let mut h = harness(&["a__"]);
+
+        h.aggregator
+            .handle_host_message(json!({ "jsonrpc": "2.0", "id": 1, "method": "tools/list" }))
+            .await;
+
+        // the server hands out the same cursor again for the second page
+        for name in ["echo", "sleep"] {
+            let request = h.server_rxs[0].recv().await.unwrap();
+            h.aggregator
+                .handle_server_message(
+                    0,
+                    json!({
+                        "jsonrpc": "2.0",
+                        "id": request["id"],
+                        "result": { "tools": [{ "name": name }], "nextCursor": "2" }
+                    }),
+                )
+                .await;
+        }
+
+        assert!(h.server_rxs[0].try_recv().is_err());
+        assert_eq!(
+            h.host_rx.recv().await.unwrap(),
+            json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "result": { "tools": [{ "name": "a__echo" }, { "name": "a__sleep" }] }
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_server_requests() {
+        let mut h = harness(&["a__", "b__"]);
+
+        // both servers use the same id for their own requests
+        for index in 0..2 {
+            h.aggregator
+                .handle_server_message(
+                    index,
+                    json!({ "jsonrpc": "2.0", "id": 0, "method": "roots/list" }),
+                )
+                .await;
+        }
+
+        let first = h.host_rx.recv().await.unwrap();
+        let second = h.host_rx.recv().await.unwrap();
+        assert_ne!(first["id"], second["id"]);
+
+        h.aggregator
+            .handle_host_message(json!({ "jsonrpc": "2.0", "id": second["id"], "result": {} }))
+            .await;
+
+        assert_eq!(
+            h.server_rxs[1].recv().await.unwrap(),
+            json!({ "jsonrpc": "2.0", "id": 0, "result": {} })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_resources() {
+        let mut h = harness(&["a__", "b__"]);
+
+        let response = list(
+            &mut h,
+            "resources/list",
+            &[
+                json!([{ "uri": "file:///a.txt", "name": "a" }]),
+                json!([{ "uri": "file:///b.txt", "name": "b" }]),
+            ],
+        )
+        .await;
+        assert_eq!(response["result"]["resources"][1]["name"], json!("b__b"));
+
+        list(
+            &mut h,
+            "resources/templates/list",
+            &[
+                json!([{ "uriTemplate": "file:///{path}", "name": "files" }]),
+                json!([{ "uriTemplate": "file:///logs/{path}", "name": "logs" }]),
+            ],
+        )
+        .await;
+
+        // listed resources go to the server that listed them, others to the server with the most
+        // specific matching template
+        for (uri, index) in [
+            ("file:///a.txt", 0),
+            ("file:///b.txt", 1),
+            ("file:///logs/today", 1),
+            ("file:///c.txt", 0),
+        ] {
+            h.aggregator
+                .handle_host_message(json!({
+                    "jsonrpc": "2.0",
+                    "id": uri,
+                    "method": "resources/read",
+                    "params": { "uri": uri }
+                }))
+                .await;
+            assert_eq!(
+                h.server_rxs[index].try_recv().unwrap()["params"]["uri"],
+                json!(uri)
+            );
+        }
+
+        h.aggregator
+            .handle_host_message(json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "method": "resources/read",
+                "params": { "uri": "https://example.com" }
+            }))
+            .await;
+        assert_eq!(
+            h.host_rx.try_recv().unwrap()["error"]["code"],
+            json!(-32602)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_completion() {
+        let mut h = harness(&["a__", "b__"]);
+
+        list(
+            &mut h,
+            "prompts/list",
+            &[json!([]), json!([{ "name": "greet" }])],
+        )
+        .await;
+        list(
+            &mut h,
+            "resources/templates/list",
+            &[
+                json!([{ "uriTemplate": "file:///{path}", "name": "files" }]),
+                json!([]),
+            ],
+        )
+        .await;
+
+        h.aggregator
+            .handle_host_message(json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "method": "completion/complete",
+                "params": {
+                    "ref": { "type": "ref/prompt", "name": "b__greet" },
+                    "argument": { "name": "name", "value": "A" }
+                }
+            }))
+            .await;
+
+        let request = h.server_rxs[1].try_recv().unwrap();
+        assert_eq!(request["params"]["ref"]["name"], json!("greet"));
+        h.aggregator
+            .handle_server_message(
+                1,
+                json!({
+                    "jsonrpc": "2.0",
+                    "id": request["id"],
+                    "result": { "completion": { "values": ["Alice"] } }
+                }),
+            )
+            .await;
+        assert_eq!(
+            h.host_rx.try_recv().unwrap(),
+            json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "result": { "completion": { "values": ["Alice"] } }
+            })
+        );
+
+        h.aggregator
+            .handle_host_message(json!({
+                "jsonrpc": "2.0",
+                "id": 2,
+                "method": "completion/complete",
+                "params": {
+                    "ref": { "type": "ref/resource", "uri": "file:///{path}" },
+                    "argument": { "name": "path", "value": "a" }
+                }
+            }))
+            .await;
+        assert_eq!(
+            h.server_rxs[0].try_recv().unwrap()["params"]["ref"]["uri"],
+            json!("file:///{path}")
+        );
+
+        // a prompt not listed yet goes to the server of its prefix
+        h.aggregator
+            .handle_host_message(json!({
+                "jsonrpc": "2.0",
+                "id": 3,
+                "method": "completion/complete",
+                "params": {
+                    "ref": { "type": "ref/prompt", "name": "a__greet" },
+                    "argument": { "name": "name", "value": "A" }
+                }
+            }))
+            .await;
+        assert_eq!(
+            h.server_rxs[0].try_recv().unwrap()["params"]["ref"]["name"],
+            json!("greet")
+        );
+
+        h.aggregator
+            .handle_host_message(json!({
+                "jsonrpc": "2.0",
+                "id": 4,
+                "method": "completion/complete",
+                "params": {
+                    "ref": { "type": "ref/prompt", "name": "c__greet" },
+                    "argument": { "name": "name", "value": "A" }
+                }
+            }))
+            .await;
+        assert_eq!(
+            h.host_rx.try_recv().unwrap()["error"]["code"],
+            json!(-32602)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_cancel_fan_out() {
+        let mut h = harness(&["a__", "b__"]);
+
+        h.aggregator
+            .handle_host_message(json!({ "jsonrpc": "2.0", "id": 1, "method": "tools/list" }))
+            .await;
+        let requests = [
+            h.server_rxs[0].try_recv().unwrap(),
+            h.server_rxs[1].try_recv().unwrap(),
+        ];
+
+        h.aggregator
+            .handle_host_message(json!({
+                "jsonrpc": "2.0",
+                "method": "notifications/cancelled",
+                "params": { "requestId": 1 }
+            }))
+            .await;
+
+        // every server is told of the cancellation under its own id, and its response dropped
+        for (index, request) in requests.iter().enumerate() {
+            let notification = h.server_rxs[index].try_recv().unwrap();
+            assert_eq!(notification["params"]["requestId"], request["id"]);
+
+            h.aggregator
+                .handle_server_message(
+                    index,
+                    json!({ "jsonrpc": "2.0", "id": request["id"], "result": { "tools": [] } }),
+                )
+                .await;
+        }
+
+        assert!(h.host_rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_batch() {
+        let mut h = harness(&["a__", "b__"]);
+
+        list(
+            &mut h,
+            "tools/list",
+            &[json!([{ "name": "echo" }]), json!([])],
+        )
+        .await;
+
+        h.aggregator
+            .handle_host_message(json!([
+                {
+                    "jsonrpc": "2.0",
+                    "id": 1,
+                    "method": "tools/call",
+                    "params": { "name": "a__echo" }
+                },
+                { "jsonrpc": "2.0", "method": "notifications/roots/list_changed" },
+                { "jsonrpc": "2.0", "id": 2, "method": "ping" }
+            ]))
+            .await;
+
+        // the ping is answered along with the call
+        assert!(h.host_rx.try_recv().is_err());
+
+        let request = h.server_rxs[0].try_recv().unwrap();
+        h.aggregator
+            .handle_server_message(
+                0,
+                json!({ "jsonrpc": "2.0", "id": request["id"], "result": { "content": [] } }),
+            )
+            .await;
+
+        assert_eq!(
+            h.host_rx.try_recv().unwrap(),
+            json!([
+                { "jsonrpc": "2.0", "id": 2, "result": {} },
+                { "jsonrpc": "2.0", "id": 1, "result": { "content": [] } }
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_server_gone() {
+        let mut h = harness(&["a__", "b__"]);
+
+        list(
+            &mut h,
+            "tools/list",
+            &[json!([{ "name": "echo" }]), json!([])],
+        )
+        .await;
+
+        h.aggregator
+            .handle_host_message(json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "method": "tools/call",
+                "params": { "name": "a__echo" }
+            }))
+            .await;
+        h.server_rxs[0].recv().await.unwrap();
+
+        h.aggregator.handle_server_gone(0).await;
+        assert_eq!(
+            h.host_rx.recv().await.unwrap()["error"]["code"],
+            json!(-32603)
+        );
+
+        h.aggregator
+            .handle_host_message(json!({ "jsonrpc": "2.0", "id": 2, "method": "prompts/list" }))
+            .await;
+        let request = h.server_rxs[1].recv().await.unwrap();
+        h.aggregator
+            .handle_server_message(
+                1,
+                json!({
+                    "jsonrpc": "2.0",
+                    "id": request["id"],
+                    "result": { "prompts": [{ "name": "greet" }] }
+                }),
+            )
+            .await;
+
+        assert_eq!(
+            h.host_rx.recv().await.unwrap()["result"],
+            json!({ "prompts": [{ "name": "b__greet" }] })
+        );
+    }
+}
@@ -0,0 +1,511 @@
+use std::{process::ExitStatus, sync::Arc};
+
+use anyhow::{anyhow, bail, Result};
+use async_trait::async_trait;
+use serde_json::Value;
+use tokio::{
+    sync::{broadcast, mpsc, oneshot},
+    task,
+};
+use uuid::Uuid;
+
+use super::{
+    flush, http,
+    process::{spawn_mcp_server, McpServerProcess},
+    relay_messages,
+    transport::{Connection, Transport},
+    Context,
+};
+use crate::{
+    mcp_server::{McpServer, RemoteMcpServer},
+    message::{Message, MessageDirection},
+    message_interceptor::{
+        record::{RecordedAction, SessionRecorder},
+        MessageInterceptor, MessageInterceptorAction,
+    },
+    server_log::ServerLogWriter,
+};
+
+/// How many session events are kept for subscribers that fall behind.
+const EVENT_CAPACITY: usize = 256;
+
+/// How the proxy reaches the MCP server it proxies.
+// Built once per proxy, so the size of `McpServer` doesn't matter.
+#[allow(clippy::large_enum_variant)]
+pub enum ServerTransport {
+    /// Spawns and supervises an MCP server process
+    Process(McpServer),
+    /// Connects to a remote MCP server over HTTP
+    Remote(RemoteMcpServer),
+    /// Exchanges messages with an MCP server over any other transport
+    Transport(Transport),
+}
+
+impl From<Transport> for ServerTransport {
+    fn from(transport: Transport) -> Self {
+        Self::Transport(transport)
+    }
+}
+
+/// Something that happened during a proxied session.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SessionEvent {
+    /// A message was intercepted, and `action` taken on it
+    Message {
+        direction: MessageDirection,
+        message: Value,
+        action: RecordedAction,
+    },
+    /// The host closed its transport
+    HostClosed,
+    /// The MCP server closed its transport, or its process exited for good
+    ServerClosed { status: Option<ExitStatus> },
+    /// The proxy was shut down through its handle
+    ShutDown,
+}
+
+/// Builds a proxy between a host and an MCP server, each reached over a transport of its own,
+/// running every message through a message interceptor.
+///
+/// ```no_run
+/// # use std::sync::Arc;
+/// # use mcp_guardian_core::{
+/// #     message_interceptor::MessageInterceptor,
+/// #     proxy::{builder::{ProxyBuilder, ServerTransport}, transport::Transport},
+/// # };
+/// # async fn example(
+/// #     message_interceptor: Arc<dyn MessageInterceptor>,
+/// #     mcp_server: mcp_guardian_core::mcp_server::McpServer,
+/// #     host: tokio::net::TcpStream,
+/// # ) -> anyhow::Result<()> {
+/// let (reader, writer) = host.into_split();
+/// let proxy = ProxyBuilder::new("time-server", message_interceptor)
+///     .host(Transport::stream(reader, writer))
+///     .server(ServerTransport::Process(mcp_server))
+///     .spawn()?;
+///
+/// proxy.shutdown().await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct ProxyBuilder {
+    mcp_server_name: String,
+    host_session_id: Option<String>,
+    message_interceptor: Arc<dyn MessageInterceptor>,
+    recorder: Option<Arc<SessionRecorder>>,
+    host: Transport,
+    server: Option<ServerTransport>,
+    events_tx: broadcast::Sender<SessionEvent>,
+}
+
+impl ProxyBuilder {
+    /// Starts building a proxy for the MCP server named `mcp_server_name`. The host is reached
+    /// over stdio unless another transport is given.
+    pub fn new(
+        mcp_server_name: impl Into<String>,
+        message_interceptor: Arc<dyn MessageInterceptor>,
+    ) -> Self {
+        Self {
+            mcp_server_name: mcp_server_name.into(),
+            host_session_id: None,
+            message_interceptor,
+            recorder: None,
+            host: Transport::Stdio,
+            server: None,
+            events_tx: broadcast::channel(EVENT_CAPACITY).0,
+        }
+    }
+
+    pub fn host_session_id(mut self, host_session_id: Option<String>) -> Self {
+        self.host_session_id = host_session_id;
+        self
+    }
+
+    /// Records the session to a transcript with `recorder`, if given.
+    pub fn recorder(mut self, recorder: Option<Arc<SessionRecorder>>) -> Self {
+        self.recorder = recorder;
+        self
+    }
+
+    pub fn host(mut self, host: Transport) -> Self {
+        self.host = host;
+        self
+    }
+
+    pub fn server(mut self, server: impl Into<ServerTransport>) -> Self {
+        self.server = Some(server.into());
+        self
+    }
+
+    /// Subscribes to the events of the session, from the moment the proxy is spawned.
+    pub fn subscribe(&self) -> broadcast::Receiver<SessionEvent> {
+        self.events_tx.subscribe()
+    }
+
+    /// Spawns the proxy on the current tokio runtime.
+    pub fn spawn(self) -> Result<ProxyHandle> {
+        let Self {
+            mcp_server_name,
+            host_session_id,
+            message_interceptor,
+            recorder,
+            host,
+            server,
+            events_tx,
+        } = self;
+
+        let Some(server) = server else {
+            bail!("No MCP server transport given.");
+        };
+        if let ServerTransport::Transport(Transport::Stdio) = server {
+            bail!("The stdio transport can only reach the host.");
+        }
+
+        let ctx = Context {
+            mcp_server_name,
+            host_session_id,
+            session_id: Uuid::new_v4().to_string(),
+            message_interceptor: Arc::new(EventInterceptor {
+                message_interceptor,
+                events_tx: events_tx.clone(),
+            }),
+        }
+        .record(recorder);
+        let session_id = ctx.session_id.clone();
+
+        let (upstream_tx, inbound_rx, upstream) = connect_server(&ctx, server)?;
+
+        let host = host.connect("host");
+        let host_reader = host.reader.abort_handle();
+        let (host_closed_tx, host_closed_rx) = oneshot::channel();
+        let relay_task = task::spawn(relay_messages(
+            Arc::new(ctx),
+            host,
+            upstream_tx,
+            inbound_rx,
+            host_closed_tx,
+        ));
+
+        let (shutdown_tx, shutdown_rx) = oneshot::channel();
+        let run_events_tx = events_tx.clone();
+        let task = task::spawn(async move {
+            let res = run(upstream, host_closed_rx, shutdown_rx, &run_events_tx).await;
+            flush(relay_task).await;
+            host_reader.abort();
+            res
+        });
+
+        Ok(ProxyHandle {
+            session_id,
+            shutdown_tx,
+            task,
+            events_tx,
+        })
+    }
+}
+
+/// Handle to a running proxy.
+pub struct ProxyHandle {
+    session_id: String,
+    shutdown_tx: oneshot::Sender<()>,
+    task: task::JoinHandle<Result<Option<ExitStatus>>>,
+    events_tx: broadcast::Sender<SessionEvent>,
+}
+
+impl ProxyHandle {
+    pub fn session_id(&self) -> &str {
+        &self.session_id
+    }
+
+    /// Subscribes to the events of the session from now on.
+    pub fn subscribe(&self) -> broadcast::Receiver<SessionEvent> {
+        self.events_tx.subscribe()
+    }
+
+    /// Waits for the session to end, once the host or the MCP server closes its transport.
+    ///
+    /// Returns the exit status of the MCP server process, if the proxy spawned one.
+    pub async fn wait(self) -> Result<Option<ExitStatus>> {
+        self.task.await?
+    }
+
+    /// Waits for the session to end, shutting the MCP server down if `shutdown_rx` receives first.
+    /// A closed `shutdown_rx` is ignored.
+    ///
+    /// Returns the exit status of the MCP server process, if the proxy spawned one.
+    pub async fn wait_or_shutdown(
+        mut self,
+        shutdown_rx: oneshot::Receiver<()>,
+    ) -> Result<Option<ExitStatus>> {
+        tokio::select! {
+            res = &mut self.task => return res?,
+            Ok(()) = shutdown_rx => {}
+        }
+
+        self.shutdown().await
+    }
+
+    /// Shuts the MCP server down and waits for the session to end.
+    ///
+    /// Returns the exit status of the MCP server process, if the proxy spawned one.
+    pub async fn shutdown(self) -> Result<Option<ExitStatus>> {
+        let _ = self.shutdown_tx.send(());
+        self.task.await?
+    }
+}
+
+/// The MCP server end of a proxy.
+enum Upstream {
+    Process(McpServerProcess),
+    Remote(task::JoinHandle<Result<()>>),
+    Transport {
+        reader: task::JoinHandle<()>,
+        writer: task::JoinHandle<()>,
+    },
+}
+
+fn connect_server(
+    ctx: &Context,
+    server: ServerTransport,
+) -> Result<(mpsc::Sender<Value>, mpsc::Receiver<Value>, Upstream)> {
+    match server {
+        ServerTransport::Process(mcp_server) => {
+            log::info!(
+                "Starting proxy for: {} {:?}",
+                mcp_server.cmd,
+                mcp_server.args
+            );
+
+            let stderr_log = ServerLogWriter::create_or_inherit(
+                &ctx.mcp_server_name,
+                &ctx.session_id,
+                &mcp_server.stderr_log.clone().unwrap_or_default(),
+            );
+
+            let (upstream_tx, inbound_rx, process) = spawn_mcp_server(&mcp_server, stderr_log)?;
+
+            Ok((upstream_tx, inbound_rx, Upstream::Process(process)))
+        }
+        ServerTransport::Remote(remote) => {
+            log::info!(
+                "Starting proxy for: {} ({} transport)",
+                remote.url,
+                remote.transport
+            );
+
+            // Upstream Message Buffer
+            let (upstream_tx, upstream_rx) = mpsc::channel::<Value>(100);
+            // Inbound Message Buffer
+            let (inbound_tx, inbound_rx) = mpsc::channel::<Value>(100);
+
+            log::info!("Starting remote connection");
+            let task = task::spawn(http::run_client(remote, upstream_rx, inbound_tx));
+
+            Ok((upstream_tx, inbound_rx, Upstream::Remote(task)))
+        }
+        ServerTransport::Transport(transport) => {
+            log::info!("Starting proxy for: {}", ctx.mcp_server_name);
+
+            let Connection {
+                tx,
+                rx,
+                reader,
+                writer,
+            } = transport.connect("MCP server");
+
+            Ok((tx, rx, Upstream::Transport { reader, writer }))
+        }
+    }
+}
+
+/// Runs the session until the host or the MCP server closes its transport, or the proxy is shut
+/// down.
+async fn run(
+    mut upstream: Upstream,
+    host_closed_rx: oneshot::Receiver<()>,
+    shutdown_rx: oneshot::Receiver<()>,
+    events_tx: &broadcast::Sender<SessionEvent>,
+) -> Result<Option<ExitStatus>> {
+    let shut_down = tokio::select! {
+        Ok(()) = host_closed_rx => {
+            let _ = events_tx.send(SessionEvent::HostClosed);
+            false
+        }
+        Ok(()) = shutdown_rx => {
+            let _ = events_tx.send(SessionEvent::ShutDown);
+            true
+        }
+        res = upstream.wait() => {
+            let status = res?;
+            let _ = events_tx.send(SessionEvent::ServerClosed { status });
+            return Ok(status);
+        }
+    };
+
+    match upstream {
+        Upstream::Process(process) => {
+            log::info!("Shutting down MCP server process.");
+            process.shutdown().await.map(Some)
+        }
+        // Closing the upstream buffer ends the connection once the host is gone.
+        Upstream::Remote(task) if shut_down => {
+            task.abort();
+            Ok(None)
+        }
+        Upstream::Remote(_) => Ok(None),
+        Upstream::Transport { reader, writer } if shut_down => {
+            reader.abort();
+            writer.abort();
+            Ok(None)
+        }
+        Upstream::Transport { .. } => Ok(None),
+    }
+}
+
+impl Upstream {
+    /// Waits for the MCP server to close its end of the connection on its own.
+    async fn wait(&mut self) -> Result<Option<ExitStatus>> {
+        match self {
+            Self::Process(process) => process.wait().await.map(Some),
+            Self::Remote(task) => match task.await {
+                Ok(Ok(())) => {
+                    log::info!("Remote MCP server closed the connection.");
+                    Ok(None)
+                }
+                Ok(Err(e)) => {
+                    log::error!("Remote connection failed: {e}");
+                    Err(e)
+                }
+                Err(e) => {
+                    log::error!("Remote connection task failed: {e}");
+                    Err(anyhow!("Remote connection task failed: {e}"))
+                }
+            },
+            Self::Transport { reader, .. } => {
+                let _ = reader.await;
+                log::info!("Upstream MCP server closed the connection.");
+                Ok(None)
+            }
+        }
+    }
+}
+
+/// Reports every message intercepted by `message_interceptor` as a session event.
+struct EventInterceptor {
+    message_interceptor: Arc<dyn MessageInterceptor>,
+    events_tx: broadcast::Sender<SessionEvent>,
+}
+
+#[async_trait]
+impl MessageInterceptor for EventInterceptor {
+    async fn intercept_message(
+        &self,
+        direction: MessageDirection,
+        message: Message,
+    ) -> Result<MessageInterceptorAction> {
+        let raw_msg = message.raw_msg.clone();
+
+        let action = self
+            .message_interceptor
+            .intercept_message(direction, message)
+            .await?;
+
+        if self.events_tx.receiver_count() > 0 {
+            let _ = self.events_tx.send(SessionEvent::Message {
+                direction,
+                action: RecordedAction::from_action(&raw_msg, &action),
+                message: raw_msg,
+            });
+        }
+
+        Ok(action)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::*;
+    use crate::message_interceptor::MessageInterceptorAction::Send;
+
+    /// Drops `tools/call` requests and sends everything else on.
+    struct DropToolCallsInterceptor;
+
+    #[async_trait]
+    impl MessageInterceptor for DropToolCallsInterceptor {
+        async fn intercept_message(
+            &self,
+            _direction: MessageDirection,
+            message: Message,
+        ) -> Result<MessageInterceptorAction> {
+            Ok(match message.raw_msg["method"].as_str() {
+                Some("tools/call") => MessageInterceptorAction::Drop,
+                _ => Send(message),
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_proxy_in_memory() {
+        let (host_tx, proxy_host_rx) = mpsc::channel(10);
+        let (proxy_host_tx, mut host_rx) = mpsc::channel(10);
+        let (server_host, server_proxy) = tokio::io::duplex(1024);
+        let (server_reader, server_writer) = tokio::io::split(server_proxy);
+
+        let builder = ProxyBuilder::new("test", Arc::new(DropToolCallsInterceptor))
+            .host(Transport::channel(proxy_host_tx, proxy_host_rx))
+            .server(Transport::stream(server_reader, server_writer));
+        let mut events = builder.subscribe();
+        let proxy = builder.spawn().unwrap();
+
+        // Echoes requests back as responses, as the MCP server
+        task::spawn(async move {
+            use tokio::io::{AsyncBufReadExt, AsyncWriteExt};
+
+            let (reader, mut writer) = tokio::io::split(server_host);
+            let mut lines = tokio::io::BufReader::new(reader).lines();
+            while let Some(line) = lines.next_line().await.unwrap() {
+                let request = serde_json::from_str::<Value>(&line).unwrap();
+                let response = json!({"jsonrpc": "2.0", "id": request["id"], "result": {}});
+                writer
+                    .write_all(format!("{response}\n").as_bytes())
+                    .await
+                    .unwrap();
+            }
+        });
+
+        host_tx
+            .send(json!({"jsonrpc": "2.0", "id": 1, "method": "tools/call"}))
+            .await
+            .unwrap();
+        host_tx
+            .send(json!({"jsonrpc": "2.0", "id": 2, "method": "tools/list"}))
+            .await
+            .unwrap();
+        assert_eq!(
+            host_rx.recv().await.unwrap(),
+            json!({"jsonrpc": "2.0", "id": 2, "result": {}})
+        );
+
+        let mut actions = Vec::new();
+        for _ in 0..3 {
+            match events.recv().await.unwrap() {
+                SessionEvent::Message { action, .. } => actions.push(action),
+                event => panic!("unexpected event: {event:?}"),
+            }
+        }
+        assert_eq!(
+            actions,
+            [
+                RecordedAction::Drop,
+                RecordedAction::Send { message: None },
+                RecordedAction::Send { message: None },
+            ]
+        );
+
+        assert_eq!(proxy.shutdown().await.unwrap(), None);
+        assert_eq!(events.recv().await.unwrap(), SessionEvent::ShutDown);
+        assert_eq!(host_rx.recv().await, None);
+    }
+}
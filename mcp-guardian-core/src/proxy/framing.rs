@@ -0,0 +1,211 @@
+use std::io;
+
+use serde_json::Value;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt};
+
+/// Largest message accepted, in bytes. Longer lines are skipped.
+pub(crate) const MAX_MESSAGE_SIZE: usize = 32 * 1024 * 1024;
+
+/// How much of a skipped line is logged.
+const LOGGED_LINE_PREVIEW: usize = 1024;
+
+/// Reads json-rpc messages framed as the MCP stdio transport specifies: one per line.
+///
+/// Lines that aren't a json-rpc message, such as banners printed by a server, are logged and
+/// skipped, as are lines longer than the maximum message size. Reading resumes at the next line
+/// either way, so a single bad line can't end the session.
+pub(crate) struct MessageReader<R> {
+    reader: R,
+    source: &'static str,
+    max_message_size: usize,
+}
+
+impl<R: AsyncBufRead + Unpin> MessageReader<R> {
+    /// Creates a reader of messages from `source`, which names it in logs.
+    pub(crate) fn new(reader: R, source: &'static str) -> Self {
+        Self {
+            reader,
+            source,
+            max_message_size: MAX_MESSAGE_SIZE,
+        }
+    }
+
+    /// Reads the next message, returning `None` once the reader is exhausted.
+    pub(crate) async fn next_message(&mut self) -> io::Result<Option<Value>> {
+        loop {
+            let Some(line) = self.next_line().await? else {
+                return Ok(None);
+            };
+
+            let line = line.strip_suffix(b"\r").unwrap_or(&line);
+            if line.iter().all(u8::is_ascii_whitespace) {
+                continue;
+            }
+
+            match serde_json::from_slice::<Value>(line) {
+                Ok(msg @ (Value::Object(_) | Value::Array(_))) => return Ok(Some(msg)),
+                Ok(_) => log::warn!(
+                    "Skipping non-message line from {}: {}",
+                    self.source,
+                    preview(line)
+                ),
+                Err(e) => log::warn!(
+                    "Skipping malformed line from {} ({e}): {}",
+                    self.source,
+                    preview(line)
+                ),
+            }
+        }
+    }
+
+    /// Reads the next line, without its newline, skipping lines over the maximum message size.
+    async fn next_line(&mut self) -> io::Result<Option<Vec<u8>>> {
+        let mut line = Vec::new();
+        let mut skipped = 0;
+
+        loop {
+            let available = self.reader.fill_buf().await?;
+
+            if available.is_empty() {
+                return Ok((!line.is_empty()).then_some(line));
+            }
+
+            let (chunk, consumed, complete) = match available.iter().position(|b| *b == b'\n') {
+                Some(i) => (&available[..i], i + 1, true),
+                None => (available, available.len(), false),
+            };
+
+            if skipped == 0 && line.len() + chunk.len() <= self.max_message_size {
+                line.extend_from_slice(chunk);
+            } else {
+                skipped += line.len() + chunk.len();
+                line.clear();
+            }
+
+            self.reader.consume(consumed);
+
+            if complete {
+                if skipped == 0 {
+                    return Ok(Some(line));
+                }
+
+                log::warn!(
+                    "Skipping {skipped} byte line from {}, over the maximum message size of {} bytes",
+                    self.source,
+                    self.max_message_size
+                );
+                skipped = 0;
+            }
+        }
+    }
+}
+
+/// Reads the next line from `reader`, without its newline, keeping at most `max_len` bytes of it.
+///
+/// Returns the line and how many bytes were cut off its end, or `None` once the reader is
+/// exhausted.
+pub(crate) async fn read_truncated_line<R: AsyncBufRead + Unpin>(
+    reader: &mut R,
+    max_len: usize,
+) -> io::Result<Option<(Vec<u8>, usize)>> {
+    let mut line = Vec::new();
+    let mut truncated = 0;
+
+    loop {
+        let available = reader.fill_buf().await?;
+
+        if available.is_empty() {
+            return Ok((!line.is_empty() || truncated > 0).then_some((line, truncated)));
+        }
+
+        let (chunk, consumed, complete) = match available.iter().position(|b| *b == b'\n') {
+            Some(i) => (&available[..i], i + 1, true),
+            None => (available, available.len(), false),
+        };
+
+        let kept = chunk.len().min(max_len - line.len());
+        line.extend_from_slice(&chunk[..kept]);
+        truncated += chunk.len() - kept;
+
+        reader.consume(consumed);
+
+        if complete {
+            return Ok(Some((line, truncated)));
+        }
+    }
+}
+
+fn preview(line: &[u8]) -> String {
+    let line = String::from_utf8_lossy(line);
+
+    match line.char_indices().nth(LOGGED_LINE_PREVIEW) {
+        Some((i, _)) => format!("{}...", &line[..i]),
+        None => line.into_owned(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+    use tokio::io::BufReader;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_resync() {
+        let input = [
+            "Server v1.0 starting...",
+            r#"{"jsonrpc":"2.0","id":1,"result":{}}"#,
+            "",
+            r#"{"jsonrpc":"2.0","id":2,"res"#,
+            "42",
+            &format!(
+                r#"{{"jsonrpc":"2.0","id":3,"result":"{}"}}"#,
+                "x".repeat(100)
+            ),
+            "[{\"jsonrpc\":\"2.0\",\"id\":4,\"result\":{}}]\r",
+            r#"{"jsonrpc":"2.0","id":5,"result":{}}"#,
+        ]
+        .join("\n");
+
+        // a small buffer, so lines span several reads
+        let mut reader = MessageReader::new(BufReader::with_capacity(8, input.as_bytes()), "test");
+        reader.max_message_size = 64;
+
+        assert_eq!(
+            reader.next_message().await.unwrap(),
+            Some(json!({ "jsonrpc": "2.0", "id": 1, "result": {} }))
+        );
+        assert_eq!(
+            reader.next_message().await.unwrap(),
+            Some(json!([{ "jsonrpc": "2.0", "id": 4, "result": {} }]))
+        );
+        // the last line doesn't need a newline
+        assert_eq!(
+            reader.next_message().await.unwrap(),
+            Some(json!({ "jsonrpc": "2.0", "id": 5, "result": {} }))
+        );
+        assert_eq!(reader.next_message().await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_read_truncated_line() {
+        let input = format!("short\r\n{}\n\nlast", "x".repeat(20));
+        let mut reader = BufReader::with_capacity(4, input.as_bytes());
+
+        let mut lines = Vec::new();
+        while let Some(line) = read_truncated_line(&mut reader, 8).await.unwrap() {
+            lines.push(line);
+        }
+
+        assert_eq!(
+            lines,
+            [
+                (b"short\r".to_vec(), 0),
+                (b"xxxxxxxx".to_vec(), 12),
+                (Vec::new(), 0),
+                (b"last".to_vec(), 0),
+            ]
+        );
+    }
+}
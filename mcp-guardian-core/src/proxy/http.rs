@@ -0,0 +1,883 @@
+use std::{collections::VecDeque, sync::Arc};
+
+use anyhow::{anyhow, bail, Result};
+use futures_util::{Stream, StreamExt};
+use reqwest::{
+    header::{HeaderMap, HeaderName, HeaderValue, ACCEPT, CONTENT_TYPE},
+    Client, Response, StatusCode, Url,
+};
+use serde_json::{json, Value};
+use tokio::{
+    sync::{mpsc, oneshot, Mutex},
+    task,
+};
+
+use crate::mcp_server::{RemoteMcpServer, RemoteTransport};
+
+pub(crate) static SESSION_ID_HEADER: &str = "mcp-session-id";
+static PROTOCOL_VERSION_HEADER: &str = "mcp-protocol-version";
+
+/// Id of the `initialize` request replayed to start a new session once a remote MCP session expires.
+const REPLAY_INITIALIZE_ID: &str = "mcp-guardian-replay-initialize";
+
+/// Connects to a remote MCP server, sending every message received on `upstream_rx` to the server
+/// and every message received from the server to `inbound_tx`.
+///
+/// Returns once `upstream_rx` is closed or the connection to the server is lost.
+pub async fn run_client(
+    remote: RemoteMcpServer,
+    upstream_rx: mpsc::Receiver<Value>,
+    inbound_tx: mpsc::Sender<Value>,
+) -> Result<()> {
+    let RemoteMcpServer {
+        url,
+        headers,
+        transport,
+    } = remote;
+
+    let url = Url::parse(&url)?;
+
+    let mut header_map = HeaderMap::new();
+    for (name, value) in headers {
+        header_map.insert(
+            HeaderName::from_bytes(name.as_bytes())?,
+            HeaderValue::from_str(&value)?,
+        );
+    }
+
+    let client = Client::builder().default_headers(header_map).build()?;
+
+    match transport {
+        RemoteTransport::StreamableHttp => {
+            run_streamable_http_client(client, url, upstream_rx, inbound_tx).await
+        }
+        RemoteTransport::Sse => run_sse_client(client, url, upstream_rx, inbound_tx).await,
+    }
+}
+
+struct StreamableHttpSession {
+    client: Client,
+    url: Url,
+    session_id: Mutex<Option<String>>,
+    protocol_version: Mutex<Option<String>>,
+    /// The host's `initialize` request, replayed if the session expires
+    initialize: Mutex<Option<Value>>,
+    /// The host's `notifications/initialized` notification, replayed if the session expires
+    initialized: Mutex<Option<Value>>,
+}
+
+impl StreamableHttpSession {
+    async fn headers(&self) -> Result<HeaderMap> {
+        let mut headers = HeaderMap::new();
+
+        if let Some(session_id) = self.session_id.lock().await.as_deref() {
+            headers.insert(SESSION_ID_HEADER, HeaderValue::from_str(session_id)?);
+        }
+        if let Some(protocol_version) = self.protocol_version.lock().await.as_deref() {
+            headers.insert(
+                PROTOCOL_VERSION_HEADER,
+                HeaderValue::from_str(protocol_version)?,
+            );
+        }
+
+        Ok(headers)
+    }
+
+    /// Posts a message to the server. If the server no longer knows the session, a new one is
+    /// started by replaying the host's initialize handshake, and the message is posted again.
+    async fn post(&self, msg: &Value) -> Result<Response> {
+        let response = self.send(msg).await?;
+
+        if response.status() != StatusCode::NOT_FOUND || self.session_id.lock().await.is_none() {
+            return Ok(response.error_for_status()?);
+        }
+
+        log::warn!("Remote MCP session has expired.");
+        *self.session_id.lock().await = None;
+
+        if msg.get("method") != Some(&json!("initialize")) {
+            self.reinitialize().await.map_err(|e| {
+                anyhow!("Remote MCP session has expired and could not be initialized again: {e}")
+            })?;
+        }
+
+        Ok(self.send(msg).await?.error_for_status()?)
+    }
+
+    async fn send(&self, msg: &Value) -> Result<Response> {
+        let response = self
+            .client
+            .post(self.url.clone())
+            .headers(self.headers().await?)
+            .header(ACCEPT, "application/json, text/event-stream")
+            .json(msg)
+            .send()
+            .await?;
+
+        if let Some(session_id) = response.headers().get(SESSION_ID_HEADER) {
+            let session_id = session_id.to_str()?.to_owned();
+            let mut current = self.session_id.lock().await;
+            if current.as_ref() != Some(&session_id) {
+                log::info!("Remote MCP session id: {session_id}");
+                *current = Some(session_id);
+            }
+        }
+
+        Ok(response)
+    }
+
+    /// Starts a new session by replaying the host's initialize handshake. The server's response
+    /// to the replayed `initialize` request isn't forwarded, as the host has already been answered.
+    async fn reinitialize(&self) -> Result<()> {
+        let Some(mut initialize) = self.initialize.lock().await.clone() else {
+            bail!("The host has not sent an initialize request.");
+        };
+
+        log::info!("Replaying initialize handshake to remote MCP server.");
+        initialize["id"] = json!(REPLAY_INITIALIZE_ID);
+
+        let response = self.send(&initialize).await?.error_for_status()?;
+
+        // the replayed response is picked out of the messages the server streams back
+        let (replay_tx, mut replay_rx) = mpsc::channel(1);
+        let reading = async move { self.handle_response(response, &replay_tx).await };
+        tokio::pin!(reading);
+        let mut read = false;
+
+        loop {
+            tokio::select! {
+                msg = replay_rx.recv() => match msg {
+                    Some(msg) if msg.get("id") == Some(&json!(REPLAY_INITIALIZE_ID)) => {
+                        if let Some(error) = msg.get("error") {
+                            bail!("Remote MCP server rejected the replayed initialize request: {error}");
+                        }
+                        break;
+                    }
+                    Some(_) => {}
+                    None => bail!("Remote MCP server did not answer the replayed initialize request."),
+                },
+                res = &mut reading, if !read => {
+                    res?;
+                    read = true;
+                }
+            }
+        }
+
+        if let Some(initialized) = self.initialized.lock().await.clone() {
+            self.send(&initialized).await?.error_for_status()?;
+        }
+
+        Ok(())
+    }
+
+    async fn handle_response(
+        &self,
+        response: Response,
+        inbound_tx: &mpsc::Sender<Value>,
+    ) -> Result<()> {
+        if response.status() == StatusCode::ACCEPTED {
+            return Ok(());
+        }
+
+        let content_type = response
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|content_type| content_type.to_str().ok())
+            .unwrap_or_default()
+            .to_owned();
+
+        if content_type.starts_with("text/event-stream") {
+            let mut events = event_stream(response);
+            while let Some(event) = events.next_event().await? {
+                if event.event == "message" {
+                    self.forward(serde_json::from_str(&event.data)?, inbound_tx)
+                        .await?;
+                }
+            }
+        } else {
+            let body = response.bytes().await?;
+            if !body.is_empty() {
+                self.forward(serde_json::from_slice(&body)?, inbound_tx)
+                    .await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn forward(&self, msg: Value, inbound_tx: &mpsc::Sender<Value>) -> Result<()> {
+        // the negotiated protocol version must accompany every request after initialization
+        if let Some(protocol_version) = msg
+            .pointer("/result/protocolVersion")
+            .and_then(Value::as_str)
+        {
+            *self.protocol_version.lock().await = Some(protocol_version.to_owned());
+        }
+
+        inbound_tx.send(msg).await?;
+
+        Ok(())
+    }
+
+    /// Opens the optional stream the server may use to send requests and notifications that
+    /// aren't associated with a client request.
+    async fn listen(&self, inbound_tx: &mpsc::Sender<Value>) -> Result<()> {
+        let response = self
+            .client
+            .get(self.url.clone())
+            .headers(self.headers().await?)
+            .header(ACCEPT, "text/event-stream")
+            .send()
+            .await?;
+
+        if response.status() == StatusCode::METHOD_NOT_ALLOWED {
+            log::info!("Remote MCP server does not offer a server-initiated message stream.");
+            return Ok(());
+        }
+
+        self.handle_response(response.error_for_status()?, inbound_tx)
+            .await
+    }
+
+    async fn terminate(&self) -> Result<()> {
+        if self.session_id.lock().await.is_none() {
+            return Ok(());
+        }
+
+        log::info!("Terminating remote MCP session");
+        self.client
+            .delete(self.url.clone())
+            .headers(self.headers().await?)
+            .send()
+            .await?;
+
+        Ok(())
+    }
+}
+
+async fn run_streamable_http_client(
+    client: Client,
+    url: Url,
+    mut upstream_rx: mpsc::Receiver<Value>,
+    inbound_tx: mpsc::Sender<Value>,
+) -> Result<()> {
+    let session = Arc::new(StreamableHttpSession {
+        client,
+        url,
+        session_id: Mutex::new(None),
+        protocol_version: Mutex::new(None),
+        initialize: Mutex::new(None),
+        initialized: Mutex::new(None),
+    });
+
+    let mut listening = false;
+
+    while let Some(msg) = upstream_rx.recv().await {
+        let initialized = msg.get("method") == Some(&json!("notifications/initialized"));
+        if initialized {
+            *session.initialized.lock().await = Some(msg.clone());
+        } else if msg.get("method") == Some(&json!("initialize")) {
+            *session.initialize.lock().await = Some(msg.clone());
+        }
+
+        // requests are sent in order, but their responses are streamed back concurrently
+        let response = match session.post(&msg).await {
+            Ok(response) => response,
+            Err(e) => {
+                log::error!("Failed to send message to remote MCP server: {e}");
+                if let Some(error_response) = error_response(&msg, &e.to_string()) {
+                    inbound_tx.send(error_response).await?;
+                }
+                continue;
+            }
+        };
+
+        let session_clone = session.clone();
+        let inbound_tx_clone = inbound_tx.clone();
+        task::spawn(async move {
+            if let Err(e) = session_clone
+                .handle_response(response, &inbound_tx_clone)
+                .await
+            {
+                log::error!("Failed to read response from remote MCP server: {e}");
+                if let Some(error_response) = error_response(&msg, &e.to_string()) {
+                    let _ = inbound_tx_clone.send(error_response).await;
+                }
+            }
+        });
+
+        if initialized && !listening {
+            listening = true;
+
+            let session_clone = session.clone();
+            let inbound_tx_clone = inbound_tx.clone();
+            task::spawn(async move {
+                if let Err(e) = session_clone.listen(&inbound_tx_clone).await {
+                    log::error!("Server-initiated message stream failed: {e}");
+                }
+            });
+        }
+    }
+
+    session.terminate().await
+}
+
+/// Resolves the message endpoint a remote MCP server sent against the url of its event stream.
+/// Endpoints on another origin are rejected, since messages are posted along with the configured
+/// headers, which may hold credentials.
+fn resolve_endpoint(url: &Url, endpoint: &str) -> Result<Url> {
+    let endpoint = url.join(endpoint)?;
+    if endpoint.origin() != url.origin() {
+        bail!("Remote MCP server sent a message endpoint on another origin: {endpoint}");
+    }
+
+    Ok(endpoint)
+}
+
+async fn run_sse_client(
+    client: Client,
+    url: Url,
+    mut upstream_rx: mpsc::Receiver<Value>,
+    inbound_tx: mpsc::Sender<Value>,
+) -> Result<()> {
+    let response = client
+        .get(url.clone())
+        .header(ACCEPT, "text/event-stream")
+        .send()
+        .await?
+        .error_for_status()?;
+
+    let (endpoint_tx, endpoint_rx) = oneshot::channel::<Result<Url>>();
+
+    // Event Stream Reception
+    //
+    // 1. Wait for the `endpoint` event naming the url messages are posted to.
+    // 2. Send json-rpc messages from `message` events to the inbound message buffer.
+    let inbound_tx_clone = inbound_tx.clone();
+    let mut event_stream_task = task::spawn(async move {
+        let mut endpoint_tx = Some(endpoint_tx);
+        let mut events = event_stream(response);
+
+        while let Some(event) = events.next_event().await? {
+            match event.event.as_str() {
+                "endpoint" => {
+                    if let Some(endpoint_tx) = endpoint_tx.take() {
+                        let _ = endpoint_tx.send(resolve_endpoint(&url, &event.data));
+                    }
+                }
+                "message" => {
+                    inbound_tx_clone
+                        .send(serde_json::from_str(&event.data)?)
+                        .await?
+                }
+                event => log::warn!("Ignoring unexpected event from remote MCP server: {event}"),
+            }
+        }
+
+        Err::<(), _>(anyhow!("Remote MCP server closed the event stream."))
+    });
+
+    let endpoint = tokio::select! {
+        endpoint = endpoint_rx => endpoint.map_err(|_| anyhow!("Remote MCP server did not send a message endpoint."))??,
+        res = &mut event_stream_task => return res?,
+    };
+
+    log::info!("Remote MCP server message endpoint: {endpoint}");
+
+    let message_transmission = async {
+        while let Some(msg) = upstream_rx.recv().await {
+            let res = client
+                .post(endpoint.clone())
+                .json(&msg)
+                .send()
+                .await
+                .and_then(Response::error_for_status);
+
+            if let Err(e) = res {
+                log::error!("Failed to send message to remote MCP server: {e}");
+                if let Some(error_response) = error_response(&msg, &e.to_string()) {
+                    inbound_tx.send(error_response).await?;
+                }
+            }
+        }
+
+        Ok(())
+    };
+
+    tokio::select! {
+        res = message_transmission => res,
+        res = event_stream_task => res?,
+    }
+}
+
+/// Builds the error response returned to the host when a request couldn't be delivered to the
+/// remote server, or a batch of them for a batch. Returns `None` if no request was in the message.
+fn error_response(msg: &Value, error: &str) -> Option<Value> {
+    if let Value::Array(batch) = msg {
+        let responses: Vec<Value> = batch
+            .iter()
+            .filter_map(|msg| error_response(msg, error))
+            .collect();
+
+        return (!responses.is_empty()).then_some(Value::Array(responses));
+    }
+
+    let id = msg.get("method").and(msg.get("id"))?;
+
+    Some(json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": {
+            "code": -32603,
+            "message": format!("Remote MCP server error: {error}"),
+        }
+    }))
+}
+
+#[derive(Debug, PartialEq)]
+struct SseEvent {
+    event: String,
+    data: String,
+}
+
+#[derive(Default)]
+struct SseParser {
+    buffer: Vec<u8>,
+    event: Option<String>,
+    data: Vec<String>,
+}
+
+impl SseParser {
+    fn feed(&mut self, chunk: &[u8]) -> Vec<SseEvent> {
+        self.buffer.extend_from_slice(chunk);
+
+        let mut events = vec![];
+
+        while let Some(pos) = self.buffer.iter().position(|b| *b == b'\n') {
+            let line = self.buffer.drain(..=pos).collect::<Vec<_>>();
+            let line = String::from_utf8_lossy(&line);
+            let line = line.trim_end_matches(['\n', '\r']);
+
+            // a blank line dispatches the event
+            if line.is_empty() {
+                let event = self.event.take();
+                if !self.data.is_empty() {
+                    events.push(SseEvent {
+                        event: event.unwrap_or_else(|| "message".to_owned()),
+                        data: self.data.join("\n"),
+                    });
+                    self.data.clear();
+                }
+                continue;
+            }
+
+            // lines starting with a colon are comments
+            if line.starts_with(':') {
+                continue;
+            }
+
+            let (field, value) = match line.split_once(':') {
+                Some((field, value)) => (field, value.strip_prefix(' ').unwrap_or(value)),
+                None => (line, ""),
+            };
+
+            match field {
+                "event" => self.event = Some(value.to_owned()),
+                "data" => self.data.push(value.to_owned()),
+                _ => {}
+            }
+        }
+
+        events
+    }
+}
+
+struct EventStream<S> {
+    body: S,
+    parser: SseParser,
+    events: VecDeque<SseEvent>,
+}
+
+fn event_stream(
+    response: Response,
+) -> EventStream<impl Stream<Item = reqwest::Result<impl AsRef<[u8]>>> + Unpin> {
+    EventStream {
+        body: Box::pin(response.bytes_stream()),
+        parser: SseParser::default(),
+        events: VecDeque::new(),
+    }
+}
+
+impl<S, B> EventStream<S>
+where
+    S: Stream<Item = reqwest::Result<B>> + Unpin,
+    B: AsRef<[u8]>,
+{
+    async fn next_event(&mut self) -> Result<Option<SseEvent>> {
+        loop {
+            if let Some(event) = self.events.pop_front() {
+                return Ok(Some(event));
+            }
+
+            match self.body.next().await {
+                Some(chunk) => self.events.extend(self.parser.feed(chunk?.as_ref())),
+                None => return Ok(None),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{collections::HashMap, convert::Infallible};
+
+    use axum::{
+        extract::State,
+        http::{HeaderMap as AxumHeaderMap, StatusCode as AxumStatusCode},
+        response::{
+            sse::{Event, Sse},
+            IntoResponse,
+        },
+        routing::{get, post},
+        Json, Router,
+    };
+    use futures_util::stream;
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    async fn serve(router: Router) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        task::spawn(async move { axum::serve(listener, router).await.unwrap() });
+
+        format!("http://{addr}")
+    }
+
+    fn result(id: &Value, result: Value) -> Value {
+        json!({ "jsonrpc": "2.0", "id": id, "result": result })
+    }
+
+    #[test]
+    fn test_error_response() {
+        let request = json!({ "jsonrpc": "2.0", "id": 1, "method": "tools/list" });
+        let notification = json!({ "jsonrpc": "2.0", "method": "notifications/initialized" });
+
+        assert_eq!(error_response(&request, "503").unwrap()["id"], json!(1));
+        assert_eq!(error_response(&notification, "503"), None);
+
+        let batch = json!([
+            request,
+            notification,
+            { "jsonrpc": "2.0", "id": 2, "method": "ping" },
+        ]);
+        let responses = error_response(&batch, "503").unwrap();
+        let ids: Vec<&Value> = responses
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|response| &response["id"])
+            .collect();
+        assert_eq!(ids, vec![&json!(1), &json!(2)]);
+        assert_eq!(responses[0]["error"]["code"], json!(-32603));
+
+        assert_eq!(error_response(&json!([notification]), "503"), None);
+    }
+
+    #[test]
+    fn test_resolve_endpoint() {
+        let url = Url::parse("http://localhost:8000/sse").unwrap();
+
+        assert_eq!(
+            resolve_endpoint(&url, "/messages?session=1")
+                .unwrap()
+                .as_str(),
+            "http://localhost:8000/messages?session=1"
+        );
+        assert!(resolve_endpoint(&url, "http://localhost:8000/messages").is_ok());
+        assert!(resolve_endpoint(&url, "http://evil.example/messages").is_err());
+        assert!(resolve_endpoint(&url, "//evil.example/messages").is_err());
+        assert!(resolve_endpoint(&url, "https://localhost:8000/messages").is_err());
+    }
+
+    #[test]
+    fn test_sse_parser() {
+        let mut parser = SseParser::default();
+
+        assert_eq!(parser.feed(b": comment\nevent: endpoint\nda"), vec![]);
+        assert_eq!(
+            parser.feed(b"ta: /messages?session=1\r\n\r\ndata: {\"a\":\ndata: 1}\n\n"),
+            vec![
+                SseEvent {
+                    event: "endpoint".to_owned(),
+                    data: "/messages?session=1".to_owned(),
+                },
+                SseEvent {
+                    event: "message".to_owned(),
+                    data: "{\"a\":\n1}".to_owned(),
+                },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_streamable_http_client() {
+        async fn handle_post(headers: AxumHeaderMap, Json(msg): Json<Value>) -> impl IntoResponse {
+            let session_id = headers
+                .get(SESSION_ID_HEADER)
+                .and_then(|v| v.to_str().ok())
+                .map(str::to_owned);
+
+            match (msg["method"].as_str(), msg.get("id")) {
+                (Some("initialize"), Some(id)) => (
+                    [(SESSION_ID_HEADER, "test-session")],
+                    Json(result(id, json!({ "protocolVersion": "2025-03-26" }))),
+                )
+                    .into_response(),
+                (Some("tools/list"), Some(id)) => {
+                    assert_eq!(session_id.as_deref(), Some("test-session"));
+                    assert_eq!(headers.get(PROTOCOL_VERSION_HEADER).unwrap(), "2025-03-26");
+                    assert_eq!(headers.get("x-api-key").unwrap(), "secret");
+
+                    let events = vec![
+                        Ok::<_, Infallible>(
+                            Event::default().data(
+                                json!({ "jsonrpc": "2.0", "method": "notifications/progress" })
+                                    .to_string(),
+                            ),
+                        ),
+                        Ok(Event::default().data(result(id, json!({ "tools": [] })).to_string())),
+                    ];
+
+                    Sse::new(stream::iter(events)).into_response()
+                }
+                _ => AxumStatusCode::ACCEPTED.into_response(),
+            }
+        }
+
+        let router = Router::new().route(
+            "/mcp",
+            post(handle_post)
+                .get(|| async { AxumStatusCode::METHOD_NOT_ALLOWED })
+                .delete(|| async { AxumStatusCode::OK }),
+        );
+        let base_url = serve(router).await;
+
+        let (upstream_tx, upstream_rx) = mpsc::channel(10);
+        let (inbound_tx, mut inbound_rx) = mpsc::channel(10);
+
+        let remote = RemoteMcpServer {
+            url: format!("{base_url}/mcp"),
+            headers: HashMap::from([("x-api-key".to_owned(), "secret".to_owned())]),
+            transport: RemoteTransport::StreamableHttp,
+        };
+        let client_task = task::spawn(run_client(remote, upstream_rx, inbound_tx));
+
+        upstream_tx
+            .send(json!({ "jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {} }))
+            .await
+            .unwrap();
+        assert_eq!(
+            inbound_rx.recv().await.unwrap(),
+            result(&json!(1), json!({ "protocolVersion": "2025-03-26" }))
+        );
+
+        upstream_tx
+            .send(json!({ "jsonrpc": "2.0", "method": "notifications/initialized" }))
+            .await
+            .unwrap();
+        upstream_tx
+            .send(json!({ "jsonrpc": "2.0", "id": 2, "method": "tools/list" }))
+            .await
+            .unwrap();
+        assert_eq!(
+            inbound_rx.recv().await.unwrap()["method"],
+            "notifications/progress"
+        );
+        assert_eq!(
+            inbound_rx.recv().await.unwrap(),
+            result(&json!(2), json!({ "tools": [] }))
+        );
+
+        drop(upstream_tx);
+        client_task.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_streamable_http_session_expired() {
+        #[derive(Default)]
+        struct Sessions {
+            expired: Vec<String>,
+            received: Vec<(Option<String>, Value)>,
+        }
+
+        async fn handle_post(
+            State(sessions): State<Arc<Mutex<Sessions>>>,
+            headers: AxumHeaderMap,
+            Json(msg): Json<Value>,
+        ) -> impl IntoResponse {
+            let session_id = headers
+                .get(SESSION_ID_HEADER)
+                .and_then(|v| v.to_str().ok())
+                .map(str::to_owned);
+
+            let mut sessions = sessions.lock().await;
+            if session_id
+                .as_ref()
+                .is_some_and(|session_id| sessions.expired.contains(session_id))
+            {
+                return AxumStatusCode::NOT_FOUND.into_response();
+            }
+            sessions
+                .received
+                .push((session_id.clone(), msg["id"].clone()));
+
+            match (msg["method"].as_str(), msg.get("id")) {
+                (Some("initialize"), Some(id)) => {
+                    let session_id = format!("session-{}", sessions.expired.len() + 1);
+                    (
+                        [(SESSION_ID_HEADER, session_id)],
+                        Json(result(id, json!({ "protocolVersion": "2025-03-26" }))),
+                    )
+                        .into_response()
+                }
+                (Some(_), Some(id)) => {
+                    // every session expires after answering a single request
+                    sessions.expired.push(session_id.unwrap());
+                    Json(result(id, json!({}))).into_response()
+                }
+                _ => AxumStatusCode::ACCEPTED.into_response(),
+            }
+        }
+
+        let sessions = Arc::new(Mutex::new(Sessions::default()));
+        let router = Router::new()
+            .route(
+                "/mcp",
+                post(handle_post)
+                    .get(|| async { AxumStatusCode::METHOD_NOT_ALLOWED })
+                    .delete(|| async { AxumStatusCode::OK }),
+            )
+            .with_state(sessions.clone());
+        let base_url = serve(router).await;
+
+        let (upstream_tx, upstream_rx) = mpsc::channel(10);
+        let (inbound_tx, mut inbound_rx) = mpsc::channel(10);
+
+        let remote = RemoteMcpServer {
+            url: format!("{base_url}/mcp"),
+            headers: HashMap::new(),
+            transport: RemoteTransport::StreamableHttp,
+        };
+        let client_task = task::spawn(run_client(remote, upstream_rx, inbound_tx));
+
+        upstream_tx
+            .send(json!({ "jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {} }))
+            .await
+            .unwrap();
+        assert_eq!(inbound_rx.recv().await.unwrap()["id"], json!(1));
+
+        upstream_tx
+            .send(json!({ "jsonrpc": "2.0", "method": "notifications/initialized" }))
+            .await
+            .unwrap();
+        for id in 2..4 {
+            upstream_tx
+                .send(json!({ "jsonrpc": "2.0", "id": id, "method": "ping" }))
+                .await
+                .unwrap();
+            // the host isn't sent the response to the replayed initialize request
+            assert_eq!(
+                inbound_rx.recv().await.unwrap(),
+                result(&json!(id), json!({}))
+            );
+        }
+
+        drop(upstream_tx);
+        client_task.await.unwrap().unwrap();
+
+        let session = |n: usize| Some(format!("session-{n}"));
+        assert_eq!(
+            sessions.lock().await.received,
+            vec![
+                (None, json!(1)),
+                (session(1), Value::Null),
+                (session(1), json!(2)),
+                (None, json!(REPLAY_INITIALIZE_ID)),
+                (session(2), Value::Null),
+                (session(2), json!(3)),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_sse_client() {
+        type EventSender = mpsc::UnboundedSender<Result<Event, Infallible>>;
+
+        let events: Arc<Mutex<Option<EventSender>>> = Arc::new(Mutex::new(None));
+
+        async fn handle_get(
+            State(events): State<Arc<Mutex<Option<EventSender>>>>,
+        ) -> impl IntoResponse {
+            let (tx, rx) = mpsc::unbounded_channel();
+            tx.send(Ok(Event::default()
+                .event("endpoint")
+                .data("/messages?session=1")))
+                .unwrap();
+            *events.lock().await = Some(tx);
+
+            let stream = stream::unfold(rx, |mut rx| async move {
+                rx.recv().await.map(|event| (event, rx))
+            });
+
+            Sse::new(stream)
+        }
+
+        async fn handle_post(
+            State(events): State<Arc<Mutex<Option<EventSender>>>>,
+            Json(msg): Json<Value>,
+        ) -> impl IntoResponse {
+            if let Some(id) = msg.get("id") {
+                let response = result(id, json!({ "echo": msg["method"] }));
+                let events = events.lock().await;
+                events
+                    .as_ref()
+                    .unwrap()
+                    .send(Ok(Event::default()
+                        .event("message")
+                        .data(response.to_string())))
+                    .unwrap();
+            }
+
+            AxumStatusCode::ACCEPTED
+        }
+
+        let router = Router::new()
+            .route("/sse", get(handle_get))
+            .route("/messages", post(handle_post))
+            .with_state(events);
+        let base_url = serve(router).await;
+
+        let (upstream_tx, upstream_rx) = mpsc::channel(10);
+        let (inbound_tx, mut inbound_rx) = mpsc::channel(10);
+
+        let remote = RemoteMcpServer {
+            url: format!("{base_url}/sse"),
+            headers: HashMap::new(),
+            transport: RemoteTransport::Sse,
+        };
+        let client_task = task::spawn(run_client(remote, upstream_rx, inbound_tx));
+
+        upstream_tx
+            .send(json!({ "jsonrpc": "2.0", "id": "a", "method": "ping" }))
+            .await
+            .unwrap();
+        assert_eq!(
+            inbound_rx.recv().await.unwrap(),
+            result(&json!("a"), json!({ "echo": "ping" }))
+        );
+
+        drop(upstream_tx);
+        client_task.await.unwrap().unwrap();
+    }
+}
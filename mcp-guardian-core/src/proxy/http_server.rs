@@ -0,0 +1,1163 @@
+use std::{
+    collections::HashMap,
+    convert::Infallible,
+    net::{IpAddr, SocketAddr},
+    process::ExitStatus,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Result};
+use axum::{
+    extract::{Request, State},
+    http::{header::ORIGIN, HeaderMap, StatusCode},
+    middleware::{self, Next},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Response,
+    },
+    routing::post,
+    Json, Router,
+};
+use futures_util::stream;
+use reqwest::Url;
+use serde_json::{json, Value};
+use tokio::{
+    net::TcpListener,
+    sync::{mpsc, oneshot, Mutex},
+    task, time,
+};
+use uuid::Uuid;
+
+use crate::{
+    mcp_server::McpServer,
+    message::{
+        Message,
+        MessageDirection::{Inbound, Outbound},
+        MessageType,
+    },
+    message_interceptor::{
+        intercept_in_order, intercept_raw_message, manual_approval::DENIED_ERROR_CODE,
+        record::SessionRecorder, InterceptedMessage, MessageInterceptor,
+    },
+    proxy::{error_response, http::SESSION_ID_HEADER, process::spawn_mcp_server, Context},
+    server_log::ServerLogWriter,
+};
+
+/// How long a session without an open event stream may go without a request before it's ended
+const SESSION_IDLE_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+/// How often idle sessions are looked for
+const SESSION_EXPIRY_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Serves a guarded MCP server over the Streamable HTTP transport at `http://{addr}/mcp`,
+/// recording the session with `recorder` if given.
+///
+/// Requests from browsers are only accepted from loopback origins and `allowed_origins`, so web
+/// pages can't reach the server through DNS rebinding.
+///
+/// Every HTTP session shares the one MCP server process, and the one proxy session recorded.
+/// Returns the exit status of the process once it exits, or once `shutdown_rx` receives and the
+/// process has been shut down.
+pub async fn serve_mcp_server(
+    mcp_server_name: String,
+    mcp_server: &McpServer,
+    message_interceptor: Arc<dyn MessageInterceptor>,
+    recorder: Option<Arc<SessionRecorder>>,
+    addr: SocketAddr,
+    allowed_origins: Vec<String>,
+    shutdown_rx: oneshot::Receiver<()>,
+) -> Result<ExitStatus> {
+    let ctx = Arc::new(
+        Context {
+            mcp_server_name,
+            host_session_id: None,
+            session_id: Uuid::new_v4().to_string(),
+            message_interceptor,
+        }
+        .record(recorder),
+    );
+
+    log::info!(
+        "Starting proxy for: {} {:?}",
+        mcp_server.cmd,
+        mcp_server.args
+    );
+
+    let stderr_log = ServerLogWriter::create_or_inherit(
+        &ctx.mcp_server_name,
+        &ctx.session_id,
+        &mcp_server.stderr_log.clone().unwrap_or_default(),
+    );
+
+    let (upstream_tx, inbound_rx, mut mcp_server_process) =
+        spawn_mcp_server(mcp_server, stderr_log)?;
+
+    let listener = TcpListener::bind(addr).await?;
+    log::info!(
+        "Serving MCP server at http://{}/mcp",
+        listener.local_addr()?
+    );
+    if !addr.ip().is_loopback() {
+        log::warn!(
+            "Listening on non-loopback address {addr}: any host that can reach it can use the MCP server, as clients aren't authenticated."
+        );
+    }
+
+    tokio::select! {
+        res = serve(ctx, listener, upstream_tx, inbound_rx, allowed_origins) => res?,
+        Ok(()) = shutdown_rx => {}
+        res = mcp_server_process.wait() => return res,
+    }
+
+    log::info!("Shutting down MCP server process.");
+    mcp_server_process.shutdown().await
+}
+
+async fn serve(
+    ctx: Arc<Context>,
+    listener: TcpListener,
+    upstream_tx: mpsc::Sender<Value>,
+    mut inbound_rx: mpsc::Receiver<Value>,
+    allowed_origins: Vec<String>,
+) -> Result<()> {
+    let server = Arc::new(Server {
+        ctx,
+        allowed_origins,
+        upstream_tx,
+        sessions: Mutex::new(HashMap::new()),
+        pending_requests: Mutex::new(HashMap::new()),
+        next_request_id: AtomicU64::new(1),
+        initialize_result: Mutex::new(None),
+        initialized: AtomicBool::new(false),
+    });
+
+    // Inbound Message Transmission
+    //
+    // 1. Read from inbound message buffer.
+    // 2. intercept_inbound_message()
+    // 3. Route to the HTTP session(s) the message belongs to.
+    log::info!("Starting inbound message transmitter");
+    let server_clone = server.clone();
+    let inbound_message_transmission_task = task::spawn(async move {
+        while let Some(msg) = inbound_rx.recv().await {
+            // Batches are split up, as their elements may belong to different sessions.
+            for msg in Message::unbatch(&msg) {
+                let server_clone = server_clone.clone();
+                let msg = msg.clone();
+                intercept_in_order(async move { server_clone.handle_inbound(msg).await }).await;
+            }
+        }
+    });
+
+    let router = Router::new()
+        .route(
+            "/mcp",
+            post(handle_post).get(handle_get).delete(handle_delete),
+        )
+        .layer(middleware::from_fn_with_state(server.clone(), check_origin))
+        .with_state(server.clone());
+
+    tokio::select! {
+        res = axum::serve(listener, router) => res?,
+        _ = inbound_message_transmission_task => {
+            log::error!("Inbound message transmission task exited unexpectedly.");
+            return Err(anyhow!("Inbound message transmission task exited unexpectedly."));
+        }
+        _ = expire_idle_sessions(server) => {}
+    }
+
+    Ok(())
+}
+
+struct Session {
+    last_active: Instant,
+    stream_tx: Option<mpsc::UnboundedSender<Value>>,
+}
+
+/// A request forwarded to the MCP server under a proxy-assigned id, so requests from different
+/// sessions can't collide.
+struct PendingRequest {
+    session_id: String,
+    id: Value,
+    progress_token: Option<Value>,
+    response_tx: oneshot::Sender<Value>,
+    /// Interception of the message the request came in, abandoned if the request is cancelled
+    /// while it's held back
+    interception: Option<Arc<Interception>>,
+}
+
+/// The interception of a message from a session, known by each of the requests it contains.
+struct Interception {
+    abort_handle: task::AbortHandle,
+    proxy_ids: Vec<Value>,
+}
+
+/// A request from a session awaiting its response.
+struct AwaitedResponse {
+    id: Value,
+    response_rx: oneshot::Receiver<Value>,
+}
+
+struct Server {
+    ctx: Arc<Context>,
+    /// Origins accepted besides loopback ones
+    allowed_origins: Vec<String>,
+    upstream_tx: mpsc::Sender<Value>,
+    sessions: Mutex<HashMap<String, Session>>,
+    pending_requests: Mutex<HashMap<Value, PendingRequest>>,
+    next_request_id: AtomicU64,
+    /// The MCP server is initialized once, by the first session. Later sessions are answered with
+    /// the same result.
+    initialize_result: Mutex<Option<Value>>,
+    initialized: AtomicBool,
+}
+
+impl Server {
+    async fn initialize(&self, session_id: &str, msg: Value) -> Value {
+        let (msg, awaited) = self.register(session_id, msg).await;
+
+        // intercepted before taking the lock, so a session held for approval doesn't hold up the
+        // others
+        let msg = match msg {
+            Some(msg) => self.intercept_outbound(session_id, msg).await,
+            None => None,
+        };
+
+        if let Some(msg) = msg {
+            // held for the exchange with the server so concurrent sessions wait for the first one
+            // to finish
+            let mut initialize_result = self.initialize_result.lock().await;
+
+            match initialize_result.clone() {
+                Some(result) => {
+                    drop(initialize_result);
+                    self.reuse_initialize(session_id, &msg, result).await;
+                }
+                None => {
+                    self.send_upstream(msg).await;
+
+                    let response = self.await_responses(awaited).await.remove(0);
+                    if let Some(result) = response.get("result") {
+                        *initialize_result = Some(result.clone());
+                    }
+
+                    return response;
+                }
+            }
+        }
+
+        self.await_responses(awaited).await.remove(0)
+    }
+
+    /// Answers a later session's initialize with the result of the first, once the session's own
+    /// request has been intercepted like any other. The answer is intercepted as the server's
+    /// response would have been.
+    async fn reuse_initialize(&self, session_id: &str, msg: &Value, result: Value) {
+        log::info!("Reusing MCP server initialization for session '{session_id}'");
+
+        self.handle_inbound(json!({ "jsonrpc": "2.0", "id": msg["id"], "result": result }))
+            .await;
+    }
+
+    /// Handles a message from a session, which may be a batch, returning the responses to its
+    /// requests.
+    async fn handle_outbound(self: &Arc<Self>, session_id: &str, msg: Value) -> Vec<Value> {
+        let (msg, awaited) = self.register(session_id, msg).await;
+
+        if let Some(msg) = msg {
+            let server = self.clone();
+            let session_id = session_id.to_owned();
+            let proxy_ids = request_ids(&msg);
+
+            let task = task::spawn(async move {
+                if let Some(msg) = server.intercept_outbound(&session_id, msg).await {
+                    server.send_upstream(msg).await;
+                }
+            });
+
+            if !proxy_ids.is_empty() {
+                let interception = Arc::new(Interception {
+                    abort_handle: task.abort_handle(),
+                    proxy_ids,
+                });
+                let mut pending_requests = self.pending_requests.lock().await;
+                for proxy_id in &interception.proxy_ids {
+                    if let Some(pending) = pending_requests.get_mut(proxy_id) {
+                        pending.interception = Some(interception.clone());
+                    }
+                }
+            }
+        }
+
+        self.await_responses(awaited).await
+    }
+
+    /// Gives the requests of a message from a session proxy ids, tracking them until they're
+    /// answered, and passes cancellations on under those ids.
+    ///
+    /// Returns what's left of the message to intercept, and the requests to await responses to.
+    async fn register(
+        &self,
+        session_id: &str,
+        msg: Value,
+    ) -> (Option<Value>, Vec<AwaitedResponse>) {
+        let mut elements = Vec::new();
+        let mut awaited = Vec::new();
+
+        for element in Message::unbatch(&msg) {
+            let mut element = element.clone();
+
+            match (
+                element.get("method").and_then(Value::as_str),
+                element.get("id"),
+            ) {
+                (Some(_), Some(_)) => {
+                    let id = element["id"].take();
+                    let proxy_id = json!(self.next_request_id.fetch_add(1, Ordering::Relaxed));
+                    element["id"] = proxy_id.clone();
+
+                    // progress notifications are routed back to the session by token, so the
+                    // token is replaced with the (unique) proxy id as well
+                    let progress_token = element
+                        .pointer_mut("/params/_meta/progressToken")
+                        .map(|token| std::mem::replace(token, proxy_id.clone()));
+
+                    let (response_tx, response_rx) = oneshot::channel();
+                    self.pending_requests.lock().await.insert(
+                        proxy_id,
+                        PendingRequest {
+                            session_id: session_id.to_owned(),
+                            id: id.clone(),
+                            progress_token,
+                            response_tx,
+                            interception: None,
+                        },
+                    );
+                    awaited.push(AwaitedResponse { id, response_rx });
+                }
+                (Some("notifications/initialized"), None) => {
+                    if self.initialized.swap(true, Ordering::SeqCst) {
+                        continue;
+                    }
+                }
+                (Some("notifications/cancelled"), None) => {
+                    let request_id = element.pointer("/params/requestId").cloned();
+                    let Some(proxy_id) = self.cancel(session_id, request_id).await else {
+                        continue;
+                    };
+                    element["params"]["requestId"] = proxy_id;
+                }
+                _ => {}
+            }
+
+            elements.push(element);
+        }
+
+        let msg = match msg {
+            Value::Array(_) if !elements.is_empty() => Some(Value::Array(elements)),
+            _ => elements.pop(),
+        };
+
+        (msg, awaited)
+    }
+
+    /// Answers a cancelled request of a session, returning its proxy id. A request held back by
+    /// the interceptor is abandoned along with the rest of its message, whose requests are
+    /// answered with errors.
+    async fn cancel(&self, session_id: &str, request_id: Option<Value>) -> Option<Value> {
+        let mut pending_requests = self.pending_requests.lock().await;
+
+        let proxy_id = pending_requests
+            .iter()
+            .find(|(_, pending)| {
+                pending.session_id == session_id && Some(&pending.id) == request_id.as_ref()
+            })
+            .map(|(proxy_id, _)| proxy_id.clone())?;
+        let pending = pending_requests.remove(&proxy_id)?;
+
+        log::info!(
+            "Abandoning cancelled request {} from session '{session_id}'",
+            pending.id
+        );
+        let _ =
+            pending
+                .response_tx
+                .send(error_response(pending.id, -32603, "Request was cancelled."));
+
+        if let Some(interception) = pending.interception {
+            if !interception.abort_handle.is_finished() {
+                interception.abort_handle.abort();
+
+                for other_id in &interception.proxy_ids {
+                    if let Some(other) = pending_requests.remove(other_id) {
+                        let _ = other.response_tx.send(error_response(
+                            other.id,
+                            -32603,
+                            "Request was abandoned along with a cancelled request in its batch.",
+                        ));
+                    }
+                }
+            }
+        }
+
+        Some(proxy_id)
+    }
+
+    /// Intercepts a message from a session, answering the requests the interceptor returns a
+    /// response to or drops. Returns the message to send on to the MCP server, if any.
+    async fn intercept_outbound(&self, session_id: &str, msg: Value) -> Option<Value> {
+        let proxy_ids = request_ids(&msg);
+
+        let InterceptedMessage { send, return_ } =
+            intercept_raw_message(self.ctx.message_interceptor.as_ref(), Outbound, msg).await;
+
+        let mut answered = send.as_ref().map(request_ids).unwrap_or_default();
+
+        if let Some(msg) = return_ {
+            for element in Message::unbatch(&msg) {
+                match (element.get("method"), element.get("id")) {
+                    (None, Some(proxy_id)) => {
+                        answered.push(proxy_id.clone());
+                        self.resolve(element.clone()).await;
+                    }
+                    _ => {
+                        self.send_to_session(session_id, element.clone()).await;
+                    }
+                }
+            }
+        }
+
+        for proxy_id in proxy_ids {
+            if !answered.contains(&proxy_id) {
+                self.resolve(error_response(
+                    proxy_id.clone(),
+                    DENIED_ERROR_CODE.into(),
+                    "Request was dropped by MCP Guardian.",
+                ))
+                .await;
+            }
+        }
+
+        send
+    }
+
+    /// Sends a message on to the MCP server, answering its requests with errors if it can't be.
+    async fn send_upstream(&self, msg: Value) {
+        if let Err(e) = self.upstream_tx.send(msg).await {
+            log::error!("Failed to send message to upstream buffer: {e}");
+
+            for proxy_id in request_ids(&e.0) {
+                self.resolve(error_response(
+                    proxy_id,
+                    -32603,
+                    "MCP Guardian failed to proxy request.",
+                ))
+                .await;
+            }
+        }
+    }
+
+    async fn await_responses(&self, awaited: Vec<AwaitedResponse>) -> Vec<Value> {
+        let mut responses = Vec::new();
+
+        for AwaitedResponse { id, response_rx } in awaited {
+            responses.push(response_rx.await.unwrap_or_else(|_| {
+                error_response(id, -32603, "MCP Guardian failed to proxy request.")
+            }));
+        }
+
+        responses
+    }
+
+    /// Answers a pending request with `msg`, a response under the request's proxy id. Returns
+    /// `false` if no such request is pending.
+    async fn resolve(&self, mut msg: Value) -> bool {
+        let pending = match msg.get("id") {
+            Some(proxy_id) => self.pending_requests.lock().await.remove(proxy_id),
+            None => None,
+        };
+        let Some(PendingRequest {
+            id, response_tx, ..
+        }) = pending
+        else {
+            return false;
+        };
+
+        msg["id"] = id;
+        let _ = response_tx.send(msg);
+
+        true
+    }
+
+    /// Intercepts a message from the MCP server and routes it to the session(s) it belongs to.
+    async fn handle_inbound(&self, msg: Value) {
+        let InterceptedMessage { send, return_ } =
+            intercept_raw_message(self.ctx.message_interceptor.as_ref(), Inbound, msg).await;
+
+        if let Some(msg) = return_ {
+            if let Err(e) = self.upstream_tx.send(msg).await {
+                log::error!("Failed to send message to upstream buffer: {e}");
+            }
+        }
+
+        let Some(mut msg) = send else {
+            return;
+        };
+
+        match Message::from_json(msg.clone()).type_ {
+            MessageType::ResponseSuccess | MessageType::ResponseFailure => {
+                if !self.resolve(msg.clone()).await {
+                    log::warn!("Received response for an unknown request: {msg}");
+                }
+            }
+            MessageType::Request => {
+                // server-initiated requests go to the most recently active session that is
+                // listening for them
+                let session_id = self
+                    .sessions
+                    .lock()
+                    .await
+                    .iter()
+                    .filter(|(_, session)| session.stream_tx.is_some())
+                    .max_by_key(|(_, session)| session.last_active)
+                    .map(|(session_id, _)| session_id.clone());
+
+                let delivered = match session_id {
+                    Some(session_id) => self.send_to_session(&session_id, msg.clone()).await,
+                    None => false,
+                };
+
+                if !delivered {
+                    log::warn!("No session available for server request: {msg}");
+                    let response =
+                        error_response(msg["id"].clone(), -32603, "No client is connected.");
+                    if let Err(e) = self.upstream_tx.send(response).await {
+                        log::error!("Failed to send message to upstream buffer: {e}");
+                    }
+                }
+            }
+            MessageType::Notification | MessageType::Unknown => {
+                let progress_session = match msg.pointer("/params/progressToken") {
+                    Some(token) => self
+                        .pending_requests
+                        .lock()
+                        .await
+                        .get(token)
+                        .map(|pending| {
+                            (pending.session_id.clone(), pending.progress_token.clone())
+                        }),
+                    None => None,
+                };
+
+                match progress_session {
+                    Some((session_id, progress_token)) => {
+                        msg["params"]["progressToken"] = progress_token.unwrap_or_default();
+                        self.send_to_session(&session_id, msg).await;
+                    }
+                    None => {
+                        for session in self.sessions.lock().await.values() {
+                            if let Some(stream_tx) = &session.stream_tx {
+                                let _ = stream_tx.send(msg.clone());
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    async fn send_to_session(&self, session_id: &str, msg: Value) -> bool {
+        let sessions = self.sessions.lock().await;
+
+        let Some(stream_tx) = sessions
+            .get(session_id)
+            .and_then(|session| session.stream_tx.as_ref())
+        else {
+            log::warn!("Session '{session_id}' is not listening for messages: {msg}");
+            return false;
+        };
+
+        stream_tx.send(msg).is_ok()
+    }
+
+    /// Ends a session, abandoning its pending requests. Returns `false` if it doesn't exist.
+    async fn end_session(&self, session_id: &str) -> bool {
+        if self.sessions.lock().await.remove(session_id).is_none() {
+            return false;
+        }
+
+        self.pending_requests
+            .lock()
+            .await
+            .retain(|_, pending| pending.session_id != session_id);
+
+        true
+    }
+
+    /// Ends the sessions that have been idle for longer than `idle_timeout`. Sessions listening
+    /// on an open event stream are still connected, and kept.
+    async fn expire_sessions(&self, idle_timeout: Duration) {
+        let expired = self
+            .sessions
+            .lock()
+            .await
+            .iter()
+            .filter(|(_, session)| {
+                let listening = session
+                    .stream_tx
+                    .as_ref()
+                    .is_some_and(|stream_tx| !stream_tx.is_closed());
+                !listening && session.last_active.elapsed() > idle_timeout
+            })
+            .map(|(session_id, _)| session_id.clone())
+            .collect::<Vec<_>>();
+
+        for session_id in expired {
+            log::info!("Ending idle session '{session_id}'");
+            self.end_session(&session_id).await;
+        }
+    }
+
+    /// Marks the session as active, returning `false` if it doesn't exist.
+    async fn touch_session(&self, session_id: &str) -> bool {
+        match self.sessions.lock().await.get_mut(session_id) {
+            Some(session) => {
+                session.last_active = Instant::now();
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// Ids of the requests in a message, which may be a batch.
+fn request_ids(msg: &Value) -> Vec<Value> {
+    Message::unbatch(msg)
+        .iter()
+        .filter_map(|element| element.get("method").and(element.get("id")))
+        .cloned()
+        .collect()
+}
+
+fn session_id(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get(SESSION_ID_HEADER)
+        .and_then(|session_id| session_id.to_str().ok())
+        .map(str::to_owned)
+}
+
+async fn handle_post(
+    State(server): State<Arc<Server>>,
+    headers: HeaderMap,
+    Json(msg): Json<Value>,
+) -> Response {
+    let message = Message::from_json(msg);
+
+    if message.type_ == MessageType::Request && message.raw_msg["method"] == "initialize" {
+        let session_id = Uuid::new_v4().to_string();
+        let response = server.initialize(&session_id, message.raw_msg).await;
+
+        if response.get("result").is_none() {
+            return Json(response).into_response();
+        }
+
+        log::info!("Starting session '{session_id}'");
+        server.sessions.lock().await.insert(
+            session_id.clone(),
+            Session {
+                last_active: Instant::now(),
+                stream_tx: None,
+            },
+        );
+
+        return ([(SESSION_ID_HEADER, session_id)], Json(response)).into_response();
+    }
+
+    let Some(session_id) = session_id(&headers) else {
+        return (StatusCode::BAD_REQUEST, "Missing Mcp-Session-Id header.").into_response();
+    };
+
+    if !server.touch_session(&session_id).await {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+
+    let invalid = match &message.raw_msg {
+        Value::Array(batch) => batch.is_empty(),
+        _ => message.type_ == MessageType::Unknown,
+    };
+    if invalid {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(error_response(Value::Null, -32600, "Invalid Request")),
+        )
+            .into_response();
+    }
+
+    let batch = message.raw_msg.is_array();
+
+    // The responses to the requests of a batch are batched up again.
+    let mut responses = server.handle_outbound(&session_id, message.raw_msg).await;
+    match (batch, responses.pop()) {
+        (_, None) => StatusCode::ACCEPTED.into_response(),
+        (false, Some(response)) => Json(response).into_response(),
+        (true, Some(response)) => {
+            responses.push(response);
+            Json(Value::Array(responses)).into_response()
+        }
+    }
+}
+
+/// Rejects requests from browsers on origins that aren't allowed. Requests without an `Origin`
+/// header don't come from a web page and are let through.
+async fn check_origin(State(server): State<Arc<Server>>, request: Request, next: Next) -> Response {
+    if let Some(origin) = request.headers().get(ORIGIN) {
+        let allowed = origin.to_str().is_ok_and(|origin| {
+            is_loopback_origin(origin) || server.allowed_origins.iter().any(|o| o == origin)
+        });
+
+        if !allowed {
+            log::warn!("Rejecting request from origin {origin:?}");
+            return (StatusCode::FORBIDDEN, "Origin not allowed.").into_response();
+        }
+    }
+
+    next.run(request).await
+}
+
+fn is_loopback_origin(origin: &str) -> bool {
+    let Some(host) = Url::parse(origin)
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_owned))
+    else {
+        return false;
+    };
+
+    host == "localhost"
+        || host
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .parse::<IpAddr>()
+            .is_ok_and(|ip| ip.is_loopback())
+}
+
+async fn handle_get(State(server): State<Arc<Server>>, headers: HeaderMap) -> Response {
+    let Some(session_id) = session_id(&headers) else {
+        return (StatusCode::BAD_REQUEST, "Missing Mcp-Session-Id header.").into_response();
+    };
+
+    let (stream_tx, stream_rx) = mpsc::unbounded_channel();
+
+    match server.sessions.lock().await.get_mut(&session_id) {
+        Some(session) => session.stream_tx = Some(stream_tx),
+        None => return StatusCode::NOT_FOUND.into_response(),
+    }
+
+    let stream = stream::unfold(stream_rx, |mut stream_rx| async move {
+        let msg = stream_rx.recv().await?;
+        let event = Event::default().event("message").data(msg.to_string());
+
+        Some((Ok::<_, Infallible>(event), stream_rx))
+    });
+
+    Sse::new(stream)
+        .keep_alive(KeepAlive::default())
+        .into_response()
+}
+
+async fn handle_delete(State(server): State<Arc<Server>>, headers: HeaderMap) -> StatusCode {
+    let Some(session_id) = session_id(&headers) else {
+        return StatusCode::BAD_REQUEST;
+    };
+
+    if !server.end_session(&session_id).await {
+        return StatusCode::NOT_FOUND;
+    }
+
+    log::info!("Ending session '{session_id}'");
+    StatusCode::OK
+}
+
+async fn expire_idle_sessions(server: Arc<Server>) {
+    let mut interval = time::interval(SESSION_EXPIRY_INTERVAL);
+    loop {
+        interval.tick().await;
+        server.expire_sessions(SESSION_IDLE_TIMEOUT).await;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Mutex as StdMutex;
+
+    use async_trait::async_trait;
+    use futures_util::future;
+    use reqwest::Client;
+
+    use super::*;
+    use crate::{
+        message::MessageDirection,
+        message_interceptor::{
+            message_log::MessageLogInterceptor,
+            MessageInterceptorAction,
+            MessageInterceptorAction::{Drop, Send},
+        },
+    };
+
+    fn context(message_interceptor: Arc<dyn MessageInterceptor>) -> Arc<Context> {
+        Arc::new(Context {
+            mcp_server_name: "test".to_owned(),
+            host_session_id: None,
+            session_id: Uuid::new_v4().to_string(),
+            message_interceptor,
+        })
+    }
+
+    /// Serves a stand-in MCP server, which answers every request with the id it received, batched
+    /// or not, returning the url it's served at.
+    async fn serve_test_server(message_interceptor: Arc<dyn MessageInterceptor>) -> String {
+        let (upstream_tx, mut upstream_rx) = mpsc::channel::<Value>(10);
+        let (inbound_tx, inbound_rx) = mpsc::channel::<Value>(10);
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/mcp", listener.local_addr().unwrap());
+        task::spawn(serve(
+            context(message_interceptor),
+            listener,
+            upstream_tx,
+            inbound_rx,
+            vec!["https://app.example.com".to_owned()],
+        ));
+
+        task::spawn(async move {
+            while let Some(msg) = upstream_rx.recv().await {
+                for msg in Message::unbatch(&msg) {
+                    if let Some(id) = msg.get("id") {
+                        let response =
+                            json!({ "jsonrpc": "2.0", "id": id, "result": { "id": id } });
+                        inbound_tx.send(response).await.unwrap();
+                    }
+                }
+            }
+        });
+
+        url
+    }
+
+    #[tokio::test]
+    async fn test_sessions_share_server() {
+        let url = serve_test_server(Arc::new(MessageLogInterceptor::new(log::Level::Info))).await;
+
+        let client = Client::new();
+
+        let mut session_ids = vec![];
+        for client_id in ["a", "b"] {
+            let response = client
+                .post(&url)
+                .json(&json!({ "jsonrpc": "2.0", "id": client_id, "method": "initialize" }))
+                .send()
+                .await
+                .unwrap();
+            let session_id = response.headers()[SESSION_ID_HEADER]
+                .to_str()
+                .unwrap()
+                .to_owned();
+            let response = response.json::<Value>().await.unwrap();
+
+            // both sessions get the result of the one initialization
+            assert_eq!(response["id"], client_id);
+            assert_eq!(response["result"], json!({ "id": 1 }));
+
+            let response = client
+                .post(&url)
+                .header(SESSION_ID_HEADER, &session_id)
+                .json(&json!({ "jsonrpc": "2.0", "method": "notifications/initialized" }))
+                .send()
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::ACCEPTED);
+
+            session_ids.push(session_id);
+        }
+        assert_ne!(session_ids[0], session_ids[1]);
+
+        // both sessions use the same request id, but the server sees distinct ids
+        let mut upstream_ids = vec![];
+        for session_id in &session_ids {
+            let response = client
+                .post(&url)
+                .header(SESSION_ID_HEADER, session_id)
+                .json(&json!({ "jsonrpc": "2.0", "id": 7, "method": "tools/list" }))
+                .send()
+                .await
+                .unwrap()
+                .json::<Value>()
+                .await
+                .unwrap();
+            assert_eq!(response["id"], 7);
+
+            upstream_ids.push(response["result"]["id"].clone());
+        }
+        assert_ne!(upstream_ids[0], upstream_ids[1]);
+
+        // batches are answered with a batch of the responses to their requests
+        let response = client
+            .post(&url)
+            .header(SESSION_ID_HEADER, &session_ids[0])
+            .json(&json!([
+                { "jsonrpc": "2.0", "id": 8, "method": "tools/list" },
+                { "jsonrpc": "2.0", "method": "notifications/progress" },
+                { "jsonrpc": "2.0", "id": 9, "method": "prompts/list" },
+            ]))
+            .send()
+            .await
+            .unwrap()
+            .json::<Value>()
+            .await
+            .unwrap();
+        let ids: Vec<&Value> = response
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|response| &response["id"])
+            .collect();
+        assert_eq!(ids, vec![&json!(8), &json!(9)]);
+
+        // browsers are only let in from allowed origins
+        for (origin, status) in [
+            ("http://localhost:6274", StatusCode::OK),
+            ("http://127.0.0.1:6274", StatusCode::OK),
+            ("https://app.example.com", StatusCode::OK),
+            ("https://evil.example.com", StatusCode::FORBIDDEN),
+            ("http://localhost.evil.example.com", StatusCode::FORBIDDEN),
+        ] {
+            let response = client
+                .post(&url)
+                .header(SESSION_ID_HEADER, &session_ids[0])
+                .header(ORIGIN, origin)
+                .json(&json!({ "jsonrpc": "2.0", "id": 10, "method": "ping" }))
+                .send()
+                .await
+                .unwrap();
+            assert_eq!(response.status(), status, "{origin}");
+        }
+
+        let response = client
+            .post(&url)
+            .header(SESSION_ID_HEADER, "unknown")
+            .json(&json!({ "jsonrpc": "2.0", "id": 8, "method": "tools/list" }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let response = client
+            .delete(&url)
+            .header(SESSION_ID_HEADER, &session_ids[0])
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    /// Drops every `initialize` after the first.
+    struct DropLaterInitialize {
+        initialized: AtomicBool,
+    }
+
+    #[async_trait]
+    impl MessageInterceptor for DropLaterInitialize {
+        async fn intercept_message(
+            &self,
+            _direction: MessageDirection,
+            message: Message,
+        ) -> Result<MessageInterceptorAction> {
+            if message.raw_msg["method"] == "initialize"
+                && self.initialized.swap(true, Ordering::SeqCst)
+            {
+                return Ok(Drop);
+            }
+
+            Ok(Send(message))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_initialize_intercepted() {
+        let url = serve_test_server(Arc::new(DropLaterInitialize {
+            initialized: AtomicBool::new(false),
+        }))
+        .await;
+
+        let client = Client::new();
+        let initialize = json!({ "jsonrpc": "2.0", "id": 1, "method": "initialize" });
+
+        let response = client.post(&url).json(&initialize).send().await.unwrap();
+        assert!(response.headers().contains_key(SESSION_ID_HEADER));
+
+        // the cached result isn't handed out past the interceptor, and no session is started
+        let response = client.post(&url).json(&initialize).send().await.unwrap();
+        assert!(!response.headers().contains_key(SESSION_ID_HEADER));
+        let response = response.json::<Value>().await.unwrap();
+        assert_eq!(response["id"], 1);
+        assert_eq!(response["error"]["code"], DENIED_ERROR_CODE);
+    }
+
+    /// Holds the `initialize` request of the client named `held` forever, as an unanswered
+    /// approval would.
+    struct HoldInitialize;
+
+    #[async_trait]
+    impl MessageInterceptor for HoldInitialize {
+        async fn intercept_message(
+            &self,
+            _direction: MessageDirection,
+            message: Message,
+        ) -> Result<MessageInterceptorAction> {
+            if message.raw_msg.pointer("/params/clientInfo/name") == Some(&json!("held")) {
+                future::pending::<()>().await;
+            }
+
+            Ok(Send(message))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_initialize_held() {
+        let url = serve_test_server(Arc::new(HoldInitialize)).await;
+
+        let client = Client::new();
+        let held = client
+            .post(&url)
+            .json(&json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "method": "initialize",
+                "params": { "clientInfo": { "name": "held" } }
+            }))
+            .send();
+        task::spawn(held);
+        time::sleep(Duration::from_millis(100)).await;
+
+        // a session held for approval doesn't keep the others from initializing
+        let response = time::timeout(
+            Duration::from_secs(5),
+            client
+                .post(&url)
+                .json(&json!({ "jsonrpc": "2.0", "id": 1, "method": "initialize" }))
+                .send(),
+        )
+        .await
+        .expect("initialize was held up by another session")
+        .unwrap();
+        assert!(response.headers().contains_key(SESSION_ID_HEADER));
+        assert!(response.json::<Value>().await.unwrap()["result"].is_object());
+    }
+
+    /// Records the ids of the requests and responses it intercepts.
+    #[derive(Default)]
+    struct IdRecorder {
+        requests: StdMutex<Vec<Value>>,
+        responses: StdMutex<Vec<Value>>,
+    }
+
+    #[async_trait]
+    impl MessageInterceptor for IdRecorder {
+        async fn intercept_message(
+            &self,
+            _direction: MessageDirection,
+            message: Message,
+        ) -> Result<MessageInterceptorAction> {
+            let ids = match message.type_ {
+                MessageType::Request => &self.requests,
+                MessageType::ResponseSuccess | MessageType::ResponseFailure => &self.responses,
+                _ => return Ok(Send(message)),
+            };
+            ids.lock().unwrap().push(message.raw_msg["id"].clone());
+
+            Ok(Send(message))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_intercepted_under_proxy_ids() {
+        let recorder = Arc::new(IdRecorder::default());
+        let url = serve_test_server(recorder.clone()).await;
+
+        let client = Client::new();
+        for _ in 0..2 {
+            let response = client
+                .post(&url)
+                .json(&json!({ "jsonrpc": "2.0", "id": 1, "method": "initialize" }))
+                .send()
+                .await
+                .unwrap();
+            let session_id = response.headers()[SESSION_ID_HEADER].clone();
+
+            client
+                .post(&url)
+                .header(SESSION_ID_HEADER, session_id)
+                .json(&json!({ "jsonrpc": "2.0", "id": 1, "method": "tools/list" }))
+                .send()
+                .await
+                .unwrap();
+        }
+
+        // every request is intercepted under the id its response is, reused initializations
+        // included, so interceptors can match them up
+        let requests = recorder.requests.lock().unwrap().clone();
+        let mut responses = recorder.responses.lock().unwrap().clone();
+        responses.sort_by_key(|id| id.as_u64());
+        assert_eq!(requests, [json!(1), json!(2), json!(3), json!(4)]);
+        assert_eq!(responses, requests);
+    }
+
+    #[tokio::test]
+    async fn test_expire_sessions() {
+        let (upstream_tx, _upstream_rx) = mpsc::channel::<Value>(10);
+        let server = Server {
+            ctx: context(Arc::new(MessageLogInterceptor::new(log::Level::Info))),
+            allowed_origins: Vec::new(),
+            upstream_tx,
+            sessions: Mutex::new(HashMap::new()),
+            pending_requests: Mutex::new(HashMap::new()),
+            next_request_id: AtomicU64::new(1),
+            initialize_result: Mutex::new(None),
+            initialized: AtomicBool::new(false),
+        };
+
+        let idle_since = Instant::now() - Duration::from_secs(60);
+        let (stream_tx, _stream_rx) = mpsc::unbounded_channel();
+        let (closed_stream_tx, _) = mpsc::unbounded_channel();
+        let mut sessions = server.sessions.lock().await;
+        for (session_id, last_active, stream_tx) in [
+            ("active", Instant::now(), None),
+            ("idle", idle_since, None),
+            ("listening", idle_since, Some(stream_tx)),
+            ("disconnected", idle_since, Some(closed_stream_tx)),
+        ] {
+            sessions.insert(
+                session_id.to_owned(),
+                Session {
+                    last_active,
+                    stream_tx,
+                },
+            );
+        }
+        drop(sessions);
+
+        server.expire_sessions(Duration::from_secs(30)).await;
+
+        let mut session_ids = server
+            .sessions
+            .lock()
+            .await
+            .keys()
+            .cloned()
+            .collect::<Vec<_>>();
+        session_ids.sort();
+        assert_eq!(session_ids, ["active", "listening"]);
+    }
+}
@@ -0,0 +1,787 @@
+use std::{
+    collections::HashMap,
+    io,
+    process::{ExitStatus, Stdio},
+    time::Duration,
+};
+
+use anyhow::{Context as _, Result};
+use serde_json::{json, Value};
+use tokio::{
+    io::{AsyncWriteExt, BufReader, BufWriter},
+    process::{Child, ChildStderr, ChildStdin, Command},
+    sync::{mpsc, oneshot},
+    task, time,
+};
+
+use super::{error_response, framing::read_truncated_line, transport::receive_stream_messages};
+use crate::{
+    mcp_server::{launch::build_command, limits::ResourceLimits, McpServer, RestartPolicy},
+    message::Message,
+    server_log::ServerLogWriter,
+};
+
+/// How long an MCP server process is given to exit at each step of shutting it down.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long output left in the pipe of an exited MCP server process is waited on.
+const DRAIN_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Size of the buffers messages to and from an MCP server process go through, unless configured.
+const DEFAULT_BUFFER_SIZE: usize = 8 * 1024;
+
+/// Longest line of stderr logged, in bytes. The rest of a longer line is cut off.
+const MAX_STDERR_LINE: usize = 64 * 1024;
+
+/// Id of the `initialize` request replayed to a restarted MCP server process.
+const REPLAY_INITIALIZE_ID: &str = "mcp-guardian-replay-initialize";
+
+/// How long a restarted MCP server process is given to answer the replayed `initialize` request.
+const REPLAY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Handle to a supervised MCP server process.
+pub(crate) struct McpServerProcess {
+    shutdown_tx: oneshot::Sender<()>,
+    task: task::JoinHandle<Result<ExitStatus>>,
+}
+
+impl McpServerProcess {
+    /// Waits for the MCP server process to exit for good, after any restarts.
+    pub(crate) async fn wait(&mut self) -> Result<ExitStatus> {
+        (&mut self.task).await?
+    }
+
+    /// Shuts the MCP server process down and waits for it to exit.
+    pub(crate) async fn shutdown(self) -> Result<ExitStatus> {
+        let _ = self.shutdown_tx.send(());
+        self.task.await?
+    }
+}
+
+/// Spawns an MCP server process, restarting it according to its restart policy.
+///
+/// A restarted process is sent the host's `initialize` handshake again before any other messages,
+/// and requests the crashed process never responded to are answered with errors, so the host's
+/// session survives the restart. A restarted process that rejects the handshake, or doesn't answer
+/// it in time, is killed and counts as a failed restart. Restarts are counted afresh once a
+/// restarted process has been initialized again, or a process stayed up for longer than the
+/// longest backoff.
+///
+/// Returns the buffer messages for the server are sent to, the buffer messages from the server
+/// are received on, and a handle to the process.
+///
+/// The stderr of the process is written to `stderr_log` if given, and inherited otherwise.
+pub(crate) fn spawn_mcp_server(
+    mcp_server: &McpServer,
+    stderr_log: Option<ServerLogWriter>,
+) -> Result<(mpsc::Sender<Value>, mpsc::Receiver<Value>, McpServerProcess)> {
+    let mut command = build_command(mcp_server)?;
+    command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(match stderr_log {
+            Some(_) => Stdio::piped(),
+            None => Stdio::inherit(),
+        });
+
+    let mut command = Command::from(command);
+    command.kill_on_drop(true);
+
+    // Upstream Message Buffer
+    let (upstream_tx, upstream_rx) = mpsc::channel::<Value>(100);
+    // Inbound Message Buffer
+    let (inbound_tx, inbound_rx) = mpsc::channel::<Value>(100);
+
+    let (shutdown_tx, shutdown_rx) = oneshot::channel();
+
+    let supervisor = Supervisor {
+        cmd: mcp_server.cmd.clone(),
+        command,
+        restart: mcp_server.restart.clone(),
+        limits: mcp_server.limits.clone(),
+        stdin_buffer_size: mcp_server
+            .stdin_buffer_size
+            .map_or(DEFAULT_BUFFER_SIZE, |size| size as usize),
+        stdout_buffer_size: mcp_server
+            .stdout_buffer_size
+            .map_or(DEFAULT_BUFFER_SIZE, |size| size as usize),
+        stderr_log_tx: stderr_log.map(|stderr_log| {
+            let (stderr_log_tx, stderr_log_rx) = mpsc::channel(100);
+            task::spawn_blocking(move || write_stderr_log(stderr_log, stderr_log_rx));
+            stderr_log_tx
+        }),
+        upstream_rx,
+        inbound_tx,
+        shutdown_rx,
+        initialize: None,
+        initialized: None,
+        in_flight: HashMap::new(),
+        restarts: 0,
+        backoff: Duration::ZERO,
+    };
+
+    let task = task::spawn(supervisor.run());
+
+    Ok((
+        upstream_tx,
+        inbound_rx,
+        McpServerProcess { shutdown_tx, task },
+    ))
+}
+
+/// How a single MCP server process ended.
+enum ChildExit {
+    /// The process exited on its own.
+    Exited(ExitStatus),
+    /// The process was stopped by the proxy.
+    Stopped(ExitStatus),
+}
+
+struct Supervisor {
+    cmd: String,
+    command: Command,
+    restart: Option<RestartPolicy>,
+    limits: Option<ResourceLimits>,
+    stdin_buffer_size: usize,
+    stdout_buffer_size: usize,
+    /// Buffer of the log shared by every process started, as they are restarted
+    stderr_log_tx: Option<mpsc::Sender<String>>,
+    upstream_rx: mpsc::Receiver<Value>,
+    inbound_tx: mpsc::Sender<Value>,
+    shutdown_rx: oneshot::Receiver<()>,
+    /// The host's `initialize` request, once a process has accepted it
+    initialize: Option<Value>,
+    /// The host's `notifications/initialized` notification, once sent
+    initialized: Option<Value>,
+    /// Requests sent to the current process that it hasn't responded to yet, by id
+    in_flight: HashMap<String, Value>,
+    /// Restarts since a process last recovered
+    restarts: u32,
+    /// Delay before the next restart
+    backoff: Duration,
+}
+
+impl Supervisor {
+    async fn run(mut self) -> Result<ExitStatus> {
+        self.reset_restarts();
+
+        loop {
+            let started = time::Instant::now();
+            let mut child = self
+                .command
+                .spawn()
+                .with_context(|| format!("Failed to start MCP server process `{}`", self.cmd))?;
+
+            log::info!("Started MCP server process (pid {:?})", child.id());
+
+            let stdin = BufWriter::with_capacity(
+                self.stdin_buffer_size,
+                child.stdin.take().context("Failed to open child stdin")?,
+            );
+            let stdout = child.stdout.take().context("Failed to open child stdout")?;
+
+            // Inbound Message Reception
+            //
+            // 1. Read json-rpc message from child stdout.
+            // 2. Send to child message buffer.
+            log::info!("Starting inbound message receiver");
+            let (child_tx, child_rx) = mpsc::channel::<Value>(100);
+            task::spawn(receive_stream_messages(
+                BufReader::with_capacity(self.stdout_buffer_size, stdout),
+                "MCP server",
+                child_tx,
+            ));
+
+            if let (Some(stderr), Some(stderr_log_tx)) = (child.stderr.take(), &self.stderr_log_tx)
+            {
+                task::spawn(log_stderr(stderr, stderr_log_tx.clone()));
+            }
+
+            let status = match self.run_child(&mut child, stdin, child_rx).await? {
+                ChildExit::Exited(status) => status,
+                ChildExit::Stopped(status) => {
+                    log::info!("MCP server process stopped with {status}.");
+                    return Ok(status);
+                }
+            };
+
+            let Some(restart) = &self.restart else {
+                log::info!("MCP server process exited with {status}.");
+                return Ok(status);
+            };
+
+            if status.success() {
+                log::info!("MCP server process exited with {status}.");
+                return Ok(status);
+            }
+
+            let max_retries = restart.max_retries;
+            let max_backoff = Duration::from_millis(restart.max_backoff_ms.into());
+
+            // a process that stayed up for a while crashed anew rather than failing to start
+            if started.elapsed() > max_backoff {
+                self.reset_restarts();
+            }
+
+            if self.restarts >= max_retries {
+                log::error!(
+                    "MCP server process exited with {status}. Giving up after {} restarts.",
+                    self.restarts
+                );
+                return Ok(status);
+            }
+
+            self.restarts += 1;
+            log::warn!(
+                "MCP server process exited with {status}. Restarting in {:?} (attempt {}/{max_retries}).",
+                self.backoff,
+                self.restarts
+            );
+
+            tokio::select! {
+                _ = time::sleep(self.backoff) => {}
+                _ = &mut self.shutdown_rx => return Ok(status),
+            }
+
+            self.backoff = (self.backoff * 2).min(max_backoff);
+        }
+    }
+
+    /// Forgets earlier restarts once a process has recovered, so that a later crash is retried
+    /// from the initial backoff again.
+    fn reset_restarts(&mut self) {
+        self.restarts = 0;
+        self.backoff = Duration::from_millis(
+            self.restart
+                .as_ref()
+                .map_or(0, |restart| restart.initial_backoff_ms.into()),
+        );
+    }
+
+    /// Relays messages to and from a running MCP server process until it exits or is stopped.
+    async fn run_child(
+        &mut self,
+        child: &mut Child,
+        mut stdin: BufWriter<ChildStdin>,
+        mut child_rx: mpsc::Receiver<Value>,
+    ) -> Result<ChildExit> {
+        // Hold back upstream messages until a restarted process has been initialized again.
+        let mut replaying = false;
+        let replay_timeout = time::sleep(REPLAY_TIMEOUT);
+        tokio::pin!(replay_timeout);
+
+        if let Some(initialize) = &self.initialize {
+            log::info!("Replaying initialize handshake to restarted MCP server process.");
+            let mut initialize = initialize.clone();
+            initialize["id"] = json!(REPLAY_INITIALIZE_ID);
+
+            if let Err(e) = write_message(&mut stdin, &initialize).await {
+                log::error!("Failed to write to child stdin: {e}");
+            }
+            replaying = true;
+        }
+
+        // Upstream Message Transmission
+        //
+        // 1. Read from upstream message buffer.
+        // 2. Write to child stdin.
+        //
+        // Inbound Message Transmission
+        //
+        // 1. Read from child message buffer.
+        // 2. Send to inbound message buffer.
+        loop {
+            tokio::select! {
+                status = child.wait() => {
+                    let status = status?;
+                    let exceeded_limit = self
+                        .limits
+                        .as_ref()
+                        .and_then(|limits| limits.exceeded_limit(&status));
+                    if let Some(limit) = &exceeded_limit {
+                        log::error!("MCP server process was killed for exceeding {limit}.");
+                    }
+
+                    // Deliver responses the process wrote before it exited.
+                    while let Ok(Some(msg)) = time::timeout(DRAIN_TIMEOUT, child_rx.recv()).await {
+                        self.receive(msg).await;
+                    }
+                    self.fail_in_flight(exceeded_limit.as_deref()).await;
+
+                    return Ok(ChildExit::Exited(status));
+                }
+                Some(msg) = child_rx.recv() => {
+                    if replaying && msg.get("id") == Some(&json!(REPLAY_INITIALIZE_ID)) {
+                        replaying = false;
+
+                        if let Some(error) = msg.get("error") {
+                            log::error!("Restarted MCP server process rejected the replayed initialize request: {error}");
+                            return self.fail_restart(child).await;
+                        }
+                        if let Some(initialized) = &self.initialized {
+                            if let Err(e) = write_message(&mut stdin, initialized).await {
+                                log::error!("Failed to write to child stdin: {e}");
+                            }
+                        }
+                        self.reset_restarts();
+                        continue;
+                    }
+
+                    self.receive(msg).await;
+                }
+                _ = &mut replay_timeout, if replaying => {
+                    log::error!("Restarted MCP server process did not answer the replayed initialize request in time.");
+                    return self.fail_restart(child).await;
+                }
+                msg = self.upstream_rx.recv(), if !replaying => match msg {
+                    Some(msg) => self.send(&mut stdin, msg).await,
+                    None => return Ok(ChildExit::Stopped(terminate(child, stdin).await?)),
+                },
+                _ = &mut self.shutdown_rx => {
+                    // Deliver whatever the host sent before it went away.
+                    while let Ok(msg) = self.upstream_rx.try_recv() {
+                        self.send(&mut stdin, msg).await;
+                    }
+
+                    return Ok(ChildExit::Stopped(terminate(child, stdin).await?));
+                }
+            }
+        }
+    }
+
+    /// Kills a restarted MCP server process that failed to be initialized again, answering the
+    /// requests the host sent in the meantime with errors.
+    async fn fail_restart(&mut self, child: &mut Child) -> Result<ChildExit> {
+        child.kill().await?;
+        let status = child.wait().await?;
+
+        while let Ok(msg) = self.upstream_rx.try_recv() {
+            for msg in Message::unbatch(&msg) {
+                if let (Some(_), Some(id)) = (msg.get("method"), msg.get("id")) {
+                    let response =
+                        error_response(id.clone(), -32603, "MCP server process failed to restart.");
+
+                    if let Err(e) = self.inbound_tx.send(response).await {
+                        log::error!("Failed to send message to inbound buffer: {e}");
+                    }
+                }
+            }
+        }
+
+        Ok(ChildExit::Exited(status))
+    }
+
+    /// Writes a message from the host to the MCP server process, keeping track of requests and
+    /// the initialize handshake. Cancelled requests are no longer tracked, as they get no response.
+    async fn send(&mut self, stdin: &mut BufWriter<ChildStdin>, msg: Value) {
+        for msg in Message::unbatch(&msg) {
+            if let (Some(_), Some(id)) = (msg.get("method"), msg.get("id")) {
+                self.in_flight.insert(id.to_string(), msg.clone());
+            } else if msg.get("method") == Some(&json!("notifications/initialized")) {
+                self.initialized = Some(msg.clone());
+            } else if msg.get("method") == Some(&json!("notifications/cancelled")) {
+                if let Some(request_id) = msg.pointer("/params/requestId") {
+                    self.in_flight.remove(&request_id.to_string());
+                }
+            }
+        }
+
+        if let Err(e) = write_message(stdin, &msg).await {
+            log::error!("Failed to write to child stdin: {e}");
+        }
+    }
+
+    /// Passes a message from the MCP server process on to the inbound message buffer.
+    async fn receive(&mut self, msg: Value) {
+        for msg in Message::unbatch(&msg) {
+            if let (None, Some(id)) = (msg.get("method"), msg.get("id")) {
+                if let Some(request) = self.in_flight.remove(&id.to_string()) {
+                    if request.get("method") == Some(&json!("initialize"))
+                        && msg.get("result").is_some()
+                    {
+                        self.initialize = Some(request);
+                    }
+                }
+            }
+        }
+
+        if let Err(e) = self.inbound_tx.send(msg).await {
+            log::error!("Failed to send message to inbound buffer: {e}");
+        }
+    }
+
+    /// Answers every request the exited MCP server process didn't respond to with an error, naming
+    /// the resource limit it exceeded if it was killed for doing so.
+    async fn fail_in_flight(&mut self, exceeded_limit: Option<&str>) {
+        let message = match exceeded_limit {
+            Some(limit) => format!("MCP server process exceeded {limit} before responding."),
+            None => "MCP server process exited before responding.".to_owned(),
+        };
+
+        for (_, request) in self.in_flight.drain() {
+            let response = json!({
+                "jsonrpc": "2.0",
+                "id": request["id"],
+                "error": {
+                    "code": -32603,
+                    "message": message,
+                }
+            });
+
+            if let Err(e) = self.inbound_tx.send(response).await {
+                log::error!("Failed to send message to inbound buffer: {e}");
+            }
+        }
+    }
+}
+
+/// Reads the stderr of an MCP server process line by line into the buffer of its log.
+async fn log_stderr(stderr: ChildStderr, stderr_log_tx: mpsc::Sender<String>) {
+    let mut stderr = BufReader::new(stderr);
+
+    loop {
+        match read_truncated_line(&mut stderr, MAX_STDERR_LINE).await {
+            Ok(Some((line, truncated))) => {
+                let line = String::from_utf8_lossy(&line);
+                let mut line = line.trim_end_matches('\r').to_owned();
+                if truncated > 0 {
+                    line.push_str(&format!(" [{truncated} more bytes cut off]"));
+                }
+
+                if stderr_log_tx.send(line).await.is_err() {
+                    break;
+                }
+            }
+            Ok(None) => break,
+            Err(e) => {
+                log::error!("Failed to read from child stderr: {e}");
+                break;
+            }
+        }
+    }
+}
+
+/// Writes lines of stderr from `stderr_log_rx` to the log until the buffer is closed. Blocking.
+fn write_stderr_log(mut stderr_log: ServerLogWriter, mut stderr_log_rx: mpsc::Receiver<String>) {
+    while let Some(line) = stderr_log_rx.blocking_recv() {
+        if let Err(e) = stderr_log.write_line(&line) {
+            log::error!("Failed to write to MCP server log: {e}");
+        }
+    }
+}
+
+async fn write_message(stdin: &mut BufWriter<ChildStdin>, msg: &Value) -> io::Result<()> {
+    stdin.write_all(format!("{msg}\n").as_bytes()).await?;
+    stdin.flush().await
+}
+
+/// Shuts an MCP server process down the way the MCP stdio transport specifies: closes its stdin,
+/// then sends SIGTERM, then SIGKILL, waiting for it to exit in between.
+async fn terminate(child: &mut Child, stdin: BufWriter<ChildStdin>) -> Result<ExitStatus> {
+    drop(stdin);
+
+    if let Ok(status) = time::timeout(SHUTDOWN_TIMEOUT, child.wait()).await {
+        return Ok(status?);
+    }
+
+    #[cfg(unix)]
+    {
+        log::warn!("MCP server process did not exit after its stdin was closed. Sending SIGTERM.");
+        signal::send(child, nix::sys::signal::Signal::SIGTERM)?;
+    }
+
+    wait_or_kill(child).await
+}
+
+/// Waits for an MCP server process to exit, killing it if it doesn't exit in time.
+async fn wait_or_kill(child: &mut Child) -> Result<ExitStatus> {
+    if let Ok(status) = time::timeout(SHUTDOWN_TIMEOUT, child.wait()).await {
+        return Ok(status?);
+    }
+
+    log::warn!("MCP server process did not exit in time. Killing it.");
+    child.kill().await?;
+
+    Ok(child.wait().await?)
+}
+
+#[cfg(unix)]
+mod signal {
+    use anyhow::Result;
+    use nix::{
+        sys::signal::{kill, Signal},
+        unistd::Pid,
+    };
+    use tokio::process::Child;
+
+    pub(super) fn send(child: &mut Child, signal: Signal) -> Result<()> {
+        if let Some(pid) = child.id() {
+            kill(Pid::from_raw(pid as i32), signal)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(all(test, unix))]
+mod test {
+    use serde_json::json;
+
+    use super::*;
+
+    fn sh(script: &str, restart: Option<RestartPolicy>) -> McpServer {
+        McpServer {
+            cmd: "sh".to_owned(),
+            args: vec!["-c".to_owned(), script.to_owned()],
+            restart,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_exit_status() {
+        let (_upstream_tx, _inbound_rx, mut process) =
+            spawn_mcp_server(&sh("exit 3", None), None).unwrap();
+
+        assert_eq!(process.wait().await.unwrap().code(), Some(3));
+    }
+
+    #[tokio::test]
+    async fn test_restart() {
+        let restart = RestartPolicy {
+            max_retries: 2,
+            initial_backoff_ms: 10,
+            max_backoff_ms: 60_000,
+        };
+        let (_upstream_tx, mut inbound_rx, mut process) = spawn_mcp_server(
+            &sh(r#"echo '{"started":true}'; exit 1"#, Some(restart)),
+            None,
+        )
+        .unwrap();
+
+        assert_eq!(process.wait().await.unwrap().code(), Some(1));
+
+        let mut starts = 0;
+        while let Ok(msg) = inbound_rx.try_recv() {
+            assert_eq!(msg, json!({ "started": true }));
+            starts += 1;
+        }
+        assert_eq!(starts, 3);
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_closes_stdin() {
+        let (upstream_tx, mut inbound_rx, process) = spawn_mcp_server(
+            &sh("while read -r line; do echo \"$line\"; done", None),
+            None,
+        )
+        .unwrap();
+
+        upstream_tx.send(json!({ "id": 1 })).await.unwrap();
+        assert_eq!(inbound_rx.recv().await, Some(json!({ "id": 1 })));
+
+        assert!(process.shutdown().await.unwrap().success());
+    }
+
+    #[tokio::test]
+    async fn test_restart_replays_handshake() {
+        // Answers requests with an empty result, echoes notifications back and exits on `crash`.
+        let script = r#"
+            while read -r line; do
+                id=$(echo "$line" | sed -n 's/.*"id":\([^,}]*\).*/\1/p')
+                case "$line" in
+                    *'"method":"crash"'*) exit 1 ;;
+                    *'"method":"notifications/'*) echo "{\"jsonrpc\":\"2.0\",\"method\":\"seen\",\"params\":$line}" ;;
+                    *) echo "{\"jsonrpc\":\"2.0\",\"id\":$id,\"result\":{}}" ;;
+                esac
+            done
+        "#;
+        let restart = RestartPolicy {
+            max_retries: 1,
+            initial_backoff_ms: 10,
+            max_backoff_ms: 60_000,
+        };
+        let (upstream_tx, mut inbound_rx, process) =
+            spawn_mcp_server(&sh(script, Some(restart)), None).unwrap();
+
+        let initialized = json!({ "jsonrpc": "2.0", "method": "notifications/initialized" });
+        let seen_initialized = json!({ "jsonrpc": "2.0", "method": "seen", "params": initialized });
+
+        upstream_tx
+            .send(json!({ "jsonrpc": "2.0", "id": 1, "method": "initialize" }))
+            .await
+            .unwrap();
+        assert_eq!(
+            inbound_rx.recv().await,
+            Some(json!({ "jsonrpc": "2.0", "id": 1, "result": {} }))
+        );
+
+        upstream_tx.send(initialized.clone()).await.unwrap();
+        assert_eq!(inbound_rx.recv().await, Some(seen_initialized.clone()));
+
+        // Restarts are counted afresh once the restarted process is initialized again, so it can
+        // crash more often than the single restart allowed.
+        for id in 2..4 {
+            upstream_tx
+                .send(json!({ "jsonrpc": "2.0", "id": id, "method": "crash" }))
+                .await
+                .unwrap();
+            let response = inbound_rx.recv().await.unwrap();
+            assert_eq!(response["id"], json!(id));
+            assert!(response.get("error").is_some());
+
+            // The restarted process is initialized again without the host noticing.
+            assert_eq!(inbound_rx.recv().await, Some(seen_initialized.clone()));
+        }
+
+        upstream_tx
+            .send(json!({ "jsonrpc": "2.0", "id": 4, "method": "ping" }))
+            .await
+            .unwrap();
+        assert_eq!(
+            inbound_rx.recv().await,
+            Some(json!({ "jsonrpc": "2.0", "id": 4, "result": {} }))
+        );
+
+        assert!(process.shutdown().await.unwrap().success());
+    }
+
+    #[tokio::test]
+    async fn test_failed_replay() {
+        let marker =
+            std::env::temp_dir().join(format!("mcp-guardian-replay-{}", std::process::id()));
+        let _ = std::fs::remove_file(&marker);
+
+        // Works the first time, then answers every request with an error once restarted.
+        let script = format!(
+            r#"
+            if [ -e '{marker}' ]; then restarted=1; else touch '{marker}'; fi
+            while read -r line; do
+                id=$(echo "$line" | sed -n 's/.*"id":\([^,}}]*\).*/\1/p')
+                case "$line" in
+                    *'"method":"crash"'*) exit 1 ;;
+                    *'"method":"notifications/'*) ;;
+                    *) if [ -n "$restarted" ]; then
+                           echo "{{\"jsonrpc\":\"2.0\",\"id\":$id,\"error\":{{\"code\":-32603,\"message\":\"no\"}}}}"
+                       else
+                           echo "{{\"jsonrpc\":\"2.0\",\"id\":$id,\"result\":{{}}}}"
+                       fi ;;
+                esac
+            done
+            "#,
+            marker = marker.display()
+        );
+        let restart = RestartPolicy {
+            max_retries: 1,
+            initial_backoff_ms: 10,
+            max_backoff_ms: 60_000,
+        };
+        let (upstream_tx, mut inbound_rx, mut process) =
+            spawn_mcp_server(&sh(&script, Some(restart)), None).unwrap();
+
+        upstream_tx
+            .send(json!({ "jsonrpc": "2.0", "id": 1, "method": "initialize" }))
+            .await
+            .unwrap();
+        assert_eq!(
+            inbound_rx.recv().await,
+            Some(json!({ "jsonrpc": "2.0", "id": 1, "result": {} }))
+        );
+
+        for msg in [
+            json!({ "jsonrpc": "2.0", "id": 2, "method": "crash" }),
+            json!({ "jsonrpc": "2.0", "id": 3, "method": "ping" }),
+        ] {
+            upstream_tx.send(msg).await.unwrap();
+        }
+
+        // Neither request is left unanswered, whichever process they reached.
+        let mut ids = vec![];
+        for _ in 0..2 {
+            let response = inbound_rx.recv().await.unwrap();
+            assert!(response.get("error").is_some());
+            ids.push(response["id"].as_u64().unwrap());
+        }
+        ids.sort();
+        assert_eq!(ids, vec![2, 3]);
+
+        // Rejecting the handshake is a failed restart, and the only one allowed.
+        assert!(!process.wait().await.unwrap().success());
+
+        let _ = std::fs::remove_file(&marker);
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_request_not_failed() {
+        let (upstream_tx, mut inbound_rx, mut process) =
+            spawn_mcp_server(&sh("read request; read cancelled; exit 1", None), None).unwrap();
+
+        for msg in [
+            json!({ "jsonrpc": "2.0", "id": 1, "method": "tools/call" }),
+            json!({
+                "jsonrpc": "2.0",
+                "method": "notifications/cancelled",
+                "params": { "requestId": 1 }
+            }),
+        ] {
+            upstream_tx.send(msg).await.unwrap();
+        }
+
+        // the cancelled request gets no response, not even once the process exits
+        assert!(!process.wait().await.unwrap().success());
+        assert_eq!(inbound_rx.try_recv().ok(), None);
+    }
+
+    #[tokio::test]
+    async fn test_exceeded_limit() {
+        let mcp_server = McpServer {
+            limits: Some(ResourceLimits {
+                max_cpu_seconds: Some(1),
+                ..Default::default()
+            }),
+            ..sh("read request; while :; do :; done", None)
+        };
+        let (upstream_tx, mut inbound_rx, mut process) =
+            spawn_mcp_server(&mcp_server, None).unwrap();
+
+        upstream_tx
+            .send(json!({"jsonrpc": "2.0", "id": 1, "method": "tools/list"}))
+            .await
+            .unwrap();
+
+        assert!(!process.wait().await.unwrap().success());
+        assert_eq!(
+            inbound_rx.recv().await.unwrap(),
+            json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "error": {
+                    "code": -32603,
+                    "message": "MCP server process exceeded its CPU time limit of 1 s before responding.",
+                }
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_launch_options() {
+        let dir = std::env::temp_dir().canonicalize().unwrap();
+        let mcp_server = McpServer {
+            cmd: r#"printf '{"cwd":"%s","umask":"%s","arg":"%s"}\n' "$(pwd)" "$(umask)""#
+                .to_owned(),
+            args: vec!["it's quoted".to_owned()],
+            cwd: Some(dir.to_string_lossy().into_owned()),
+            shell: Some("sh".to_owned()),
+            umask: Some("027".to_owned()),
+            stdout_buffer_size: Some(16),
+            ..Default::default()
+        };
+
+        let (_upstream_tx, mut inbound_rx, _process) = spawn_mcp_server(&mcp_server, None).unwrap();
+
+        let msg = inbound_rx.recv().await.unwrap();
+        assert_eq!(msg["cwd"], json!(dir.to_string_lossy()));
+        assert_eq!(msg["umask"], json!("0027"));
+        assert_eq!(msg["arg"], json!("it's quoted"));
+    }
+}
@@ -0,0 +1,150 @@
+use serde_json::Value;
+use tokio::{
+    io::{AsyncBufRead, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader, BufWriter},
+    sync::mpsc,
+    task,
+};
+
+use super::{framing::MessageReader, transmit_host_messages};
+
+/// How the proxy exchanges json-rpc messages with the host, or with an MCP server.
+pub enum Transport {
+    /// The stdio of the proxy process, as the MCP stdio transport specifies. Host side only.
+    Stdio,
+    /// Messages framed as the MCP stdio transport specifies, one per line, over a byte stream
+    Stream {
+        reader: Box<dyn AsyncRead + Send + Unpin>,
+        writer: Box<dyn AsyncWrite + Send + Unpin>,
+    },
+    /// Messages exchanged in memory: sent on `tx`, received on `rx`
+    Channel {
+        tx: mpsc::Sender<Value>,
+        rx: mpsc::Receiver<Value>,
+    },
+}
+
+impl Transport {
+    pub fn stream(
+        reader: impl AsyncRead + Send + Unpin + 'static,
+        writer: impl AsyncWrite + Send + Unpin + 'static,
+    ) -> Self {
+        Self::Stream {
+            reader: Box::new(reader),
+            writer: Box::new(writer),
+        }
+    }
+
+    pub fn channel(tx: mpsc::Sender<Value>, rx: mpsc::Receiver<Value>) -> Self {
+        Self::Channel { tx, rx }
+    }
+
+    /// Starts exchanging messages over the transport with `peer`, which names it in logs.
+    pub(crate) fn connect(self, peer: &'static str) -> Connection {
+        // Messages for the peer
+        let (tx, peer_rx) = mpsc::channel::<Value>(100);
+        // Messages from the peer
+        let (peer_tx, rx) = mpsc::channel::<Value>(100);
+
+        let (reader, writer) = match self {
+            Self::Stdio => (
+                task::spawn(receive_stream_messages(
+                    BufReader::new(tokio::io::stdin()),
+                    peer,
+                    peer_tx,
+                )),
+                task::spawn_blocking(move || transmit_host_messages(peer_rx)),
+            ),
+            Self::Stream { reader, writer } => (
+                task::spawn(receive_stream_messages(
+                    BufReader::new(reader),
+                    peer,
+                    peer_tx,
+                )),
+                task::spawn(transmit_stream_messages(writer, peer, peer_rx)),
+            ),
+            Self::Channel { tx, rx } => (
+                task::spawn(forward_messages(rx, peer_tx)),
+                task::spawn(forward_messages(peer_rx, tx)),
+            ),
+        };
+
+        Connection {
+            tx,
+            rx,
+            reader,
+            writer,
+        }
+    }
+}
+
+/// Messages exchanged with a peer over a transport.
+pub(crate) struct Connection {
+    /// Buffer of messages for the peer. The transport is closed once it is.
+    pub(crate) tx: mpsc::Sender<Value>,
+    /// Buffer of messages from the peer, closed once the peer closes the transport
+    pub(crate) rx: mpsc::Receiver<Value>,
+    /// Completes once the peer closes the transport
+    pub(crate) reader: task::JoinHandle<()>,
+    /// Completes once every message for the peer is written and the transport is closed
+    pub(crate) writer: task::JoinHandle<()>,
+}
+
+/// Reads messages from `peer` into `peer_tx` until the peer closes the transport, or `peer_tx` is
+/// closed.
+pub(crate) async fn receive_stream_messages(
+    reader: impl AsyncBufRead + Unpin,
+    peer: &'static str,
+    peer_tx: mpsc::Sender<Value>,
+) {
+    let mut reader = MessageReader::new(reader, peer);
+
+    loop {
+        match reader.next_message().await {
+            Ok(Some(msg)) => {
+                if peer_tx.send(msg).await.is_err() {
+                    break;
+                }
+            }
+            Ok(None) => break,
+            Err(e) => {
+                log::error!("Failed to read from {peer}: {e}");
+                break;
+            }
+        }
+    }
+}
+
+async fn transmit_stream_messages(
+    writer: Box<dyn AsyncWrite + Send + Unpin>,
+    peer: &'static str,
+    mut peer_rx: mpsc::Receiver<Value>,
+) {
+    let mut writer = BufWriter::new(writer);
+
+    while let Some(msg) = peer_rx.recv().await {
+        let mut line = msg.to_string();
+        line.push('\n');
+
+        // the peer is gone, so later messages couldn't be written either
+        if let Err(e) = writer.write_all(line.as_bytes()).await {
+            log::error!("Failed to write to {peer}: {e}");
+            break;
+        }
+        if let Err(e) = writer.flush().await {
+            log::error!("Failed to flush {peer}: {e}");
+            break;
+        }
+    }
+
+    if let Err(e) = writer.shutdown().await {
+        log::warn!("Failed to close {peer}: {e}");
+    }
+}
+
+async fn forward_messages(mut rx: mpsc::Receiver<Value>, tx: mpsc::Sender<Value>) {
+    while let Some(msg) = rx.recv().await {
+        if tx.send(msg).await.is_err() {
+            break;
+        }
+    }
+}
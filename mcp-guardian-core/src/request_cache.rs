@@ -33,6 +33,18 @@ impl RequestCache {
         Ok(())
     }
 
+    /// Looks up a cached request without removing it from the cache.
+    pub fn get_request(&self, id: &Value) -> Result<Option<Value>> {
+        let request = self
+            .cache
+            .lock()
+            .expect("Error unlocking mutex")
+            .get(id)
+            .cloned();
+
+        Ok(request)
+    }
+
     pub fn pop_request(&self, id: &Value) -> Result<Option<Value>> {
         let request = self.cache.lock().expect("Error unlocking mutex").remove(id);
 
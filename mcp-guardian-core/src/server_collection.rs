@@ -2,7 +2,7 @@ pub mod claude_config;
 
 use std::fs;
 
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, bail, Result};
 use serde::{Deserialize, Serialize};
 use ts_rs::TS;
 
@@ -12,6 +12,41 @@ use crate::dirs::AppSubDir::ServerCollections;
 #[ts(export)]
 pub struct ServerCollection {
     pub servers: Vec<Server>,
+    /// Runs every server behind a single proxy, which the host sees as one MCP server, rather than
+    /// a proxy per server.
+    #[serde(default)]
+    pub aggregate: bool,
+}
+
+impl ServerCollection {
+    /// Checks that the names of every server can be told apart from the others' when the
+    /// collection is aggregated, i.e. that no server's prefix starts with another's.
+    pub fn check_prefixes(&self) -> Result<()> {
+        if !self.aggregate {
+            return Ok(());
+        }
+
+        let prefixes = self
+            .servers
+            .iter()
+            .map(|server| (&server.mcp_server, server.prefix()))
+            .collect::<Vec<_>>();
+
+        for (i, (mcp_server, prefix)) in prefixes.iter().enumerate() {
+            for (other_mcp_server, other_prefix) in &prefixes[i + 1..] {
+                if prefix.starts_with(other_prefix.as_str())
+                    || other_prefix.starts_with(prefix.as_str())
+                {
+                    bail!(
+                        "Prefixes of '{mcp_server}' ('{prefix}') and '{other_mcp_server}' \
+                         ('{other_prefix}') overlap. Set a distinct prefix for either."
+                    );
+                }
+            }
+        }
+
+        Ok(())
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, TS)]
@@ -19,6 +54,24 @@ pub struct ServerCollection {
 pub struct Server {
     pub mcp_server: String,
     pub guard_profile: String,
+    /// Prepended to the names of the server's tools, prompts and resources when the collection is
+    /// aggregated. Defaults to the name of the MCP server followed by `__`.
+    #[serde(default)]
+    #[ts(optional = nullable)]
+    pub prefix: Option<String>,
+}
+
+impl Server {
+    /// The prefix of the server's names when the collection is aggregated.
+    pub fn prefix(&self) -> String {
+        match &self.prefix {
+            Some(prefix) => prefix.clone(),
+            None => {
+                let name = self.mcp_server.rsplit('.').next().unwrap_or_default();
+                format!("{name}__")
+            }
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, TS)]
@@ -42,6 +95,7 @@ pub fn load_server_collection(namespace: &str, name: &str) -> Result<Option<Serv
 
     let server_collection = fs::read_to_string(&file_path)?;
     let server_collection = serde_json::from_str::<ServerCollection>(&server_collection)?;
+    server_collection.check_prefixes()?;
 
     log::info!(
         "{} servers found in collection '{name}'.",
@@ -57,6 +111,8 @@ pub fn save_server_collection(
     server_collection: &ServerCollection,
 ) -> Result<()> {
     log::info!("Saving server collection.");
+    server_collection.check_prefixes()?;
+
     let json_str = serde_json::to_string_pretty(server_collection)?;
 
     let dir_path = ServerCollections.path()?.join(namespace);
@@ -148,3 +204,34 @@ pub fn delete_server_collection(namespace: &str, name: &str) -> Result<()> {
     );
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn server(mcp_server: &str, prefix: Option<&str>) -> Server {
+        Server {
+            mcp_server: mcp_server.to_owned(),
+            guard_profile: "core.log-only".to_owned(),
+            prefix: prefix.map(str::to_owned),
+        }
+    }
+
+    #[test]
+    fn test_check_prefixes() {
+        let mut server_collection = ServerCollection {
+            servers: vec![server("a.time", None), server("b.time", None)],
+            aggregate: false,
+        };
+        assert!(server_collection.check_prefixes().is_ok());
+
+        server_collection.aggregate = true;
+        assert!(server_collection.check_prefixes().is_err());
+
+        server_collection.servers[1].prefix = Some("time__b__".to_owned());
+        assert!(server_collection.check_prefixes().is_err());
+
+        server_collection.servers[1].prefix = Some("b_time__".to_owned());
+        assert!(server_collection.check_prefixes().is_ok());
+    }
+}
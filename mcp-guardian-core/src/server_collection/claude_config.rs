@@ -3,7 +3,10 @@ use std::{collections::HashMap, fs, fs::File, io::BufReader, path::PathBuf};
 use anyhow::{anyhow, bail, Result};
 use serde::{Deserialize, Serialize};
 
-use crate::server_collection::{load_server_collection, Server};
+use crate::{
+    mcp_server::load_mcp_server,
+    server_collection::{load_server_collection, Server},
+};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -17,6 +20,8 @@ pub struct ClaudeMcpServer {
     pub args: Vec<String>,
     #[serde(default, skip_serializing_if = "HashMap::is_empty")]
     pub env: HashMap<String, String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cwd: Option<String>,
 }
 
 pub fn generate_claude_config_for_server_collection(
@@ -28,21 +33,40 @@ pub fn generate_claude_config_for_server_collection(
     let server_collection = load_server_collection(namespace, name)?
         .ok_or_else(|| anyhow!("Server collection not found"))?;
 
+    let command = match proxy_path.clone() {
+        Some(proxy_path) => proxy_path.to_string_lossy().to_string(),
+        None => "mcp-guardian-proxy".to_owned(),
+    };
+
     let mut mcp_servers = HashMap::new();
 
+    if server_collection.aggregate {
+        mcp_servers.insert(
+            name.to_owned(),
+            ClaudeMcpServer {
+                command,
+                args: vec![
+                    "--server-collection".to_owned(),
+                    format!("{namespace}.{name}"),
+                ],
+                env: HashMap::new(),
+                cwd: None,
+            },
+        );
+
+        return Ok(ClaudeConfig { mcp_servers });
+    }
+
     for Server {
         mcp_server,
         guard_profile,
+        ..
     } in server_collection.servers
     {
         let [namespace, name] = &mcp_server.split('.').collect::<Vec<_>>()[..] else {
             bail!("Invalid mcp-server format");
         };
 
-        let command = match proxy_path.clone() {
-            Some(proxy_path) => proxy_path.to_string_lossy().to_string(),
-            None => "mcp-guardian-proxy".to_owned(),
-        };
         let args = vec![
             "--mcp-server".to_owned(),
             format!("{namespace}.{name}"),
@@ -50,12 +74,16 @@ pub fn generate_claude_config_for_server_collection(
             guard_profile,
         ];
 
+        // the proxy runs where the server does
+        let cwd = load_mcp_server(namespace, name)?.and_then(|mcp_server| mcp_server.cwd);
+
         mcp_servers.insert(
             (*name).to_owned(),
             ClaudeMcpServer {
-                command,
+                command: command.clone(),
                 args,
                 env: HashMap::new(),
+                cwd,
             },
         );
     }
@@ -0,0 +1,349 @@
+use std::{
+    fs::{self, File},
+    io::Write,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use anyhow::{anyhow, bail, Result};
+use humantime::format_rfc3339_millis;
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+use crate::{dirs::AppSubDir::ServerLogs, mcp_server::StderrLogConfig};
+
+/// Log of an MCP server process' stderr during a single proxy session.
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct ServerLog {
+    pub mcp_server_name: String,
+    pub session_id: String,
+    /// Combined size in bytes of the log and its rotations
+    #[ts(type = "number")]
+    pub size: u64,
+}
+
+/// Writes an MCP server process' stderr to its log, rotating the log once it grows too big.
+pub struct ServerLogWriter {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    max_file_size: u64,
+    max_files: u32,
+    mirror: bool,
+}
+
+impl ServerLogWriter {
+    pub fn create(
+        mcp_server_name: &str,
+        session_id: &str,
+        config: &StderrLogConfig,
+    ) -> Result<Self> {
+        Self::create_in(&ServerLogs.path()?, mcp_server_name, session_id, config)
+    }
+
+    /// Creates the log as [`create`](Self::create) does, or returns `None` if it can't be
+    /// created, so that the server's stderr is inherited instead of keeping the server from
+    /// starting.
+    pub fn create_or_inherit(
+        mcp_server_name: &str,
+        session_id: &str,
+        config: &StderrLogConfig,
+    ) -> Option<Self> {
+        match Self::create(mcp_server_name, session_id, config) {
+            Ok(stderr_log) => {
+                log::info!(
+                    "Logging MCP server stderr to {}",
+                    stderr_log.path().display()
+                );
+                Some(stderr_log)
+            }
+            Err(e) => {
+                log::warn!(
+                    "Failed to create MCP server stderr log, inheriting stderr instead: {e:#}"
+                );
+                None
+            }
+        }
+    }
+
+    fn create_in(
+        logs_dir: &Path,
+        mcp_server_name: &str,
+        session_id: &str,
+        config: &StderrLogConfig,
+    ) -> Result<Self> {
+        let path = log_path(logs_dir, mcp_server_name, session_id)?;
+        let dir_path = logs_dir.join(mcp_server_name);
+        fs::create_dir_all(&dir_path)?;
+        check_within(logs_dir, &dir_path)?;
+
+        Self::open(path, config)
+    }
+
+    fn open(path: PathBuf, config: &StderrLogConfig) -> Result<Self> {
+        Ok(Self {
+            file: File::create(&path)?,
+            path,
+            size: 0,
+            max_file_size: config.max_file_size.into(),
+            max_files: config.max_files,
+            mirror: config.mirror,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn write_line(&mut self, line: &str) -> Result<()> {
+        if self.mirror {
+            eprintln!("{line}");
+        }
+
+        let entry = format!("[{}] {line}\n", format_rfc3339_millis(SystemTime::now()));
+
+        if self.size > 0 && self.size + entry.len() as u64 > self.max_file_size {
+            self.rotate()?;
+        }
+
+        self.file.write_all(entry.as_bytes())?;
+        self.size += entry.len() as u64;
+
+        Ok(())
+    }
+
+    /// Moves `{session_id}.log` to `{session_id}.log.1`, `{session_id}.log.1` to
+    /// `{session_id}.log.2` and so on, dropping the oldest log, and starts a new log.
+    fn rotate(&mut self) -> Result<()> {
+        if self.max_files > 0 {
+            for n in (1..self.max_files).rev() {
+                let from = rotated_path(&self.path, n);
+                if from.exists() {
+                    fs::rename(from, rotated_path(&self.path, n + 1))?;
+                }
+            }
+
+            fs::rename(&self.path, rotated_path(&self.path, 1))?;
+        }
+
+        self.file = File::create(&self.path)?;
+        self.size = 0;
+
+        Ok(())
+    }
+}
+
+fn rotated_path(path: &Path, n: u32) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(format!(".{n}"));
+
+    PathBuf::from(path)
+}
+
+pub fn list_server_logs() -> Result<Vec<ServerLog>> {
+    log::info!("Listing MCP server logs.");
+    let mut server_logs = Vec::new();
+
+    for entry in fs::read_dir(ServerLogs.path()?)? {
+        let server_dir = entry?.path();
+
+        if !server_dir.is_dir() {
+            log::warn!("Encountered non-directory entry in server logs directory: {server_dir:?}");
+            continue;
+        }
+
+        let mcp_server_name = server_dir
+            .file_name()
+            .ok_or_else(|| anyhow!("Failed to get file name."))?
+            .to_str()
+            .ok_or_else(|| anyhow!("Failed to convert file name to string."))?;
+
+        for entry in fs::read_dir(&server_dir)? {
+            let file_path = entry?.path();
+
+            // rotated logs are counted towards the log they were rotated from
+            if file_path
+                .extension()
+                .is_none_or(|extension| extension != "log")
+            {
+                continue;
+            }
+
+            let session_id = file_path
+                .file_stem()
+                .ok_or_else(|| anyhow!("Failed to get file stem."))?
+                .to_str()
+                .ok_or_else(|| anyhow!("Failed to convert file stem to string."))?;
+
+            let size = log_paths(&file_path)
+                .iter()
+                .map(|path| fs::metadata(path).map(|metadata| metadata.len()))
+                .sum::<std::io::Result<u64>>()?;
+
+            server_logs.push(ServerLog {
+                mcp_server_name: mcp_server_name.to_owned(),
+                session_id: session_id.to_owned(),
+                size,
+            });
+        }
+    }
+
+    server_logs.sort_by(|a, b| {
+        (&a.mcp_server_name, &a.session_id).cmp(&(&b.mcp_server_name, &b.session_id))
+    });
+
+    log::info!("Found {} MCP server logs.", server_logs.len());
+    Ok(server_logs)
+}
+
+/// Reads the log of an MCP server process for a proxy session, including the rotated logs still
+/// kept, oldest first.
+pub fn read_server_log(mcp_server_name: &str, session_id: &str) -> Result<String> {
+    log::info!("Reading MCP server log '{mcp_server_name}/{session_id}'.");
+
+    read_log(&ServerLogs.path()?, mcp_server_name, session_id)
+}
+
+fn read_log(logs_dir: &Path, mcp_server_name: &str, session_id: &str) -> Result<String> {
+    let path = log_path(logs_dir, mcp_server_name, session_id)?;
+
+    if !path.exists() {
+        return Err(anyhow!("MCP server log {} does not exist.", path.display()));
+    }
+    check_within(logs_dir, &path)?;
+
+    let mut server_log = String::new();
+    for path in log_paths(&path).iter().rev() {
+        server_log.push_str(&String::from_utf8_lossy(&fs::read(path)?));
+    }
+
+    Ok(server_log)
+}
+
+/// Path of the log of an MCP server process for a proxy session. The server name and session id
+/// must each be a single path component, so the log can't be outside `logs_dir`.
+fn log_path(logs_dir: &Path, mcp_server_name: &str, session_id: &str) -> Result<PathBuf> {
+    for component in [mcp_server_name, session_id] {
+        if component.is_empty()
+            || component == "."
+            || component == ".."
+            || component.contains(['/', '\\', '\0'])
+        {
+            bail!("Invalid MCP server log name: {component:?}");
+        }
+    }
+
+    Ok(logs_dir
+        .join(mcp_server_name)
+        .join(format!("{session_id}.log")))
+}
+
+/// Checks that `path` is still under `logs_dir` once symlinks are resolved.
+fn check_within(logs_dir: &Path, path: &Path) -> Result<()> {
+    if !path.canonicalize()?.starts_with(logs_dir.canonicalize()?) {
+        bail!(
+            "MCP server log {} is outside the logs directory.",
+            path.display()
+        );
+    }
+
+    Ok(())
+}
+
+/// Paths of a log and its rotations, newest first.
+fn log_paths(path: &Path) -> Vec<PathBuf> {
+    let mut paths = vec![path.to_owned()];
+
+    for n in 1.. {
+        let rotated_path = rotated_path(path, n);
+        if !rotated_path.exists() {
+            break;
+        }
+        paths.push(rotated_path);
+    }
+
+    paths
+}
+
+#[cfg(test)]
+mod test {
+    use uuid::Uuid;
+
+    use super::*;
+
+    #[test]
+    fn test_rotation() {
+        let dir_path = std::env::temp_dir().join(format!("mcp-guardian-{}", Uuid::new_v4()));
+        fs::create_dir_all(&dir_path).unwrap();
+        let path = dir_path.join("session.log");
+
+        let config = StderrLogConfig {
+            mirror: false,
+            max_file_size: 50,
+            max_files: 2,
+        };
+        let mut writer = ServerLogWriter::open(path.clone(), &config).unwrap();
+
+        // every entry is a little over 30 bytes, so each log holds just one
+        for n in 0..5 {
+            writer.write_line(&format!("line {n}")).unwrap();
+        }
+
+        let paths = log_paths(&path);
+        assert_eq!(paths.len(), 3);
+
+        let contents = paths
+            .iter()
+            .map(|path| fs::read_to_string(path).unwrap())
+            .collect::<Vec<_>>();
+        assert!(contents[0].ends_with("line 4\n"));
+        assert!(contents[1].ends_with("line 3\n"));
+        assert!(contents[2].ends_with("line 2\n"));
+
+        fs::remove_dir_all(dir_path).unwrap();
+    }
+
+    #[test]
+    fn test_path_traversal() {
+        let dir_path = std::env::temp_dir().join(format!("mcp-guardian-{}", Uuid::new_v4()));
+        let logs_dir = dir_path.join("logs");
+        fs::create_dir_all(logs_dir.join("server")).unwrap();
+        fs::write(dir_path.join("secret.log"), "secret").unwrap();
+        fs::write(logs_dir.join("server/session.log"), "log").unwrap();
+
+        assert_eq!(read_log(&logs_dir, "server", "session").unwrap(), "log");
+
+        for (mcp_server_name, session_id) in [
+            ("..", "secret"),
+            ("server", "../../secret"),
+            ("server/..", "../secret"),
+            (".", "session"),
+            ("", "session"),
+        ] {
+            assert!(read_log(&logs_dir, mcp_server_name, session_id).is_err());
+            assert!(ServerLogWriter::create_in(
+                &logs_dir,
+                mcp_server_name,
+                session_id,
+                &StderrLogConfig::default()
+            )
+            .is_err());
+        }
+
+        // the server's stderr is inherited instead
+        assert!(
+            ServerLogWriter::create_or_inherit("..", "session", &StderrLogConfig::default())
+                .is_none()
+        );
+
+        // symlinks out of the logs directory aren't followed
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(&dir_path, logs_dir.join("link")).unwrap();
+            assert!(read_log(&logs_dir, "link", "secret").is_err());
+        }
+
+        fs::remove_dir_all(dir_path).unwrap();
+    }
+}
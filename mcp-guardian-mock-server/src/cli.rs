@@ -0,0 +1,13 @@
+use std::path::PathBuf;
+
+use clap::{Parser, ValueHint};
+
+/// mcp-guardian-mock-server
+///
+/// A scriptable MCP server over stdio, for testing the proxy.
+#[derive(Debug, Clone, Parser)]
+pub struct Args {
+    /// The path to the JSON scenario the server plays.
+    #[clap(value_hint = ValueHint::FilePath, value_parser)]
+    pub scenario: PathBuf,
+}
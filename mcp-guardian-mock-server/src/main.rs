@@ -0,0 +1,23 @@
+use std::{fs, io, process};
+
+use anyhow::{Context as _, Result};
+use clap::Parser;
+use mcp_guardian_mock_server::{
+    cli,
+    scenario::Scenario,
+    server::{serve, Exit},
+};
+
+fn main() -> Result<()> {
+    let cli::Args { scenario } = cli::Args::parse();
+
+    let json_str = fs::read_to_string(&scenario)
+        .with_context(|| format!("Failed to read scenario '{}'", scenario.display()))?;
+    let scenario = serde_json::from_str::<Scenario>(&json_str)
+        .with_context(|| format!("Invalid scenario '{}'", scenario.display()))?;
+
+    match serve(&scenario, io::stdin().lock(), io::stdout().lock())? {
+        Exit::Closed => Ok(()),
+        Exit::Crash(exit_code) => process::exit(exit_code),
+    }
+}
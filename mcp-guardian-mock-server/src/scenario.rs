@@ -0,0 +1,69 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+/// What a mock MCP server does: the tools it lists, what calling them does, and the messages it
+/// sends of its own accord.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Scenario {
+    /// Server info answered to `initialize`
+    #[serde(default = "Scenario::default_server_info")]
+    pub server_info: Value,
+    /// Tools answered to `tools/list`
+    #[serde(default)]
+    pub tools: Vec<Value>,
+    /// What calling each tool does. Calls to tools without a script are answered with an error.
+    #[serde(default)]
+    pub tool_calls: Vec<ToolCallScript>,
+    /// Messages sent once the client sends `notifications/initialized`
+    #[serde(default)]
+    pub on_initialized: Vec<Cue>,
+}
+
+impl Scenario {
+    fn default_server_info() -> Value {
+        json!({ "name": "mcp-guardian-mock-server", "version": env!("CARGO_PKG_VERSION") })
+    }
+
+    pub fn tool_call(&self, tool: &str) -> Option<&ToolCallScript> {
+        self.tool_calls.iter().find(|script| script.tool == tool)
+    }
+}
+
+/// What calling a tool does.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToolCallScript {
+    pub tool: String,
+    /// Messages sent before the call is answered
+    #[serde(default)]
+    pub cues: Vec<Cue>,
+    pub outcome: ToolCallOutcome,
+}
+
+/// How a tool call is answered.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ToolCallOutcome {
+    /// Answers with `result`
+    Result { result: Value },
+    /// Answers with a json-rpc error
+    Error { code: i32, message: String },
+    /// Exits with `exit_code` without answering
+    Crash { exit_code: i32 },
+}
+
+/// A message the server sends of its own accord.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Cue {
+    Notification {
+        method: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        params: Option<Value>,
+    },
+    /// A server-initiated request. Responses to it are ignored.
+    Request {
+        method: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        params: Option<Value>,
+    },
+}
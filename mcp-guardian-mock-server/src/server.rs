@@ -0,0 +1,192 @@
+use std::io::{BufRead, Write};
+
+use anyhow::Result;
+use serde_json::{json, Value};
+
+use crate::scenario::{Cue, Scenario, ToolCallOutcome};
+
+const PROTOCOL_VERSION: &str = "2025-03-26";
+
+/// Why the server stopped.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Exit {
+    /// The client closed the connection
+    Closed,
+    /// The scenario crashed the server with an exit code
+    Crash(i32),
+}
+
+/// Plays `scenario`, reading json-rpc messages from `input` and writing to `output`, one per line.
+///
+/// Batches are not supported. Lines that aren't json are skipped.
+pub fn serve(scenario: &Scenario, input: impl BufRead, mut output: impl Write) -> Result<Exit> {
+    let mut server = Server {
+        scenario,
+        output: &mut output,
+        next_request_id: 1,
+    };
+
+    for line in input.lines() {
+        let Ok(message) = serde_json::from_str::<Value>(&line?) else {
+            continue;
+        };
+
+        if let Some(exit) = server.handle(&message)? {
+            return Ok(exit);
+        }
+    }
+
+    Ok(Exit::Closed)
+}
+
+struct Server<'a, W> {
+    scenario: &'a Scenario,
+    output: &'a mut W,
+    next_request_id: u64,
+}
+
+impl<W: Write> Server<'_, W> {
+    fn handle(&mut self, message: &Value) -> Result<Option<Exit>> {
+        let id = message.get("id");
+        let method = message["method"].as_str();
+        let params = &message["params"];
+
+        let result = match (id, method) {
+            // Responses to the server's own requests
+            (Some(_), None) => return Ok(None),
+            (None, Some("notifications/initialized")) => {
+                for cue in &self.scenario.on_initialized {
+                    self.cue(cue)?;
+                }
+                return Ok(None);
+            }
+            (None, _) => return Ok(None),
+            (Some(_), Some("initialize")) => Ok(json!({
+                "protocolVersion": params["protocolVersion"].as_str().unwrap_or(PROTOCOL_VERSION),
+                "capabilities": { "tools": {} },
+                "serverInfo": self.scenario.server_info,
+            })),
+            (Some(_), Some("ping")) => Ok(json!({})),
+            (Some(_), Some("tools/list")) => Ok(json!({ "tools": self.scenario.tools })),
+            (Some(_), Some("tools/call")) => {
+                let tool = params["name"].as_str().unwrap_or_default();
+                match self.scenario.tool_call(tool) {
+                    Some(script) => {
+                        for cue in &script.cues {
+                            self.cue(cue)?;
+                        }
+                        match &script.outcome {
+                            ToolCallOutcome::Result { result } => Ok(result.clone()),
+                            ToolCallOutcome::Error { code, message } => {
+                                Err((*code, message.clone()))
+                            }
+                            ToolCallOutcome::Crash { exit_code } => {
+                                return Ok(Some(Exit::Crash(*exit_code)))
+                            }
+                        }
+                    }
+                    None => Err((-32602, format!("Unknown tool: {tool}"))),
+                }
+            }
+            (Some(_), method) => Err((
+                -32601,
+                format!("Method not found: {}", method.unwrap_or_default()),
+            )),
+        };
+
+        let response = match result {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            Err((code, message)) => json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "error": { "code": code, "message": message }
+            }),
+        };
+        self.send(&response)?;
+
+        Ok(None)
+    }
+
+    fn cue(&mut self, cue: &Cue) -> Result<()> {
+        let (mut message, params) = match cue {
+            Cue::Notification { method, params } => {
+                (json!({ "jsonrpc": "2.0", "method": method }), params)
+            }
+            Cue::Request { method, params } => {
+                let id = format!("mock-{}", self.next_request_id);
+                self.next_request_id += 1;
+                (
+                    json!({ "jsonrpc": "2.0", "id": id, "method": method }),
+                    params,
+                )
+            }
+        };
+        if let Some(params) = params {
+            message["params"] = params.clone();
+        }
+
+        self.send(&message)
+    }
+
+    fn send(&mut self, message: &Value) -> Result<()> {
+        writeln!(self.output, "{message}")?;
+        self.output.flush()?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_serve() {
+        let scenario = serde_json::from_value::<Scenario>(json!({
+            "tools": [{ "name": "echo", "inputSchema": { "type": "object" } }],
+            "tool_calls": [
+                {
+                    "tool": "echo",
+                    "cues": [{ "type": "notification", "method": "notifications/progress" }],
+                    "outcome": { "type": "result", "result": { "content": [] } }
+                },
+                { "tool": "crash", "outcome": { "type": "crash", "exit_code": 3 } }
+            ],
+            "on_initialized": [{ "type": "request", "method": "roots/list" }]
+        }))
+        .unwrap();
+
+        let input = [
+            json!({"jsonrpc": "2.0", "method": "notifications/initialized"}),
+            json!({"jsonrpc": "2.0", "id": 1, "method": "tools/list"}),
+            json!({"jsonrpc": "2.0", "id": 2, "method": "tools/call", "params": {"name": "echo"}}),
+            json!({"jsonrpc": "2.0", "id": 3, "method": "tools/call", "params": {"name": "nope"}}),
+            json!({"jsonrpc": "2.0", "id": 4, "method": "tools/call", "params": {"name": "crash"}}),
+            json!({"jsonrpc": "2.0", "id": 5, "method": "ping"}),
+        ]
+        .map(|message| format!("{message}\n"))
+        .concat();
+
+        let mut output = Vec::new();
+        let exit = serve(&scenario, input.as_bytes(), &mut output).unwrap();
+
+        let output = String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str::<Value>(line).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(exit, Exit::Crash(3));
+        assert_eq!(
+            output,
+            [
+                json!({"jsonrpc": "2.0", "id": "mock-1", "method": "roots/list"}),
+                json!({"jsonrpc": "2.0", "id": 1, "result": {"tools": [
+                    { "name": "echo", "inputSchema": { "type": "object" } }
+                ]}}),
+                json!({"jsonrpc": "2.0", "method": "notifications/progress"}),
+                json!({"jsonrpc": "2.0", "id": 2, "result": {"content": []}}),
+                json!({"jsonrpc": "2.0", "id": 3, "error": {"code": -32602, "message": "Unknown tool: nope"}}),
+            ]
+        );
+    }
+}
@@ -0,0 +1,300 @@
+//! Integration tests of the proxy against the mock MCP server.
+//!
+//! Each test runs the proxy in-process, which spawns and supervises the mock server as it would any
+//! MCP server, and plays the host over an in-memory transport.
+
+use std::{env, fs, path::PathBuf, process::ExitStatus, time::Duration};
+
+use mcp_guardian_core::{
+    guard_profile::{load_guard_profile, profiles::CORE_NAMESPACE},
+    mcp_server::{McpServer, RestartPolicy},
+    proxy::{
+        builder::{ProxyBuilder, ProxyHandle, ServerTransport},
+        transport::Transport,
+    },
+};
+use serde_json::{json, Value};
+use tokio::{sync::mpsc, time::timeout};
+
+/// How long the host waits for a message from the proxy
+const RECV_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The proxy, running in this process, as seen by the host, in front of the mock server.
+struct Proxy {
+    host_tx: Option<mpsc::Sender<Value>>,
+    host_rx: mpsc::Receiver<Value>,
+    handle: ProxyHandle,
+    _dir: TempDir,
+}
+
+impl Proxy {
+    fn spawn(scenario: Value, guard_profile: &str) -> Self {
+        Self::spawn_with_restart(scenario, guard_profile, None)
+    }
+
+    /// Spawns the proxy, which restarts the mock server according to `restart`.
+    fn spawn_with_restart(
+        scenario: Value,
+        guard_profile: &str,
+        restart: Option<RestartPolicy>,
+    ) -> Self {
+        let dir = TempDir::new();
+        let scenario_path = dir.0.join("scenario.json");
+        fs::write(&scenario_path, scenario.to_string()).unwrap();
+
+        let mcp_server = McpServer {
+            cmd: env!("CARGO_BIN_EXE_mcp-guardian-mock-server").to_owned(),
+            args: vec![scenario_path.to_string_lossy().into_owned()],
+            restart,
+            ..Default::default()
+        };
+
+        let message_interceptor = load_guard_profile(CORE_NAMESPACE, guard_profile)
+            .unwrap()
+            .expect("guard profile not found")
+            .primary_message_interceptor
+            .try_into_message_interceptor("mock".to_owned())
+            .unwrap();
+
+        let (host_tx, proxy_rx) = mpsc::channel(100);
+        let (proxy_tx, host_rx) = mpsc::channel(100);
+        let handle = ProxyBuilder::new("mock", message_interceptor)
+            .host(Transport::channel(proxy_tx, proxy_rx))
+            .server(ServerTransport::Process(mcp_server))
+            .spawn()
+            .unwrap();
+
+        Self {
+            host_tx: Some(host_tx),
+            host_rx,
+            handle,
+            _dir: dir,
+        }
+    }
+
+    async fn send(&self, message: Value) {
+        self.host_tx.as_ref().unwrap().send(message).await.unwrap();
+    }
+
+    /// Receives the next message from the proxy, `None` once the proxy closed the host's transport.
+    async fn try_recv(&mut self) -> Option<Value> {
+        timeout(RECV_TIMEOUT, self.host_rx.recv())
+            .await
+            .expect("no message from the proxy")
+    }
+
+    async fn recv(&mut self) -> Value {
+        self.try_recv().await.expect("the proxy closed the host")
+    }
+
+    /// Initializes the session, as hosts do before anything else.
+    async fn initialize(&mut self) -> Value {
+        self.send(json!({
+            "jsonrpc": "2.0",
+            "id": 0,
+            "method": "initialize",
+            "params": {
+                "protocolVersion": "2025-03-26",
+                "capabilities": {},
+                "clientInfo": { "name": "test", "version": "0" }
+            }
+        }))
+        .await;
+        let response = self.recv().await;
+        self.send(json!({"jsonrpc": "2.0", "method": "notifications/initialized"}))
+            .await;
+
+        response
+    }
+
+    /// Closes the host's transport, if it's still open, and waits for the session to end and the
+    /// mock server to exit.
+    async fn close(mut self) -> ExitStatus {
+        drop(self.host_tx.take());
+        self.handle
+            .wait()
+            .await
+            .unwrap()
+            .expect("the proxy spawned no process")
+    }
+}
+
+/// Directory of a test's files, removed once the test is done.
+struct TempDir(PathBuf);
+
+impl TempDir {
+    fn new() -> Self {
+        let path = env::temp_dir().join(format!("mcp-guardian-mock-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&path).unwrap();
+        Self(path)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+fn tool_call(id: u32, tool: &str) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "method": "tools/call",
+        "params": { "name": tool, "arguments": {} }
+    })
+}
+
+fn scenario() -> Value {
+    json!({
+        "server_info": { "name": "mock", "version": "1.0.0" },
+        "tools": [
+            { "name": "get_current_time", "inputSchema": { "type": "object" } },
+            { "name": "echo", "inputSchema": { "type": "object" } }
+        ],
+        "tool_calls": [
+            {
+                "tool": "get_current_time",
+                "outcome": {
+                    "type": "result",
+                    "result": { "content": [{ "type": "text", "text": "12:00" }] }
+                }
+            },
+            {
+                "tool": "echo",
+                "cues": [
+                    {
+                        "type": "notification",
+                        "method": "notifications/progress",
+                        "params": { "progressToken": "echo", "progress": 1 }
+                    },
+                    { "type": "request", "method": "sampling/createMessage" }
+                ],
+                "outcome": { "type": "result", "result": { "content": [] } }
+            },
+            {
+                "tool": "fail",
+                "outcome": { "type": "error", "code": -32000, "message": "failed" }
+            },
+            { "tool": "crash", "outcome": { "type": "crash", "exit_code": 3 } }
+        ],
+        "on_initialized": [{ "type": "request", "method": "roots/list" }]
+    })
+}
+
+#[tokio::test]
+async fn test_initialize() {
+    let mut proxy = Proxy::spawn(scenario(), "log-only");
+
+    let response = proxy.initialize().await;
+    assert_eq!(response["id"], 0);
+    assert_eq!(response["result"]["serverInfo"]["name"], "mock");
+    assert_eq!(proxy.recv().await["method"], "roots/list");
+
+    proxy
+        .send(json!({"jsonrpc": "2.0", "id": 1, "method": "tools/list"}))
+        .await;
+    let response = proxy.recv().await;
+    assert_eq!(response["result"]["tools"][1]["name"], "echo");
+
+    assert!(proxy.close().await.success());
+}
+
+#[tokio::test]
+async fn test_tool_calls() {
+    let mut proxy = Proxy::spawn(scenario(), "log-only");
+    proxy.initialize().await;
+    proxy.recv().await;
+
+    proxy.send(tool_call(1, "get_current_time")).await;
+    assert_eq!(
+        proxy.recv().await,
+        json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "result": { "content": [{ "type": "text", "text": "12:00" }] }
+        })
+    );
+
+    proxy.send(tool_call(2, "fail")).await;
+    assert_eq!(
+        proxy.recv().await,
+        json!({"jsonrpc": "2.0", "id": 2, "error": {"code": -32000, "message": "failed"}})
+    );
+}
+
+#[tokio::test]
+async fn test_cues() {
+    let mut proxy = Proxy::spawn(scenario(), "log-only");
+    proxy.initialize().await;
+
+    let request = proxy.recv().await;
+    assert_eq!(request["method"], "roots/list");
+    proxy
+        .send(json!({"jsonrpc": "2.0", "id": request["id"], "result": {"roots": []}}))
+        .await;
+
+    proxy.send(tool_call(1, "echo")).await;
+    assert_eq!(proxy.recv().await["method"], "notifications/progress");
+    assert_eq!(proxy.recv().await["method"], "sampling/createMessage");
+    assert_eq!(proxy.recv().await["id"], 1);
+}
+
+#[tokio::test]
+async fn test_blocked_tool_call() {
+    let mut proxy = Proxy::spawn(scenario(), "block-get-current-time");
+    proxy.initialize().await;
+    proxy.recv().await;
+
+    proxy.send(tool_call(1, "get_current_time")).await;
+    proxy.send(tool_call(2, "fail")).await;
+
+    // The blocked call is dropped, so the next call is the first to be answered
+    assert_eq!(proxy.recv().await["id"], 2);
+}
+
+#[tokio::test]
+async fn test_crash() {
+    let mut proxy = Proxy::spawn(scenario(), "log-only");
+    proxy.initialize().await;
+    proxy.recv().await;
+
+    // The call that crashed the mock server is answered with an error, and the session ends with it
+    proxy.send(tool_call(1, "crash")).await;
+    assert_eq!(
+        proxy.recv().await,
+        json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "error": { "code": -32603, "message": "MCP server process exited before responding." }
+        })
+    );
+    assert_eq!(proxy.try_recv().await, None);
+
+    assert_eq!(proxy.close().await.code(), Some(3));
+}
+
+#[tokio::test]
+async fn test_restart() {
+    let restart = RestartPolicy {
+        max_retries: 1,
+        initial_backoff_ms: 10,
+        max_backoff_ms: 10,
+    };
+    let mut proxy = Proxy::spawn_with_restart(scenario(), "log-only", Some(restart));
+    proxy.initialize().await;
+    assert_eq!(proxy.recv().await["method"], "roots/list");
+
+    proxy.send(tool_call(1, "crash")).await;
+    assert_eq!(proxy.recv().await["error"]["code"], -32603);
+
+    // The restarted mock server is initialized again without the host's help, and answers the
+    // calls that follow
+    proxy.send(tool_call(2, "get_current_time")).await;
+    assert_eq!(proxy.recv().await["method"], "roots/list");
+    let response = proxy.recv().await;
+    assert_eq!(response["id"], 2);
+    assert_eq!(response["result"]["content"][0]["text"], "12:00");
+
+    assert!(proxy.close().await.success());
+}
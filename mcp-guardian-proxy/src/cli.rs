@@ -1,3 +1,5 @@
+use std::{net::SocketAddr, path::PathBuf};
+
 use clap::Parser;
 
 /// mcp-guardian-proxy
@@ -20,6 +22,22 @@ pub struct Args {
     #[clap(short, long)]
     pub mcp_server: Option<String>,
 
+    /// [Optional] Server collection to proxy as a single MCP server ("{namespace}.{name}"), each server guarded by its own guard profile. This is mutually exclusive with an MCP server configuration or command.
+    #[clap(short, long)]
+    pub server_collection: Option<String>,
+
+    /// [Optional] Serve the MCP server over Streamable HTTP at this address (e.g. "127.0.0.1:8080") instead of stdio.
+    #[clap(short, long)]
+    pub listen: Option<SocketAddr>,
+
+    /// [Optional] Origin allowed to make requests when serving over Streamable HTTP (e.g. "https://app.example.com"), besides loopback origins. Can be given more than once.
+    #[clap(long = "allow-origin")]
+    pub allowed_origins: Vec<String>,
+
+    /// [Optional] Record every message, along with what the guard profile did with it, to this JSONL transcript. The transcript is appended to if it exists.
+    #[clap(short, long)]
+    pub record: Option<PathBuf>,
+
     /// MCP server command
     #[clap(value_parser, last=true, num_args=0..=100)]
     pub cmd: Vec<String>,
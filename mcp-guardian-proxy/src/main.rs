@@ -1,9 +1,19 @@
-use std::collections::HashMap;
+use std::{path::Path, process::ExitStatus, sync::Arc};
 
 use anyhow::{bail, Result};
 use clap::Parser;
-use mcp_guardian_core::{mcp_server::McpServer, proxy::proxy_mcp_server};
+use mcp_guardian_core::{
+    guard_profile::GuardProfile,
+    mcp_server::McpServer,
+    message_interceptor::record::SessionRecorder,
+    proxy::{
+        aggregate::{proxy_mcp_servers, AggregatedMcpServer},
+        http_server::serve_mcp_server,
+        proxy_mcp_server, proxy_remote_mcp_server,
+    },
+};
 use mcp_guardian_proxy::cli;
+use tokio::{sync::oneshot, task};
 
 #[tokio::main]
 async fn main() -> Result<()> {
@@ -12,30 +22,46 @@ async fn main() -> Result<()> {
         host_session_id,
         guard_profile,
         mcp_server,
+        server_collection,
+        listen,
+        allowed_origins,
+        record,
         cmd,
     } = cli::Args::parse();
 
+    if let Some(server_collection) = server_collection {
+        if mcp_server.is_some() || !cmd.is_empty() || listen.is_some() {
+            bail!("A server collection cannot be combined with an MCP server configuration, a command or --listen.");
+        }
+
+        proxy_server_collection(name, host_session_id, &server_collection, record.as_deref())
+            .await?;
+
+        exit_now();
+    }
+
     let name = name.unwrap_or("unnamed".to_owned());
 
     mcp_guardian_core::init(&format!("mcp-guardian-proxy.{name}"))?;
 
     log::info!("Starting mcp-guardian-proxy");
 
-    let (command, args, env) = match (mcp_server, &cmd[..]) {
+    let mcp_server = match (mcp_server, &cmd[..]) {
         // Using mcp-server configuration
         (Some(mcp_server), []) => {
             let [namespace, name] = &mcp_server.split('.').collect::<Vec<_>>()[..] else {
                 log::error!("Invalid MCP server format. Expected \"{{namespace}}.{{name}}\".");
                 bail!("Invalid MCP server format. Expected \"{{namespace}}.{{name}}\".");
             };
-            let McpServer { cmd, args, env } =
-                mcp_guardian_core::mcp_server::load_mcp_server(namespace, name)?
-                    .ok_or_else(|| anyhow::anyhow!("MCP server not found."))?;
-
-            (cmd, args, env)
+            mcp_guardian_core::mcp_server::load_mcp_server(namespace, name)?
+                .ok_or_else(|| anyhow::anyhow!("MCP server not found."))?
         }
         // Using provided command
-        (None, [command, args @ ..]) => (command.clone(), args.to_vec(), HashMap::new()),
+        (None, [command, args @ ..]) => McpServer {
+            cmd: command.clone(),
+            args: args.to_vec(),
+            ..Default::default()
+        },
         // Both provided
         (Some(_), [..]) => {
             log::error!("Cannot specify both an MCP server configuration and a command to run. Use one or the other.");
@@ -49,35 +75,207 @@ async fn main() -> Result<()> {
     };
 
     log::info!("Name: {name}");
-    log::info!("Command: {command}");
-    log::info!("Args: {}", args.join(" "));
-
-    let args = args.iter().map(String::as_str).collect::<Vec<_>>();
-
-    let guard_profile = {
-        let [namespace, profile_name] = &guard_profile.split('.').collect::<Vec<_>>()[..] else {
-            log::error!(
-                "Invalid guard profile format. Expected \"{{namespace}}.{{profile_name}}\"."
-            );
-            bail!("Invalid guard profile format. Expected \"{{namespace}}.{{profile_name}}\".");
-        };
+    match &mcp_server.remote {
+        Some(remote) => log::info!("Url: {}", remote.url),
+        None => {
+            log::info!("Command: {}", mcp_server.cmd);
+            log::info!("Args: {}", mcp_server.args.join(" "));
+        }
+    }
 
-        mcp_guardian_core::guard_profile::load_guard_profile(namespace, profile_name)?
-            .ok_or_else(|| anyhow::anyhow!("Guard profile not found."))?
-    };
+    let guard_profile = load_guard_profile(&guard_profile)?;
 
     let message_interceptor = guard_profile
         .primary_message_interceptor
         .try_into_message_interceptor(name.clone())?;
 
-    let _ = env; // TODO: add env to the process
+    let recorder = create_recorder(record.as_deref())?;
+
+    let res = match (&mcp_server.remote, listen) {
+        (Some(remote), None) => proxy_remote_mcp_server(
+            name,
+            host_session_id,
+            remote,
+            message_interceptor,
+            recorder,
+            shutdown_on_signal(),
+        )
+        .await
+        .map(|()| None),
+        (None, None) => proxy_mcp_server(
+            name,
+            host_session_id,
+            &mcp_server,
+            message_interceptor,
+            recorder,
+            shutdown_on_signal(),
+        )
+        .await
+        .map(Some),
+        (None, Some(addr)) => serve_mcp_server(
+            name,
+            &mcp_server,
+            message_interceptor,
+            recorder,
+            addr,
+            allowed_origins,
+            shutdown_on_signal(),
+        )
+        .await
+        .map(Some),
+        (Some(_), Some(_)) => {
+            log::error!("Remote MCP servers cannot be served over HTTP.");
+            bail!("Remote MCP servers cannot be served over HTTP.")
+        }
+    };
+
+    match res {
+        Ok(Some(status)) if !status.success() => {
+            log::error!("MCP server exited with {status}");
+            std::process::exit(exit_code(status));
+        }
+        Ok(_) => {}
+        Err(e) => {
+            log::error!("Error starting MCP server: {e:#}");
+            eprint!("Error starting MCP server: {e:#}");
+            std::process::exit(1);
+        }
+    }
+
+    exit_now();
+}
+
+fn load_guard_profile(guard_profile: &str) -> Result<GuardProfile> {
+    let [namespace, profile_name] = &guard_profile.split('.').collect::<Vec<_>>()[..] else {
+        log::error!("Invalid guard profile format. Expected \"{{namespace}}.{{profile_name}}\".");
+        bail!("Invalid guard profile format. Expected \"{{namespace}}.{{profile_name}}\".");
+    };
+
+    mcp_guardian_core::guard_profile::load_guard_profile(namespace, profile_name)?
+        .ok_or_else(|| anyhow::anyhow!("Guard profile not found."))
+}
+
+fn create_recorder(record: Option<&Path>) -> Result<Option<Arc<SessionRecorder>>> {
+    record
+        .map(|path| SessionRecorder::create(path).map(Arc::new))
+        .transpose()
+}
+
+/// Proxies every server in a server collection as a single MCP server.
+async fn proxy_server_collection(
+    name: Option<String>,
+    host_session_id: Option<String>,
+    server_collection: &str,
+    record: Option<&Path>,
+) -> Result<()> {
+    let [namespace, collection_name] = &server_collection.split('.').collect::<Vec<_>>()[..] else {
+        bail!("Invalid server collection format. Expected \"{{namespace}}.{{name}}\".");
+    };
+
+    let name = name.unwrap_or((*collection_name).to_owned());
+
+    mcp_guardian_core::init(&format!("mcp-guardian-proxy.{name}"))?;
+
+    log::info!("Starting mcp-guardian-proxy for server collection {server_collection}");
+
+    let server_collection =
+        mcp_guardian_core::server_collection::load_server_collection(namespace, collection_name)?
+            .ok_or_else(|| anyhow::anyhow!("Server collection not found."))?;
+
+    let mut mcp_servers = Vec::new();
+
+    for server in &server_collection.servers {
+        let [namespace, mcp_server_name] = &server.mcp_server.split('.').collect::<Vec<_>>()[..]
+        else {
+            bail!("Invalid MCP server format. Expected \"{{namespace}}.{{name}}\".");
+        };
+
+        let mcp_server =
+            mcp_guardian_core::mcp_server::load_mcp_server(namespace, mcp_server_name)?
+                .ok_or_else(|| anyhow::anyhow!("MCP server {} not found.", server.mcp_server))?;
+
+        let message_interceptor = load_guard_profile(&server.guard_profile)?
+            .primary_message_interceptor
+            .try_into_message_interceptor((*mcp_server_name).to_owned())?;
+
+        mcp_servers.push(AggregatedMcpServer {
+            name: (*mcp_server_name).to_owned(),
+            prefix: server.prefix(),
+            mcp_server,
+            message_interceptor,
+        });
+    }
+
+    let recorder = create_recorder(record)?;
 
-    if let Err(e) =
-        proxy_mcp_server(name, host_session_id, &command, &args, message_interceptor).await
+    if let Err(e) = proxy_mcp_servers(
+        name,
+        host_session_id,
+        mcp_servers,
+        recorder,
+        shutdown_on_signal(),
+    )
+    .await
     {
-        log::error!("Error starting MCP server: {e}");
-        eprint!("Error starting MCP server: {e}");
+        log::error!("Error starting MCP servers: {e:#}");
+        eprint!("Error starting MCP servers: {e:#}");
+        std::process::exit(1);
+    }
+
+    Ok(())
+}
+
+/// Returns a receiver that receives once the proxy is asked to exit: on SIGINT or SIGTERM, or on
+/// Ctrl-C where there are no Unix signals.
+fn shutdown_on_signal() -> oneshot::Receiver<()> {
+    let (shutdown_tx, shutdown_rx) = oneshot::channel();
+
+    task::spawn(async move {
+        match shutdown_signal().await {
+            Ok(()) => {
+                log::info!("Received shutdown signal. Shutting down.");
+                let _ = shutdown_tx.send(());
+            }
+            Err(e) => log::error!("Failed to listen for shutdown signals: {e}"),
+        }
+    });
+
+    shutdown_rx
+}
+
+#[cfg(unix)]
+async fn shutdown_signal() -> std::io::Result<()> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut interrupt = signal(SignalKind::interrupt())?;
+    let mut terminate = signal(SignalKind::terminate())?;
+
+    tokio::select! {
+        _ = interrupt.recv() => {}
+        _ = terminate.recv() => {}
     }
 
     Ok(())
 }
+
+#[cfg(not(unix))]
+async fn shutdown_signal() -> std::io::Result<()> {
+    tokio::signal::ctrl_c().await
+}
+
+/// Exits without waiting for the runtime to shut down, which would wait for the blocking read of
+/// the host's stdin until the host closes it, if it hasn't yet.
+fn exit_now() -> ! {
+    std::process::exit(0)
+}
+
+/// Exit code mirroring an MCP server's exit status, using the shell convention of 128 + the signal
+/// number for servers killed by a signal.
+fn exit_code(status: ExitStatus) -> i32 {
+    #[cfg(unix)]
+    if let Some(signal) = std::os::unix::process::ExitStatusExt::signal(&status) {
+        return 128 + signal;
+    }
+
+    status.code().unwrap_or(1)
+}
@@ -2,6 +2,7 @@ pub mod guard_profiles;
 pub mod mcp_servers;
 pub mod pending_messages;
 pub mod server_collections;
+pub mod server_logs;
 
 use guard_profiles::{
     delete_guard_profile, get_guard_profile, list_guard_profiles, set_guard_profile,
@@ -9,12 +10,15 @@ use guard_profiles::{
 use mcp_servers::{
     delete_mcp_server, get_mcp_server, import_claude_config, list_mcp_servers, set_mcp_server,
 };
-use pending_messages::{approve_message, deny_message, get_pending_messages};
+use pending_messages::{
+    approve_message, deny_message, get_message_approval_history, get_pending_messages,
+};
 use server_collections::{
     apply_claude_config_for_server_collection, delete_server_collection,
     generate_claude_config_for_server_collection, get_server_collection, list_server_collections,
     set_server_collection,
 };
+use server_logs::{get_server_log, list_server_logs};
 
 pub type Result<T> = std::result::Result<T, String>;
 
@@ -40,7 +44,10 @@ pub fn run() {
             apply_claude_config_for_server_collection,
             get_pending_messages,
             approve_message,
-            deny_message
+            deny_message,
+            get_message_approval_history,
+            list_server_logs,
+            get_server_log
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
@@ -1,3 +1,4 @@
+use mcp_guardian_core::message_approval::ApprovalHistoryEntry;
 use serde_json::Value;
 
 use crate::Result;
@@ -22,3 +23,10 @@ pub async fn deny_message(id: String) -> Result<()> {
         .await
         .map_err(|e| format!("deny_message(id={id}) failed: {}", e))
 }
+
+#[tauri::command]
+pub async fn get_message_approval_history(id: String) -> Result<Vec<ApprovalHistoryEntry>> {
+    mcp_guardian_core::message_approval::get_approval_history(id.clone())
+        .await
+        .map_err(|e| format!("get_approval_history(id={id}) failed: {}", e))
+}
@@ -0,0 +1,18 @@
+use mcp_guardian_core::server_log::ServerLog;
+
+use crate::Result;
+
+#[tauri::command]
+pub async fn list_server_logs() -> Result<Vec<ServerLog>> {
+    mcp_guardian_core::server_log::list_server_logs()
+        .map_err(|e| format!("list_server_logs() failed: {e}"))
+}
+
+#[tauri::command]
+pub async fn get_server_log(mcp_server_name: &str, session_id: &str) -> Result<String> {
+    mcp_guardian_core::server_log::read_server_log(mcp_server_name, session_id).map_err(|e| {
+        format!(
+            "get_server_log(mcp_server_name={mcp_server_name}, session_id={session_id}) failed: {e}"
+        )
+    })
+}